@@ -0,0 +1,267 @@
+//! compare the storage of rows before and after they were kept in trees:
+//! a `Vec` of rows holding a `String` each, against a `RowBuffer` of rows holding chunks.
+//! run it with `cargo bench`, it needs a few GB of memory
+#![warn(clippy::all, clippy::pedantic)]
+
+use std::{
+    env, fs,
+    hint::black_box,
+    io::{self, Write},
+    process,
+    time::{Duration, Instant},
+};
+
+use text_editor::{buffer::RowBuffer, Document, Position, Row};
+use unicode_segmentation::UnicodeSegmentation;
+
+const MB: usize = 1024 * 1024;
+
+/// the operations of a document on its rows
+trait Storage {
+    const NAME: &'static str;
+
+    fn from_text(text: &str) -> Self;
+    fn len(&self) -> usize;
+    fn row_len(&self, y: usize) -> usize;
+    fn insert_row(&mut self, y: usize, text: &str);
+    fn remove_row(&mut self, y: usize);
+    fn insert(&mut self, y: usize, x: usize, text: &str);
+    fn drain(&mut self, y: usize, start: usize, end: usize) -> String;
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()>;
+}
+
+/// a row as it was kept before, the whole row in one string
+struct StringRow {
+    content: String,
+    len: usize,
+}
+
+impl StringRow {
+    fn new(content: &str) -> Self {
+        let mut row = Self {
+            content: content.to_string(),
+            len: 0,
+        };
+        row.update_len();
+        row
+    }
+
+    fn update_len(&mut self) {
+        self.len = if self.content.is_ascii() {
+            self.content.len()
+        } else {
+            self.content.graphemes(true).count()
+        };
+    }
+
+    fn byte_index(&self, at: usize) -> usize {
+        if self.content.is_ascii() {
+            return at.min(self.content.len());
+        }
+        self.content
+            .grapheme_indices(true)
+            .nth(at)
+            .map_or(self.content.len(), |(index, _)| index)
+    }
+}
+
+impl Storage for Vec<StringRow> {
+    const NAME: &'static str = "Vec<String>";
+
+    fn from_text(text: &str) -> Self {
+        text.lines().map(StringRow::new).collect()
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn row_len(&self, y: usize) -> usize {
+        self[y].len
+    }
+
+    fn insert_row(&mut self, y: usize, text: &str) {
+        self.insert(y, StringRow::new(text));
+    }
+
+    fn remove_row(&mut self, y: usize) {
+        self.remove(y);
+    }
+
+    fn insert(&mut self, y: usize, x: usize, text: &str) {
+        let row = &mut self[y];
+        let index = row.byte_index(x);
+        row.content.insert_str(index, text);
+        row.update_len();
+    }
+
+    fn drain(&mut self, y: usize, start: usize, end: usize) -> String {
+        let row = &mut self[y];
+        let range = row.byte_index(start)..row.byte_index(end);
+        let removed = row.content.drain(range).collect();
+        row.update_len();
+        removed
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        for row in self {
+            writer.write_all(row.content.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for RowBuffer {
+    const NAME: &'static str = "RowBuffer";
+
+    fn from_text(text: &str) -> Self {
+        text.lines().map(Row::from).collect()
+    }
+
+    fn len(&self) -> usize {
+        RowBuffer::len(self)
+    }
+
+    fn row_len(&self, y: usize) -> usize {
+        self[y].len()
+    }
+
+    fn insert_row(&mut self, y: usize, text: &str) {
+        self.insert(y, Row::from(text));
+    }
+
+    fn remove_row(&mut self, y: usize) {
+        self.remove(y);
+    }
+
+    fn insert(&mut self, y: usize, x: usize, text: &str) {
+        self[y].insert_str(x, text);
+    }
+
+    fn drain(&mut self, y: usize, start: usize, end: usize) -> String {
+        self[y].drain(start, end)
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        for row in self.iter() {
+            writeln!(writer, "{row}")?;
+        }
+        Ok(())
+    }
+}
+
+/// run `f` and print how long it took
+fn time<R>(storage: &str, name: &str, f: impl FnOnce() -> R) -> R {
+    let now = Instant::now();
+    let result = f();
+    print_time(storage, name, now.elapsed());
+    result
+}
+
+fn print_time(storage: &str, name: &str, elapsed: Duration) {
+    println!("{storage:>12}  {name:<56} {elapsed:>12.3?}");
+}
+
+/// the edits of a document with many short rows
+fn many_rows<S: Storage>(text: &str, edits: usize) {
+    let mut rows = time(S::NAME, "open", || S::from_text(text));
+    let middle = rows.len() / 2;
+    time(
+        S::NAME,
+        &format!("{edits} row insertions and removals"),
+        || {
+            for i in 0..edits {
+                rows.insert_row(middle + i, "fn main() {}");
+                rows.remove_row(i * 7);
+            }
+        },
+    );
+    time(
+        S::NAME,
+        &format!("{edits} typed and deleted characters"),
+        || {
+            for i in 0..edits {
+                let y = middle + i * 13;
+                rows.insert(y, 10, "x");
+                black_box(rows.drain(y, 20, 21));
+            }
+        },
+    );
+    time(S::NAME, "read the length of every row", || {
+        black_box((0..rows.len()).map(|y| rows.row_len(y)).sum::<usize>())
+    });
+    let mut saved = Vec::with_capacity(text.len());
+    time(S::NAME, "save", || rows.write_to(&mut saved).unwrap());
+}
+
+/// the edits of a single row
+fn long_row<S: Storage>(name: &str, text: &str, edits: usize) {
+    let mut rows = time(S::NAME, &format!("open {name}"), || S::from_text(text));
+    let len = rows.row_len(0);
+    time(S::NAME, &format!("{edits} edits in {name}"), || {
+        for i in 0..edits {
+            let x = len / 2 + i * 101;
+            rows.insert(0, x, "x");
+            black_box(rows.drain(0, i * 31, i * 31 + 1));
+        }
+    });
+    time(
+        S::NAME,
+        &format!("{edits} characters typed at the end of {name}"),
+        || {
+            for _ in 0..edits {
+                let end = rows.row_len(0);
+                rows.insert(0, end, "y");
+            }
+        },
+    );
+}
+
+/// open and edit a file as the editor does, highlighting the rows shown
+fn document(name: &str, text: &str, edits: usize, rows: usize) {
+    let path = env::temp_dir().join(format!("storage-bench-{}.rs", process::id()));
+    fs::write(&path, text).unwrap();
+    let mut doc = time("Document", &format!("open {name} and show it"), || {
+        let mut doc = Document::open(path.to_str().unwrap()).unwrap();
+        doc.highlight_to(rows);
+        doc
+    });
+    fs::remove_file(&path).unwrap();
+    // in the rows shown, the rows below them are not highlighted yet
+    let y = rows / 2;
+    let x = doc.row(y).map_or(0, |row| row.len() / 2);
+    time(
+        "Document",
+        &format!("{edits} highlighted edits in {name}"),
+        || {
+            for i in 0..edits {
+                let at = Position { x: x + i, y };
+                doc.insert_str(&at, "x");
+                doc.highlight_to(y + rows);
+                doc.delete(&at);
+                doc.highlight_to(y + rows);
+            }
+        },
+    );
+}
+
+fn main() {
+    let line = "    let total = handle(\"request\", 12) + 7; // served by worker-7\n";
+    let file = line.repeat(100 * MB / line.len());
+    println!("a file of 100 MB, {} rows", file.lines().count());
+    many_rows::<Vec<StringRow>>(&file, 1_000);
+    many_rows::<RowBuffer>(&file, 1_000);
+    document("the file", &file, 100, 50);
+    drop(file);
+
+    let ascii = "x = 1; ".repeat(100 * MB / 7);
+    let accented = "e\u{301}a".repeat(10 * MB / 4);
+    println!("a single line of 100 MB, and one of 10 MB with combining accents");
+    long_row::<Vec<StringRow>>("the ascii line", &ascii, 100);
+    long_row::<RowBuffer>("the ascii line", &ascii, 100);
+    long_row::<Vec<StringRow>>("the accented line", &accented, 20);
+    long_row::<RowBuffer>("the accented line", &accented, 20);
+    // highlighting a whole row of 100 MB takes too long for a benchmark
+    document("an ascii line of 10 MB", &ascii[..10 * MB], 100, 1);
+}
@@ -0,0 +1,37 @@
+use std::{env, fs, path::Path};
+
+/// bundle every language definition in src/highlightkeys and every theme in src/themes into the binary,
+/// so that the editor doesn't depend on the directory it runs in
+fn main() {
+    bundle("highlightkeys", "json", "languages.rs");
+    bundle("themes", "toml", "themes.rs");
+}
+
+/// write `BUNDLED`, the name and the contents of every file in `src/<dir>` with `extension`, to `out_file`
+fn bundle(dir: &str, extension: &str, out_file: &str) {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is not set");
+    let source_dir = Path::new(&manifest_dir).join("src").join(dir);
+    println!("cargo:rerun-if-changed={}", source_dir.display());
+
+    let mut files: Vec<(String, String)> = fs::read_dir(&source_dir)
+        .unwrap_or_else(|_| panic!("can't read src/{dir}"))
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_str()?.to_string();
+            Some((name, path.display().to_string()))
+        })
+        .collect();
+    files.sort();
+
+    let mut code = String::from("pub const BUNDLED: &[(&str, &str)] = &[\n");
+    for (name, path) in files {
+        code.push_str(&format!("    ({name:?}, include_str!({path:?})),\n"));
+    }
+    code.push_str("];\n");
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is not set");
+    fs::write(Path::new(&out_dir).join(out_file), code)
+        .unwrap_or_else(|_| panic!("can't write {out_file}"));
+}
@@ -0,0 +1,557 @@
+use std::ops::{Add, Index, IndexMut, Sub};
+
+use crate::Row;
+
+/// a leaf is split into two halves when it holds more items than this
+const MAX_ITEMS: usize = 512;
+/// an inner node is split into two halves when it has more children than this
+const MAX_CHILDREN: usize = 32;
+
+/// what the items under a node add up to
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Measure {
+    pub items: usize,
+    // the graphemes and the bytes of the items holding text, a row only counts as an item
+    pub graphemes: usize,
+    pub bytes: usize,
+}
+
+impl Add for Measure {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            items: self.items.saturating_add(other.items),
+            graphemes: self.graphemes.saturating_add(other.graphemes),
+            bytes: self.bytes.saturating_add(other.bytes),
+        }
+    }
+}
+
+impl Sub for Measure {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            items: self.items.saturating_sub(other.items),
+            graphemes: self.graphemes.saturating_sub(other.graphemes),
+            bytes: self.bytes.saturating_sub(other.bytes),
+        }
+    }
+}
+
+/// an item kept in a `Tree`
+pub trait Measured {
+    fn measure(&self) -> Measure;
+}
+
+/// a sequence of items in a B-tree whose leaves hold the items in order.
+/// every inner node keeps the measure of the items under each child beside it,
+/// so finding, inserting or removing an item walks down one path of the tree,
+/// which takes O(log n) time. the rows of a document are kept in one,
+/// and so are the chunks of text of a long row
+pub struct Tree<T> {
+    root: Node<T>,
+    measure: Measure,
+}
+
+/// the storage of rows
+pub type RowBuffer = Tree<Row>;
+
+impl Measured for Row {
+    fn measure(&self) -> Measure {
+        Measure {
+            items: 1,
+            ..Measure::default()
+        }
+    }
+}
+
+enum Node<T> {
+    Leaf(Vec<T>),
+    // the measure of the items under each child and the child
+    Inner(Vec<(Measure, Node<T>)>),
+}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Self {
+            root: Node::default(),
+            measure: Measure::default(),
+        }
+    }
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Node::Leaf(Vec::new())
+    }
+}
+
+impl<T: Measured> Tree<T> {
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.measure.items
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.measure.items == 0
+    }
+
+    /// what all the items add up to
+    #[must_use]
+    pub fn measure(&self) -> Measure {
+        self.measure
+    }
+
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.root.get(index)
+    }
+
+    /// the measure of the item must stay the same, remove it and insert it again otherwise
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.root.get_mut(index)
+    }
+
+    #[must_use]
+    pub fn last(&self) -> Option<&T> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.insert(self.len(), item);
+    }
+
+    /// insert an item at `index`, all items after it will be shifted
+    ///
+    /// # Panics
+    ///
+    /// will panic if `index` is greater than the number of items
+    pub fn insert(&mut self, index: usize, item: T) {
+        assert!(
+            index <= self.len(),
+            "insertion index {index} is out of range"
+        );
+        self.measure = self.measure + item.measure();
+        if let Some(right) = self.root.insert(index, item) {
+            // the tree grows from the root, so every leaf stays at the same depth
+            let left = std::mem::take(&mut self.root);
+            let left_measure = left.measure();
+            self.root = Node::Inner(vec![(left_measure, left), (right.measure(), right)]);
+        }
+    }
+
+    /// remove the item at `index` and return it
+    ///
+    /// # Panics
+    ///
+    /// will panic if `index` is out of range
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "removal index {index} is out of range");
+        let item = self.root.remove(index);
+        self.measure = self.measure - item.measure();
+        // the tree shrinks from the root as well
+        while let Node::Inner(children) = &mut self.root {
+            if children.len() > 1 {
+                break;
+            }
+            self.root = children.pop().map(|(_, child)| child).unwrap_or_default();
+        }
+        item
+    }
+
+    /// the index of the item holding `position`, counted by `metric` from the start,
+    /// and the measure of the items before it. `None` if `position` is at the end or after it
+    #[must_use]
+    pub fn seek(&self, position: usize, metric: fn(&Measure) -> usize) -> Option<(usize, Measure)> {
+        let mut before = Measure::default();
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Leaf(items) => {
+                    for item in items {
+                        let after = before + item.measure();
+                        if position < metric(&after) {
+                            return Some((before.items, before));
+                        }
+                        before = after;
+                    }
+                    return None;
+                }
+                Node::Inner(children) => {
+                    let mut found = None;
+                    for (measure, child) in children {
+                        let after = before + *measure;
+                        if position < metric(&after) {
+                            found = Some(child);
+                            break;
+                        }
+                        before = after;
+                    }
+                    node = found?;
+                }
+            }
+        }
+    }
+
+    /// move the items from `index` on to a new tree.
+    /// both trees are built again, which takes O(n) time but doesn't copy the items
+    #[must_use]
+    pub fn split_off(&mut self, index: usize) -> Self {
+        let mut items = std::mem::take(self).into_items();
+        let rest = items.split_off(index.min(items.len()));
+        *self = items.into_iter().collect();
+        rest.into_iter().collect()
+    }
+
+    /// the items in order, the tree is taken apart
+    #[must_use]
+    pub fn into_items(self) -> Vec<T> {
+        let mut items = Vec::with_capacity(self.len());
+        self.root.into_items(&mut items);
+        items
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.root.iter()
+    }
+}
+
+impl<T: Measured> Node<T> {
+    /// what the items under this node add up to
+    fn measure(&self) -> Measure {
+        match self {
+            Node::Leaf(items) => items
+                .iter()
+                .fold(Measure::default(), |sum, item| sum + item.measure()),
+            Node::Inner(children) => children
+                .iter()
+                .fold(Measure::default(), |sum, (measure, _)| sum + *measure),
+        }
+    }
+
+    /// whether the node has less than half of the items or children it can hold
+    fn is_underfull(&self) -> bool {
+        match self {
+            Node::Leaf(items) => items.len() < MAX_ITEMS / 2,
+            Node::Inner(children) => children.len() < MAX_CHILDREN / 2,
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        match self {
+            Node::Leaf(items) => items.get(index),
+            Node::Inner(children) => {
+                let (child, offset) = locate(children, index)?;
+                children[child].1.get(offset)
+            }
+        }
+    }
+
+    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        match self {
+            Node::Leaf(items) => items.get_mut(index),
+            Node::Inner(children) => {
+                let (child, offset) = locate(children, index)?;
+                children[child].1.get_mut(offset)
+            }
+        }
+    }
+
+    /// insert an item at `index`, return the second half of this node if it has to be split
+    fn insert(&mut self, index: usize, item: T) -> Option<Node<T>> {
+        match self {
+            Node::Leaf(items) => items.insert(index, item),
+            Node::Inner(children) => {
+                // appending to the end means appending to the last child
+                let (child, offset) = locate(children, index).unwrap_or_else(|| {
+                    let last = children.len().saturating_sub(1);
+                    (last, children[last].0.items)
+                });
+                let (measure, node) = &mut children[child];
+                *measure = *measure + item.measure();
+                if let Some(right) = node.insert(offset, item) {
+                    let right_measure = right.measure();
+                    *measure = *measure - right_measure;
+                    children.insert(child.saturating_add(1), (right_measure, right));
+                }
+            }
+        }
+        self.split_if_full()
+    }
+
+    /// remove the item at `index`, an underfull child is merged with its sibling
+    fn remove(&mut self, index: usize) -> T {
+        match self {
+            Node::Leaf(items) => items.remove(index),
+            Node::Inner(children) => {
+                let (child, offset) = locate(children, index).expect("item index out of range");
+                let (measure, node) = &mut children[child];
+                let item = node.remove(offset);
+                *measure = *measure - item.measure();
+                if node.is_underfull() {
+                    merge_child(children, child);
+                }
+                item
+            }
+        }
+    }
+
+    /// split off the second half of this node if it holds too many items or children
+    fn split_if_full(&mut self) -> Option<Node<T>> {
+        match self {
+            Node::Leaf(items) if items.len() > MAX_ITEMS => {
+                Some(Node::Leaf(items.split_off(items.len() / 2)))
+            }
+            Node::Inner(children) if children.len() > MAX_CHILDREN => {
+                Some(Node::Inner(children.split_off(children.len() / 2)))
+            }
+            _ => None,
+        }
+    }
+
+    /// append the items or children of `other`, a node at the same depth
+    fn append(&mut self, other: Node<T>) {
+        match (self, other) {
+            (Node::Leaf(items), Node::Leaf(mut others)) => items.append(&mut others),
+            (Node::Inner(children), Node::Inner(mut others)) => children.append(&mut others),
+            _ => unreachable!("every leaf is at the same depth"),
+        }
+    }
+
+    fn into_items(self, items: &mut Vec<T>) {
+        match self {
+            Node::Leaf(mut leaf) => items.append(&mut leaf),
+            Node::Inner(children) => {
+                for (_, child) in children {
+                    child.into_items(items);
+                }
+            }
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        match self {
+            Node::Leaf(items) => Box::new(items.iter()),
+            Node::Inner(children) => Box::new(children.iter().flat_map(|(_, child)| child.iter())),
+        }
+    }
+}
+
+/// find the child which contains the item at `index` and the index of the item in it
+fn locate<T>(children: &[(Measure, Node<T>)], index: usize) -> Option<(usize, usize)> {
+    let mut offset = index;
+    for (child, (measure, _)) in children.iter().enumerate() {
+        if offset < measure.items {
+            return Some((child, offset));
+        }
+        offset -= measure.items;
+    }
+    None
+}
+
+/// merge the child at `index` with a sibling,
+/// the merged node is split again into two halves if it is too large
+fn merge_child<T: Measured>(children: &mut Vec<(Measure, Node<T>)>, index: usize) {
+    if children.len() < 2 {
+        return;
+    }
+    let left = index.min(children.len().saturating_sub(2));
+    let (right_measure, right) = children.remove(left.saturating_add(1));
+    let (measure, node) = &mut children[left];
+    *measure = *measure + right_measure;
+    node.append(right);
+    if let Some(rest) = node.split_if_full() {
+        let rest_measure = rest.measure();
+        *measure = *measure - rest_measure;
+        children.insert(left.saturating_add(1), (rest_measure, rest));
+    }
+}
+
+impl<T: Measured> FromIterator<T> for Tree<T> {
+    /// build the tree from the bottom up
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        // keep the nodes half full, so that there is room for new items
+        let mut nodes = Vec::new();
+        let mut items = Vec::new();
+        for item in iter {
+            items.push(item);
+            if items.len() == MAX_ITEMS / 2 {
+                nodes.push(Node::Leaf(std::mem::replace(
+                    &mut items,
+                    Vec::with_capacity(MAX_ITEMS),
+                )));
+            }
+        }
+        if !items.is_empty() || nodes.is_empty() {
+            nodes.push(Node::Leaf(items));
+        }
+        while nodes.len() > 1 {
+            let mut children = nodes
+                .into_iter()
+                .map(|node| (node.measure(), node))
+                .peekable();
+            nodes = Vec::new();
+            while children.peek().is_some() {
+                nodes.push(Node::Inner(
+                    children.by_ref().take(MAX_CHILDREN / 2).collect(),
+                ));
+            }
+        }
+        let root = nodes.pop().unwrap_or_default();
+        Self {
+            measure: root.measure(),
+            root,
+        }
+    }
+}
+
+impl<T: Measured> Extend<T> for Tree<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            self.push(item);
+        }
+    }
+}
+
+impl<T: Measured> Index<usize> for Tree<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.get(index).expect("item index out of range")
+    }
+}
+
+impl<T: Measured> IndexMut<usize> for Tree<T> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.get_mut(index).expect("item index out of range")
+    }
+}
+
+#[cfg(test)]
+mod buffer_tests {
+    use super::{Measure, Measured, Node, RowBuffer, Tree, MAX_CHILDREN, MAX_ITEMS};
+    use crate::Row;
+
+    /// check the numbers of items kept in the tree and their measures, return its depth
+    fn depth<T: Measured>(node: &Node<T>) -> usize {
+        match node {
+            Node::Leaf(items) => {
+                assert!(items.len() <= MAX_ITEMS);
+                1
+            }
+            Node::Inner(children) => {
+                assert!(children.len() <= MAX_CHILDREN);
+                let depths: Vec<usize> = children
+                    .iter()
+                    .map(|(measure, child)| {
+                        assert_eq!(*measure, child.measure());
+                        depth(child)
+                    })
+                    .collect();
+                assert!(depths.windows(2).all(|pair| pair[0] == pair[1]));
+                depths[0] + 1
+            }
+        }
+    }
+
+    fn contents(buffer: &RowBuffer) -> Vec<String> {
+        buffer.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut buffer = RowBuffer::default();
+        buffer.push(Row::from("b"));
+        buffer.insert(0, Row::from("a"));
+        buffer.push(Row::from("d"));
+        buffer.insert(2, Row::from("c"));
+        assert_eq!(contents(&buffer), vec!["a", "b", "c", "d"]);
+        assert_eq!(buffer.remove(1).to_string(), "b");
+        assert_eq!(contents(&buffer), vec!["a", "c", "d"]);
+        assert_eq!(buffer.last().unwrap().to_string(), "d");
+        assert!(buffer.get(3).is_none());
+    }
+
+    #[test]
+    fn rows_across_nodes() {
+        // enough rows for a tree of three levels
+        let count = MAX_ITEMS * MAX_CHILDREN * 2;
+        let mut buffer: RowBuffer = (0..count).map(|i| Row::from(&i.to_string()[..])).collect();
+        let mut expected: Vec<String> = (0..count).map(|i| i.to_string()).collect();
+        assert_eq!(depth(&buffer.root), 3);
+        // fill one leaf until it has to be split, again and again
+        for i in 0..MAX_ITEMS * 4 {
+            buffer.insert(10, Row::from(&format!("new{i}")[..]));
+            expected.insert(10, format!("new{i}"));
+        }
+        buffer.remove(0);
+        expected.remove(0);
+        assert_eq!(contents(&buffer), expected);
+        assert_eq!(buffer.len(), expected.len());
+        for (index, row) in expected.iter().enumerate().step_by(97) {
+            assert_eq!(&buffer[index].to_string(), row);
+        }
+        depth(&buffer.root);
+        // removing rows merges the nodes, until the root is a leaf again
+        let mut step = 0;
+        while !buffer.is_empty() {
+            step = (step + 7919) % buffer.len();
+            assert_eq!(buffer.remove(step).to_string(), expected.remove(step));
+            if step % 1000 == 0 {
+                depth(&buffer.root);
+            }
+        }
+        assert_eq!(depth(&buffer.root), 1);
+        buffer.push(Row::from("a"));
+        assert_eq!(contents(&buffer), vec!["a"]);
+    }
+
+    /// a piece of text which counts its characters as graphemes
+    struct Text(String);
+
+    impl Measured for Text {
+        fn measure(&self) -> Measure {
+            Measure {
+                items: 1,
+                graphemes: self.0.chars().count(),
+                bytes: self.0.len(),
+            }
+        }
+    }
+
+    #[test]
+    fn seek_and_split() {
+        let mut tree: Tree<Text> = (0..MAX_ITEMS * 3)
+            .map(|i| Text("é".repeat(i % 3)))
+            .collect();
+        let graphemes = MAX_ITEMS * 3;
+        assert_eq!(tree.measure().graphemes, graphemes);
+        assert_eq!(tree.measure().bytes, graphemes * 2);
+        // the items of 0, 1 and 2 graphemes repeat, every 3 items hold 3 graphemes
+        let (index, before) = tree.seek(1000, |m| m.graphemes).unwrap();
+        assert_eq!((index, before.graphemes), (1001, 1000));
+        assert_eq!(tree.seek(2000, |m| m.bytes).unwrap().0, 1001);
+        assert!(tree.seek(graphemes, |m| m.graphemes).is_none());
+        // an empty item is never found
+        assert_ne!(tree.seek(0, |m| m.graphemes).unwrap().0, 0);
+
+        tree.insert(1001, Text("abc".to_string()));
+        assert_eq!(tree.measure().graphemes, graphemes + 3);
+        assert_eq!(tree.seek(1002, |m| m.graphemes).unwrap().0, 1001);
+        assert_eq!(tree.seek(1003, |m| m.graphemes).unwrap().0, 1002);
+        depth(&tree.root);
+
+        let rest = tree.split_off(1002);
+        assert_eq!(rest.len(), MAX_ITEMS * 3 + 1 - 1002);
+        assert_eq!(tree.len(), 1002);
+        assert_eq!(
+            tree.measure().graphemes + rest.measure().graphemes,
+            graphemes + 3
+        );
+        depth(&tree.root);
+        depth(&rest.root);
+    }
+}
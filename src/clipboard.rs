@@ -0,0 +1,132 @@
+use crate::Terminal;
+
+const BASE64_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// like `kill-ring-max` of Emacs, the oldest text is dropped when there are more
+const MAX_ENTRIES: usize = 60;
+
+/// the texts copied or cut by the user, a kill ring like the one of Emacs.
+/// the newest text is pasted, unless the user goes back to an older one.
+/// if `osc52` is on, the text will also be sent to the clipboard of the terminal
+/// by an OSC 52 escape sequence, so that it can be pasted in other programs
+pub struct Clipboard {
+    // the oldest text comes first
+    entries: Vec<String>,
+    // the text that is pasted
+    current: usize,
+    pub osc52: bool,
+}
+
+impl Clipboard {
+    #[must_use]
+    pub fn new(osc52: bool) -> Self {
+        Self {
+            entries: Vec::new(),
+            current: 0,
+            osc52,
+        }
+    }
+
+    pub fn copy(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.entries.push(text);
+        self.current = self.entries.len().saturating_sub(1);
+        self.send_to_terminal();
+    }
+
+    /// add `text` to the end of the newest text, the lines killed one after another are pasted together
+    pub fn append(&mut self, text: &str) {
+        let Some(last) = self.entries.last_mut() else {
+            self.copy(text.to_string());
+            return;
+        };
+        last.push_str(text);
+        self.current = self.entries.len().saturating_sub(1);
+        self.send_to_terminal();
+    }
+
+    /// go back to the text copied before the one that is pasted, the oldest one is followed by the newest
+    pub fn rotate(&mut self) -> &str {
+        self.current = self
+            .current
+            .checked_sub(1)
+            .unwrap_or(self.entries.len().saturating_sub(1));
+        self.text()
+    }
+
+    #[must_use]
+    pub fn text(&self) -> &str {
+        self.entries.get(self.current).map_or("", String::as_str)
+    }
+
+    fn send_to_terminal(&self) {
+        if self.osc52 {
+            Terminal::set_clipboard(&base64(self.text().as_bytes()));
+        }
+    }
+}
+
+/// OSC 52 requires the text to be encoded in base64
+fn base64(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(char::from(
+                    BASE64_TABLE[(n >> (18 - 6 * i)) as usize & 0x3f],
+                ));
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod clipboard_tests {
+    use super::{base64, Clipboard, MAX_ENTRIES};
+
+    #[test]
+    fn base64_test() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("hello\nwörld".as_bytes()), "aGVsbG8Kd8O2cmxk");
+    }
+
+    #[test]
+    fn kill_ring() {
+        let mut clipboard = Clipboard::new(false);
+        assert_eq!(clipboard.rotate(), "");
+        clipboard.copy("one".to_string());
+        clipboard.copy(String::new());
+        clipboard.copy("two".to_string());
+        clipboard.append(" more");
+        assert_eq!(clipboard.text(), "two more");
+        assert_eq!(clipboard.rotate(), "one");
+        assert_eq!(clipboard.rotate(), "two more");
+        // a new text is pasted next, wherever the ring was rotated to
+        clipboard.rotate();
+        clipboard.copy("three".to_string());
+        assert_eq!(clipboard.text(), "three");
+
+        for i in 0..MAX_ENTRIES {
+            clipboard.copy(i.to_string());
+        }
+        assert_eq!(clipboard.entries.len(), MAX_ENTRIES);
+        assert_eq!(clipboard.entries[0], "0");
+    }
+}
@@ -0,0 +1,421 @@
+use std::{fs, path::Path};
+
+use crate::{
+    config::{Config, SETTINGS},
+    keymap::{Command, REGISTRY},
+};
+
+// the oldest lines are forgotten beyond this
+const MAX_HISTORY: usize = 100;
+
+// the commands which aren't in the keymap, with their short names.
+// the ones followed by an argument are completed with a space after them
+const COMMANDS: &[(&str, &str, bool)] = &[
+    ("write", "w", true),
+    ("edit", "e", true),
+    ("goto", "g", true),
+    ("set", "", true),
+    ("wq", "x", false),
+    ("q", "", false),
+];
+
+/// a line typed in the command line
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Invocation {
+    // a command of the keymap called by its name, like `split-vertical`
+    Run(Command),
+    // save, or write a copy to the file if there is one
+    Write(Option<String>),
+    WriteQuit,
+    Edit(String),
+    // read the file of the current buffer again
+    Reload,
+    Goto(Target),
+    // the name of the setting and its value
+    Set(String, String),
+}
+
+/// the line to go to, from 1 or relative to the cursor
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Line {
+    Number(usize),
+    // `+N`
+    Down(usize),
+    // `-N`
+    Up(usize),
+}
+
+/// where `goto` moves the cursor, the column is counted from 1 too
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Target {
+    pub line: Line,
+    pub column: Option<usize>,
+}
+
+// how a target is written, shown when it can't be read
+pub const TARGET_FORMAT: &str = "expected a line like 120, 120:45, +10 or -10";
+
+/// read a target written like `120`, `120:45` or `+10`, the way compilers print the place of an error
+#[must_use]
+pub fn parse_target(text: &str) -> Option<Target> {
+    let (line, column) = text
+        .trim()
+        .split_once(':')
+        .map_or((text.trim(), None), |(line, column)| (line, Some(column)));
+    // the sign is read here, `parse` would accept a `+`
+    let number = |text: &str| {
+        Some(text)
+            .filter(|text| text.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|text| text.parse().ok())
+    };
+    let line = if let Some(lines) = line.strip_prefix('+') {
+        Line::Down(number(lines)?)
+    } else if let Some(lines) = line.strip_prefix('-') {
+        Line::Up(number(lines)?)
+    } else {
+        Line::Number(number(line)?)
+    };
+    let column = match column {
+        Some(column) => Some(number(column)?),
+        None => None,
+    };
+    Some(Target { line, column })
+}
+
+/// read `line`, e.g. `w name`, `goto 120`, `set tab_width=4` or `e file`.
+/// the `:` of Vim can be typed before the command, and a bare target, like `120`, goes there
+pub fn parse(line: &str) -> Result<Invocation, String> {
+    let line = line.trim().trim_start_matches(':').trim_start();
+    let (name, argument) = line
+        .split_once(char::is_whitespace)
+        .map_or((line, ""), |(name, argument)| (name, argument.trim()));
+    let argument = (!argument.is_empty()).then(|| argument.to_string());
+    if let (Some(target), None) = (parse_target(name), &argument) {
+        return Ok(Invocation::Goto(target));
+    }
+    match (name, argument) {
+        ("w" | "write", file) => Ok(Invocation::Write(file)),
+        ("e" | "edit", Some(file)) => Ok(Invocation::Edit(file)),
+        ("e" | "edit", None) => Ok(Invocation::Reload),
+        ("g" | "goto", target) => target
+            .as_deref()
+            .and_then(parse_target)
+            .map(Invocation::Goto)
+            .ok_or_else(|| TARGET_FORMAT.to_string()),
+        ("set", argument) => argument
+            .as_deref()
+            .and_then(|argument| argument.split_once('='))
+            .map(|(name, value)| Invocation::Set(name.trim().to_string(), value.to_string()))
+            .ok_or_else(|| "expected a setting like tab_width=4".to_string()),
+        ("wq" | "x", None) => Ok(Invocation::WriteQuit),
+        ("q", None) => Ok(Invocation::Run(Command::Quit)),
+        (name, None) => Command::from_name(name)
+            .map(Invocation::Run)
+            .ok_or_else(|| format!("unknown command `{name}`")),
+        (name, Some(_)) => Err(format!("`{name}` takes no argument")),
+    }
+}
+
+/// the lines `line` can be completed to, in order: the names of the commands,
+/// the files of `write` and `edit`, the settings of `set` and the names they can be set to.
+/// `themes` are the names of the themes
+#[must_use]
+pub fn complete(line: &str, themes: &[String]) -> Vec<String> {
+    let Some((name, argument)) = line.split_once(' ') else {
+        return command_names()
+            .into_iter()
+            .filter(|name| name.starts_with(line))
+            .collect();
+    };
+    let arguments = match name {
+        "w" | "write" | "e" | "edit" => files(argument),
+        "set" => match argument.split_once('=') {
+            None => SETTINGS
+                .iter()
+                .filter(|setting| setting.starts_with(argument))
+                .map(|setting| format!("{setting}="))
+                .collect(),
+            Some((setting, value)) => {
+                let choices = if setting == "theme" {
+                    themes.to_vec()
+                } else {
+                    Config::choices(setting)
+                        .into_iter()
+                        .map(ToString::to_string)
+                        .collect()
+                };
+                choices
+                    .into_iter()
+                    .filter(|choice| choice.starts_with(value))
+                    .map(|choice| format!("{setting}={choice}"))
+                    .collect()
+            }
+        },
+        _ => Vec::new(),
+    };
+    arguments
+        .into_iter()
+        .map(|argument| format!("{name} {argument}"))
+        .collect()
+}
+
+/// every name a command can be called by, the ones taking an argument end with a space
+fn command_names() -> Vec<String> {
+    let mut names: Vec<String> = COMMANDS
+        .iter()
+        .flat_map(|(name, short, _)| [name, short])
+        .filter(|name| !name.is_empty())
+        .map(ToString::to_string)
+        .collect();
+    names.extend(REGISTRY.iter().map(|(name, _)| (*name).to_string()));
+    names.sort();
+    names.dedup();
+    for name in &mut names {
+        let takes_argument = COMMANDS
+            .iter()
+            .any(|(long, short, argument)| *argument && (long == name || short == name));
+        if takes_argument {
+            name.push(' ');
+        }
+    }
+    names
+}
+
+/// the files and directories starting with `path`, the directories end with `/`
+fn files(path: &str) -> Vec<String> {
+    let (dir, prefix) = path.rsplit_once('/').map_or(("", path), |(dir, prefix)| {
+        // the root directory
+        (if dir.is_empty() { "/" } else { dir }, prefix)
+    });
+    let Ok(entries) = fs::read_dir(if dir.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(dir)
+    }) else {
+        return Vec::new();
+    };
+    let mut files: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // the hidden files are only completed once their `.` is typed
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(match dir {
+                "" => format!("{name}{slash}"),
+                "/" => format!("/{name}{slash}"),
+                dir => format!("{dir}/{name}{slash}"),
+            })
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// the lines entered before, browsed with UP and DOWN from the newest one
+#[derive(Default)]
+pub struct History {
+    lines: Vec<String>,
+    // the line being shown, `None` while the user is typing a new one
+    browsing: Option<usize>,
+    // the line typed before browsing, DOWN comes back to it
+    typed: String,
+}
+
+impl History {
+    /// remember `line`, unless it repeats the last one, and stop browsing
+    pub fn add(&mut self, line: &str) {
+        self.browsing = None;
+        if line.trim().is_empty() || self.lines.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.lines.push(line.to_string());
+        if self.lines.len() > MAX_HISTORY {
+            self.lines.remove(0);
+        }
+    }
+
+    /// the line before the one shown, `typed` is the input which will be given back
+    /// by coming back with `newer`. `None` at the oldest line
+    pub fn older(&mut self, typed: &str) -> Option<&str> {
+        let index = if let Some(index) = self.browsing {
+            index.checked_sub(1)?
+        } else {
+            self.typed = typed.to_string();
+            self.lines.len().checked_sub(1)?
+        };
+        self.browsing = Some(index);
+        self.lines.get(index).map(String::as_str)
+    }
+
+    /// the line after the one shown, or the typed one after the newest line.
+    /// `None` if no line is shown
+    pub fn newer(&mut self) -> Option<&str> {
+        let index = self.browsing?.saturating_add(1);
+        if index < self.lines.len() {
+            self.browsing = Some(index);
+            self.lines.get(index).map(String::as_str)
+        } else {
+            self.browsing = None;
+            Some(&self.typed)
+        }
+    }
+
+    /// stop browsing, the next UP starts from the newest line again
+    pub fn reset(&mut self) {
+        self.browsing = None;
+    }
+}
+
+#[cfg(test)]
+mod command_line_tests {
+    use std::{env, fs, process};
+
+    use super::{complete, parse, parse_target, History, Invocation, Line, Target};
+    use crate::keymap::Command;
+
+    #[test]
+    fn parse_commands() {
+        let parsed = |line| parse(line);
+        assert_eq!(parsed("w"), Ok(Invocation::Write(None)));
+        assert_eq!(
+            parsed(":w  notes.txt "),
+            Ok(Invocation::Write(Some("notes.txt".to_string())))
+        );
+        assert_eq!(
+            parsed("edit src/main.rs"),
+            Ok(Invocation::Edit("src/main.rs".to_string()))
+        );
+        let line = |number| Target {
+            line: Line::Number(number),
+            column: None,
+        };
+        assert_eq!(parsed("goto 120"), Ok(Invocation::Goto(line(120))));
+        assert_eq!(parsed(":42"), Ok(Invocation::Goto(line(42))));
+        assert_eq!(
+            parsed("set tabwidth = 4"),
+            Ok(Invocation::Set("tabwidth".to_string(), " 4".to_string()))
+        );
+        assert_eq!(parsed("wq"), Ok(Invocation::WriteQuit));
+        assert_eq!(parsed("q"), Ok(Invocation::Run(Command::Quit)));
+        assert_eq!(
+            parsed("split-vertical"),
+            Ok(Invocation::Run(Command::SplitVertical))
+        );
+
+        assert_eq!(parsed("e"), Ok(Invocation::Reload));
+        assert_eq!(
+            parsed("goto x"),
+            Err("expected a line like 120, 120:45, +10 or -10".to_string())
+        );
+        assert_eq!(
+            parsed("set tab_width"),
+            Err("expected a setting like tab_width=4".to_string())
+        );
+        assert_eq!(parsed("fly"), Err("unknown command `fly`".to_string()));
+        assert_eq!(
+            parsed("undo 3"),
+            Err("`undo` takes no argument".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_targets() {
+        let target = |line, column| Some(Target { line, column });
+        assert_eq!(parse_target("120"), target(Line::Number(120), None));
+        assert_eq!(
+            parse_target(" 123:45 "),
+            target(Line::Number(123), Some(45))
+        );
+        assert_eq!(parse_target("+10"), target(Line::Down(10), None));
+        assert_eq!(parse_target("-3:1"), target(Line::Up(3), Some(1)));
+        assert_eq!(parse_target("0"), target(Line::Number(0), None));
+
+        assert_eq!(parse_target(""), None);
+        assert_eq!(parse_target("+"), None);
+        assert_eq!(parse_target("--3"), None);
+        assert_eq!(parse_target("+-3"), None);
+        assert_eq!(parse_target("12:"), None);
+        assert_eq!(parse_target("12:+4"), None);
+        assert_eq!(parse_target("1:2:3"), None);
+        assert_eq!(parse_target("x"), None);
+    }
+
+    #[test]
+    fn complete_names_and_arguments() {
+        let themes = ["default".to_string(), "light".to_string()];
+        assert_eq!(
+            complete("sp", &themes),
+            ["split-horizontal", "split-vertical"]
+        );
+        assert_eq!(
+            complete("se", &themes),
+            [
+                "search",
+                "search-backward",
+                "select-down",
+                "select-left",
+                "select-right",
+                "select-up",
+                "set "
+            ]
+        );
+        assert_eq!(complete("wr", &themes), ["write "]);
+        assert_eq!(
+            complete("set s", &themes),
+            ["set soft_wrap=", "set status_timeout="]
+        );
+        assert_eq!(
+            complete("set soft_wrap=", &themes),
+            [
+                "set soft_wrap=off",
+                "set soft_wrap=anywhere",
+                "set soft_wrap=words"
+            ]
+        );
+        assert_eq!(complete("set theme=l", &themes), ["set theme=light"]);
+        assert!(complete("goto 1", &themes).is_empty());
+
+        let dir = env::temp_dir().join(format!("text-editor-complete-{}", process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+        let dir_name = dir.display();
+        assert_eq!(
+            complete(&format!("e {dir_name}/"), &themes),
+            [
+                format!("e {dir_name}/notes.txt"),
+                format!("e {dir_name}/src/")
+            ]
+        );
+        assert_eq!(
+            complete(&format!("w {dir_name}/.h"), &themes),
+            [format!("w {dir_name}/.hidden")]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn browse_history() {
+        let mut history = History::default();
+        assert_eq!(history.older("typed"), None);
+        history.add("w");
+        history.add("goto 3");
+        history.add("goto 3");
+        history.add(" ");
+
+        assert_eq!(history.newer(), None);
+        assert_eq!(history.older("typed"), Some("goto 3"));
+        assert_eq!(history.older("ignored"), Some("w"));
+        assert_eq!(history.older("ignored"), None);
+        assert_eq!(history.newer(), Some("goto 3"));
+        assert_eq!(history.newer(), Some("typed"));
+        assert_eq!(history.newer(), None);
+
+        history.older("");
+        history.reset();
+        assert_eq!(history.older(""), Some("goto 3"));
+    }
+}
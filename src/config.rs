@@ -0,0 +1,541 @@
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use termion::event::Key;
+use toml::{Table, Value};
+
+use crate::{
+    filetype::SchemaError,
+    keymap::{parse_keys, Command, Keymap},
+    theme::DEFAULT_THEME,
+};
+
+// the settings of the user, in `config_dir`, or system wide in `$XDG_CONFIG_DIRS/<editor>`
+const CONFIG_FILE: &str = "config.toml";
+// the settings of a project, in the current directory or one of its parents
+const PROJECT_FILE: &str = concat!(".", env!("CARGO_PKG_NAME"), ".toml");
+const MAX_TAB_WIDTH: usize = 32;
+
+/// how the rows are numbered in the gutter
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    // the distance to the cursor row, the cursor row itself shows its absolute number
+    Relative,
+}
+
+/// how rows longer than the screen are shown
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum SoftWrap {
+    // the row is scrolled horizontally
+    Off,
+    Anywhere,
+    // break lines after whitespace, unless a word is longer than the screen
+    Words,
+}
+
+/// the way of editing, on top of the key bindings
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Profile {
+    // the keys type text unless they are bound to a command
+    Default,
+    // the normal, insert and visual modes of Vim
+    Vim,
+    // the keys of Emacs and its kill ring
+    Emacs,
+}
+
+const LINE_NUMBERS: [(&str, LineNumbers); 3] = [
+    ("off", LineNumbers::Off),
+    ("absolute", LineNumbers::Absolute),
+    ("relative", LineNumbers::Relative),
+];
+
+const SOFT_WRAP: [(&str, SoftWrap); 3] = [
+    ("off", SoftWrap::Off),
+    ("anywhere", SoftWrap::Anywhere),
+    ("words", SoftWrap::Words),
+];
+
+const PROFILES: [(&str, Profile); 3] = [
+    ("default", Profile::Default),
+    ("vim", Profile::Vim),
+    ("emacs", Profile::Emacs),
+];
+
+/// the names of the settings in the settings file
+pub const SETTINGS: &[&str] = &[
+    "tab_width",
+    "soft_wrap",
+    "line_numbers",
+    "theme",
+    "autosave",
+    "keys",
+    "profile",
+    "quit_times",
+    "status_timeout",
+    "osc52",
+];
+
+/// the settings of the editor, the defaults are overridden by the settings files
+#[derive(Clone, PartialEq, Debug)]
+pub struct Config {
+    // a tab reaches the next multiple of this column
+    pub tab_width: usize,
+    pub soft_wrap: SoftWrap,
+    pub line_numbers: LineNumbers,
+    // the name of the theme, a bundled one or one in `theme::user_dir`
+    pub theme: String,
+    // how often the modified buffers are saved, `None` if they are only saved by the user
+    pub autosave: Option<Duration>,
+    // the keys of the profile with the ones bound by the user on top
+    pub keymap: Keymap,
+    pub profile: Profile,
+    // the keys bound in the settings files, in order
+    bindings: Vec<(Vec<Key>, Command)>,
+    // how many times CTRL-Q must be pressed again to quit without saving
+    pub quit_times: u8,
+    // how long a status message is shown
+    pub status_timeout: Duration,
+    // whether the copied text is sent to the clipboard of the terminal as well
+    pub osc52: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            soft_wrap: SoftWrap::Off,
+            line_numbers: LineNumbers::Off,
+            theme: DEFAULT_THEME.to_string(),
+            autosave: None,
+            keymap: Keymap::default(),
+            profile: Profile::Default,
+            bindings: Vec::new(),
+            quit_times: 1,
+            status_timeout: Duration::from_secs(5),
+            osc52: true,
+        }
+    }
+}
+
+impl Config {
+    /// the defaults overridden by every file in `files` in turn, the missing files are skipped.
+    /// an invalid setting is left out and reported with the name of its file
+    #[must_use]
+    pub fn load(files: &[PathBuf]) -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        let mut errors = Vec::new();
+        for file in files {
+            match fs::read_to_string(file) {
+                Ok(source) => errors.extend(
+                    config
+                        .merge(&source)
+                        .into_iter()
+                        .map(|e| format!("{}: {e}", file.display())),
+                ),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => errors.push(format!("{}: {e}", file.display())),
+            }
+        }
+        (config, errors)
+    }
+
+    /// apply the settings in `source`, a TOML document.
+    /// the settings it doesn't mention or gets wrong keep their values
+    pub fn merge(&mut self, source: &str) -> Vec<SchemaError> {
+        let table = match source.parse::<Table>() {
+            Ok(table) => table,
+            Err(e) => {
+                // the message of the parser spans several lines with a snippet of the file
+                let line = e
+                    .span()
+                    .and_then(|span| source.get(..span.start))
+                    .map_or(1, |before| before.matches('\n').count().saturating_add(1));
+                return vec![SchemaError::new(
+                    &format!("line {line}"),
+                    e.message().trim_end(),
+                )];
+            }
+        };
+        let mut errors = Vec::new();
+        for (key, value) in &table {
+            let result = match key.as_str() {
+                "tab_width" => {
+                    as_integer(value, key, 1, MAX_TAB_WIDTH).map(|width| self.tab_width = width)
+                }
+                "soft_wrap" => as_choice(value, key, &SOFT_WRAP).map(|mode| self.soft_wrap = mode),
+                "line_numbers" => {
+                    as_choice(value, key, &LINE_NUMBERS).map(|mode| self.line_numbers = mode)
+                }
+                "theme" => value
+                    .as_str()
+                    .filter(|name| !name.is_empty())
+                    .map(|name| self.theme = name.to_string())
+                    .ok_or_else(|| SchemaError::new(key, "expected the name of a theme")),
+                // 0 turns it off
+                "autosave" => as_seconds(value, key)
+                    .map(|interval| self.autosave = (!interval.is_zero()).then_some(interval)),
+                "keys" => {
+                    as_table(value, key).map(|keys| errors.extend(self.merge_keys(keys, key)))
+                }
+                "profile" => as_choice(value, key, &PROFILES).map(|profile| self.profile = profile),
+                "quit_times" => {
+                    as_integer(value, key, 0, u8::MAX).map(|times| self.quit_times = times)
+                }
+                "status_timeout" => {
+                    as_seconds(value, key).map(|timeout| self.status_timeout = timeout)
+                }
+                "osc52" => value
+                    .as_bool()
+                    .map(|on| self.osc52 = on)
+                    .ok_or_else(|| SchemaError::new(key, "expected true or false")),
+                _ => Err(unknown_key(key, SETTINGS)),
+            };
+            if let Err(e) = result {
+                errors.push(e);
+            }
+        }
+        // the keys bound by the user stay when a later file switches the profile
+        self.keymap = match self.profile {
+            Profile::Emacs => Keymap::emacs(),
+            Profile::Default | Profile::Vim => Keymap::default(),
+        };
+        for (keys, command) in &self.bindings {
+            self.keymap.bind(keys.clone(), *command);
+        }
+        errors
+    }
+
+    /// change the setting `name` to `value`, which is written like in the settings file,
+    /// but a word can be left without quotes, e.g. `soft_wrap` and `words`.
+    /// the underscores of the name can be left out too, like in `tabwidth`
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), SchemaError> {
+        let name = SETTINGS
+            .iter()
+            .find(|setting| **setting == name || setting.replace('_', "") == name)
+            .ok_or_else(|| unknown_key(name, SETTINGS))?;
+        let value = value.trim();
+        let mut source = format!("{name} = {value}");
+        if source.parse::<Table>().is_err() {
+            source = format!("{name} = {}", Value::from(value));
+        }
+        match self.merge(&source).into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// the names `setting` can be set to, empty if it isn't set to a name from a list
+    #[must_use]
+    pub fn choices(setting: &str) -> Vec<&'static str> {
+        let names = match setting {
+            "soft_wrap" => SOFT_WRAP.map(|(name, _)| name),
+            "line_numbers" => LINE_NUMBERS.map(|(name, _)| name),
+            "profile" => PROFILES.map(|(name, _)| name),
+            _ => return Vec::new(),
+        };
+        names.to_vec()
+    }
+
+    /// add the keys in `table` to the bindings, e.g. `ctrl-w = "close-window"` or `"ctrl-k ctrl-c" = "copy"`
+    fn merge_keys(&mut self, table: &Table, path: &str) -> Vec<SchemaError> {
+        let mut errors = Vec::new();
+        for (name, value) in table {
+            let path = format!("{path}.{name}");
+            let Some(keys) = parse_keys(name) else {
+                errors.push(SchemaError::new(
+                    &path,
+                    "not a key, expected names like ctrl-s, alt-left, f5 or esc separated by spaces",
+                ));
+                continue;
+            };
+            match value.as_str().map(|name| (name, Command::from_name(name))) {
+                Some((_, Some(command))) => self.bindings.push((keys, command)),
+                Some((name, None)) => {
+                    errors.push(SchemaError::new(
+                        &path,
+                        &format!("unknown command `{name}`"),
+                    ));
+                }
+                None => errors.push(SchemaError::new(&path, "expected the name of a command")),
+            }
+        }
+        errors
+    }
+}
+
+/// the directory of the user's settings, `$XDG_CONFIG_HOME/<editor>` or `~/.config/<editor>`
+#[must_use]
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join(env!("CARGO_PKG_NAME")))
+}
+
+/// the settings files in the order they are applied: the system wide ones,
+/// the user's one and the one of the project in the current directory
+#[must_use]
+pub fn files() -> Vec<PathBuf> {
+    let system = env::var_os("XDG_CONFIG_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".into());
+    // the first directory is the most important one
+    let mut files: Vec<PathBuf> = env::split_paths(&system)
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")).join(CONFIG_FILE))
+        .collect();
+    files.reverse();
+    files.extend(config_dir().map(|dir| dir.join(CONFIG_FILE)));
+    if let Ok(dir) = env::current_dir() {
+        files.extend(project_file(&dir));
+    }
+    files
+}
+
+/// the nearest project settings file in `dir` or its parents
+fn project_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|file| file.is_file())
+}
+
+fn unknown_key(path: &str, known: &[&str]) -> SchemaError {
+    SchemaError::new(
+        path,
+        &format!("unknown key, expected one of {}", known.join(", ")),
+    )
+}
+
+fn as_table<'a>(value: &'a Value, path: &str) -> Result<&'a Table, SchemaError> {
+    value
+        .as_table()
+        .ok_or_else(|| SchemaError::new(path, "expected a table"))
+}
+
+fn as_integer<T>(value: &Value, path: &str, min: T, max: T) -> Result<T, SchemaError>
+where
+    T: TryFrom<i64> + PartialOrd + Copy + fmt::Display,
+{
+    value
+        .as_integer()
+        .and_then(|number| T::try_from(number).ok())
+        .filter(|number| *number >= min && *number <= max)
+        .ok_or_else(|| SchemaError::new(path, &format!("expected a number from {min} to {max}")))
+}
+
+/// a whole or fractional number of seconds
+fn as_seconds(value: &Value, path: &str) -> Result<Duration, SchemaError> {
+    let seconds = match value {
+        Value::Integer(seconds) => u64::try_from(*seconds).ok().map(Duration::from_secs),
+        Value::Float(seconds) => Duration::try_from_secs_f64(*seconds).ok(),
+        _ => None,
+    };
+    seconds.ok_or_else(|| SchemaError::new(path, "expected a number of seconds"))
+}
+
+/// one of the names in `choices`
+fn as_choice<T: Copy>(value: &Value, path: &str, choices: &[(&str, T)]) -> Result<T, SchemaError> {
+    let name = value.as_str().unwrap_or_default();
+    choices
+        .iter()
+        .find(|(choice, _)| *choice == name)
+        .map(|(_, value)| *value)
+        .ok_or_else(|| {
+            let names: Vec<&str> = choices.iter().map(|(choice, _)| *choice).collect();
+            SchemaError::new(path, &format!("expected one of {}", names.join(", ")))
+        })
+}
+
+#[cfg(test)]
+mod config_tests {
+    use std::{env, fs, process, time::Duration};
+
+    use termion::event::Key;
+
+    use super::{project_file, Config, LineNumbers, Profile, SoftWrap, PROJECT_FILE};
+    use crate::keymap::{Binding, Command};
+
+    /// the mistakes found in `source` as they are shown to the user
+    fn errors(config: &mut Config, source: &str) -> Vec<String> {
+        config
+            .merge(source)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn settings() {
+        let mut config = Config::default();
+        let source = r#"
+            tab_width = 8
+            soft_wrap = "words"
+            line_numbers = "relative"
+            autosave = 30
+            quit_times = 3
+            status_timeout = 2.5
+            osc52 = false
+            theme = "solarized-dark"
+            profile = "vim"
+
+            [keys]
+            ctrl-w = "close-window"
+            alt-s = "save"
+            "ctrl-k ctrl-c" = "copy"
+        "#;
+        assert!(errors(&mut config, source).is_empty());
+        assert_eq!(config.tab_width, 8);
+        assert_eq!(config.soft_wrap, SoftWrap::Words);
+        assert_eq!(config.line_numbers, LineNumbers::Relative);
+        assert_eq!(config.autosave, Some(Duration::from_secs(30)));
+        assert_eq!(config.quit_times, 3);
+        assert_eq!(config.status_timeout, Duration::from_millis(2500));
+        assert!(!config.osc52);
+        assert_eq!(config.theme, "solarized-dark");
+        assert_eq!(config.profile, Profile::Vim);
+        assert_eq!(
+            config.keymap.lookup(&[Key::Ctrl('w')]),
+            Binding::Command(Command::CloseWindow)
+        );
+        assert_eq!(
+            config.keymap.lookup(&[Key::Alt('s')]),
+            Binding::Command(Command::Save)
+        );
+        assert_eq!(
+            config.keymap.lookup(&[Key::Ctrl('s')]),
+            Binding::Command(Command::Save)
+        );
+        assert_eq!(config.keymap.lookup(&[Key::Ctrl('k')]), Binding::Prefix);
+        assert_eq!(
+            config.keymap.lookup(&[Key::Ctrl('k'), Key::Ctrl('c')]),
+            Binding::Command(Command::Copy)
+        );
+
+        // 0 turns autosave off again
+        assert!(errors(&mut config, "autosave = 0").is_empty());
+        assert_eq!(config.autosave, None);
+    }
+
+    #[test]
+    fn invalid_settings_are_reported() {
+        let mut config = Config::default();
+        let source = r#"
+            tab_width = 0
+            soft_wrap = true
+            line_numbers = "absolute"
+            tab_size = 2
+            theme = ""
+            keys = { ctrl-ww = "save", ctrl-w = "destroy" }
+            status_timeout = -1
+            osc52 = "yes"
+        "#;
+        assert_eq!(
+            errors(&mut config, source),
+            // in the order of the keys
+            [
+                "keys.ctrl-w: unknown command `destroy`",
+                "keys.ctrl-ww: not a key, expected names like ctrl-s, alt-left, f5 or esc separated by spaces",
+                "osc52: expected true or false",
+                "soft_wrap: expected one of off, anywhere, words",
+                "status_timeout: expected a number of seconds",
+                "tab_size: unknown key, expected one of tab_width, soft_wrap, line_numbers, theme, autosave, keys, profile, quit_times, status_timeout, osc52",
+                "tab_width: expected a number from 1 to 32",
+                "theme: expected the name of a theme",
+            ]
+        );
+        // the valid settings are applied anyway
+        assert_eq!(config.line_numbers, LineNumbers::Absolute);
+        assert_eq!(config.tab_width, 4);
+
+        assert_eq!(
+            errors(&mut config, "tab_width = 4\nsoft_wrap = \n"),
+            ["line 2: string values must be quoted, expected literal string"]
+        );
+    }
+
+    #[test]
+    fn set_one_setting() {
+        let mut config = Config::default();
+        config.set("tabwidth", "8").unwrap();
+        assert_eq!(config.tab_width, 8);
+        config.set("soft_wrap", "words").unwrap();
+        assert_eq!(config.soft_wrap, SoftWrap::Words);
+        config.set("theme", "\"light\"").unwrap();
+        assert_eq!(config.theme, "light");
+        config.set("status_timeout", " 0.5 ").unwrap();
+        assert_eq!(config.status_timeout, Duration::from_millis(500));
+        config.set("profile", "emacs").unwrap();
+        assert_eq!(
+            config.keymap.lookup(&[Key::Ctrl('a')]),
+            Binding::Command(Command::LineStart)
+        );
+
+        let error =
+            |config: &mut Config, name, value| config.set(name, value).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut config, "tab_width", "words"),
+            "tab_width: expected a number from 1 to 32"
+        );
+        assert_eq!(
+            error(&mut config, "wrap", "off"),
+            "wrap: unknown key, expected one of tab_width, soft_wrap, line_numbers, theme, autosave, keys, profile, quit_times, status_timeout, osc52"
+        );
+        assert_eq!(config.tab_width, 8);
+        assert_eq!(
+            Config::choices("line_numbers"),
+            ["off", "absolute", "relative"]
+        );
+        assert!(Config::choices("tab_width").is_empty());
+    }
+
+    #[test]
+    fn project_settings_override_user_settings() {
+        let dir = env::temp_dir().join(format!("config-test-{}", process::id()));
+        let nested = dir.join("src").join("bin");
+        fs::create_dir_all(&nested).unwrap();
+        let user = dir.join("config.toml");
+        fs::write(
+            &user,
+            "tab_width = 8\nline_numbers = \"absolute\"\nkeys = { ctrl-t = \"save\" }\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join(PROJECT_FILE),
+            "tab_width = 2\nquit_times = 300\nprofile = \"emacs\"\n",
+        )
+        .unwrap();
+
+        // the project file is found from a subdirectory
+        let project = project_file(&nested).unwrap();
+        assert_eq!(project, dir.join(PROJECT_FILE));
+        let missing = dir.join("missing.toml");
+        let (config, errors) = Config::load(&[missing, user, project.clone()]);
+        assert_eq!(
+            (config.tab_width, config.line_numbers),
+            (2, LineNumbers::Absolute)
+        );
+        // the keys of the user are put on top of the keys of the profile chosen later
+        assert_eq!(
+            config.keymap.lookup(&[Key::Ctrl('t')]),
+            Binding::Command(Command::Save)
+        );
+        assert_eq!(
+            config.keymap.lookup(&[Key::Ctrl('f')]),
+            Binding::Command(Command::MoveRight)
+        );
+        assert_eq!(
+            errors,
+            [format!(
+                "{}: quit_times: expected a number from 0 to 255",
+                project.display()
+            )]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,130 +1,369 @@
 use std::{
+    borrow::Cow,
     fs,
     io::{self, Error, Write},
+    time::SystemTime,
 };
 
-use crate::{FileType, Position, Row, SearchDirection};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    buffer::RowBuffer,
+    highlighting::State,
+    history::{Edit, History},
+    languages,
+    search::Query,
+    FileType, Position, Row, SearchDirection,
+};
+
+/// like Vim, modelines are looked for in this many rows at the start and the end of a file
+const MODELINE_ROWS: usize = 5;
 
 /// we need a structure to represent the document the user is editing
-/// and a vector of row should be included
-/// `dirty` used to represent if the file has been modified since opened
+/// and a buffer of rows should be included
+/// `history` records every edit so that they can be undone,
+/// it also tells whether the file has been modified since opened.
+/// rows are highlighted when they are about to be shown, not when the file is opened
 #[derive(Default)]
 pub struct Document {
-    rows: Vec<Row>,
+    rows: RowBuffer,
     pub filename: Option<String>,
-    dirty: bool,
+    history: History,
     filetype: FileType,
+    // the rows before this one are highlighted, the others are not yet
+    highlighted: usize,
+    // the matches of the search are highlighted as well
+    query: Option<Query>,
+    // the modification time of the file when we read or wrote it last time
+    disk_modified: Option<SystemTime>,
+    // the modification time of the last change made by another program which has been reported,
+    // the document is in conflict with its file until it is saved or read again
+    reported: Option<SystemTime>,
 }
 
 impl Document {
+    /// # Errors
+    ///
+    /// will return an error if the file can't be read
     pub fn open(filename: &str) -> Result<Self, io::Error> {
         let contents = fs::read_to_string(filename)?;
-        let filetype = FileType::from(filename);
-        let rows = contents
-            .lines()
-            .map(|line| {
-                let mut row = Row::from(line);
-                row.highlight(None, filetype.highlighting_opts());
-                row
-            })
-            .collect();
-        Ok(Self {
-            rows,
+        let mut document = Self {
+            rows: contents.lines().map(Row::from).collect(),
             filename: Some(filename.to_string()),
-            dirty: false,
-            filetype,
-        })
+            history: History::default(),
+            filetype: FileType::default(),
+            highlighted: 0,
+            query: None,
+            disk_modified: fs::metadata(filename).and_then(|m| m.modified()).ok(),
+            reported: None,
+        };
+        document.filetype = document.detect_filetype();
+        Ok(document)
+    }
+
+    /// find out the file type by the filename, the shebang on the first row
+    /// and the modelines in the first and last rows
+    fn detect_filetype(&self) -> FileType {
+        let len = self.rows.len();
+        let lines: Vec<Cow<str>> = (0..len.min(MODELINE_ROWS))
+            .chain(len.saturating_sub(MODELINE_ROWS).max(MODELINE_ROWS)..len)
+            .map(|y| self.rows[y].slice(0, self.rows[y].len()))
+            .collect();
+        let lines: Vec<&str> = lines.iter().map(AsRef::as_ref).collect();
+        languages::registry()
+            .detect(self.filename.as_deref(), &lines)
+            .cloned()
+            .unwrap_or_default()
     }
 
     pub fn insert(&mut self, at: &Position, c: char) {
-        if at.y > self.len() {
-            return;
+        self.insert_str(at, &c.to_string());
+    }
+
+    /// insert a string which may contain '\n' at `at`, return the position right after it
+    pub fn insert_str(&mut self, at: &Position, text: &str) -> Position {
+        if at.y > self.len() || text.is_empty() {
+            return at.clone();
         }
-        self.dirty = true;
-        if c == '\n' {
-            self.insert_newline(at);
-            return;
+        let (at, text) = match self.rows.last() {
+            // typing after the last row is the same as breaking the last row first
+            Some(last) if at.y == self.len() => {
+                let end = Position {
+                    x: last.len(),
+                    y: at.y.saturating_sub(1),
+                };
+                let text = if text == "\n" {
+                    text.to_string()
+                } else {
+                    format!("\n{text}")
+                };
+                (end, text)
+            }
+            _ => (at.clone(), text.to_string()),
+        };
+        let edit = Edit::Insert { at, text };
+        self.apply(&edit);
+        edit.end()
+    }
+
+    /// the text between two positions, rows are joined by '\n'
+    #[must_use]
+    pub fn text(&self, start: &Position, end: &Position) -> String {
+        let mut text = String::new();
+        for y in start.y..=end.y {
+            let Some(row) = self.rows.get(y) else {
+                break;
+            };
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { row.len() };
+            text.push_str(&row.slice(from, to));
+            if y != end.y && y.saturating_add(1) < self.len() {
+                text.push('\n');
+            }
         }
-        let opts = self.filetype.highlighting_opts();
-        if at.y == self.len() {
-            let mut row = Row::default();
-            row.insert(0, c);
-            row.highlight(None, opts);
-            self.rows.push(row);
-        } else {
-            let row = &mut self.rows[at.y];
-            row.insert(at.x, c);
-            row.highlight(None, opts);
+        text
+    }
+
+    /// remove the text between two positions and return it
+    pub fn remove(&mut self, start: &Position, end: &Position) -> String {
+        let text = self.text(start, end);
+        if !text.is_empty() {
+            self.apply(&Edit::Delete {
+                at: start.clone(),
+                text: text.clone(),
+            });
         }
+        text
     }
 
-    #[allow(clippy::integer_arithmetic)]
     pub fn delete(&mut self, at: &Position) {
         // if the cursor's y is greater than the number of rows
         // there is nothing to delete
-        let len = self.len();
-        if at.y >= len {
+        if at.y >= self.len() {
             return;
         }
+        #[allow(clippy::arithmetic_side_effects)]
+        let text = if at.x == self.rows[at.y].len() && at.y + 1 < self.len() {
+            "\n".to_string()
+        } else if let Some(text) = self.rows[at.y].text(at.x) {
+            text
+        } else {
+            return;
+        };
+        self.apply(&Edit::Delete {
+            at: at.clone(),
+            text,
+        });
+    }
 
-        self.dirty = true;
+    /// replace `count` graphemes from `at` with `text`
+    pub fn replace(&mut self, at: &Position, count: usize, text: &str) {
+        let rows = self.len();
+        let removed = self.delete_text(at, count);
+        self.history.record(
+            Edit::Delete {
+                at: at.clone(),
+                text: removed,
+            },
+            rows,
+        );
+        self.apply(&Edit::Insert {
+            at: at.clone(),
+            text: text.to_string(),
+        });
+    }
 
-        let opts = self.filetype.highlighting_opts();
+    /// the edits made before `commit_transaction` will be undone in one step
+    pub fn begin_transaction(&mut self) {
+        self.history.begin();
+    }
 
-        // if the cursor is at the end of line
-        // then we should delete the next row, and append it to current line
-        if at.x == self.rows[at.y].len() && at.y + 1 < len {
-            let next_row = self.rows.remove(at.y + 1);
-            let row = &mut self.rows[at.y];
-            row.append(&next_row);
-            row.highlight(None, opts);
-        } else {
-            let row = &mut self.rows[at.y];
-            row.delete(at.x);
-            row.highlight(None, opts);
+    pub fn commit_transaction(&mut self) {
+        self.history.commit();
+    }
+
+    /// undo the last edit step, return where the cursor was before the step:
+    /// at the start of the typed text, or after the text removed by BACKSPACE
+    pub fn undo(&mut self) -> Option<Position> {
+        let (edits, rows) = self.history.undo()?;
+        self.replay(&edits);
+        // the row added by typing into an empty document isn't removed by the edits
+        if rows == 0 && self.len() == 1 {
+            self.rows.remove(0);
+            self.highlighted = 0;
         }
+        // the inverse of the first edit of the step comes last
+        let (first, rest) = edits.split_last()?;
+        let backspaces = matches!(
+            (first, rest.last()),
+            (Edit::Insert { at, .. }, Some(Edit::Insert { at: next, .. }))
+                if next.y == at.y && next.x < at.x
+        );
+        Some(if backspaces {
+            first.end()
+        } else {
+            first.start()
+        })
     }
 
-    pub fn insert_newline(&mut self, at: &Position) {
-        if at.y > self.len() {
-            return;
+    /// redo the last undone edit step, return the position where the change ends
+    pub fn redo(&mut self) -> Option<Position> {
+        let edits = self.history.redo()?;
+        self.replay(&edits);
+        edits.last().map(|edit| match edit {
+            Edit::Insert { .. } => edit.end(),
+            Edit::Delete { .. } => edit.start(),
+        })
+    }
+
+    /// apply edits coming from the history without recording them again
+    fn replay(&mut self, edits: &[Edit]) {
+        for edit in edits {
+            match edit {
+                Edit::Insert { at, text } => {
+                    self.insert_text(at, text);
+                }
+                Edit::Delete { at, text } => {
+                    self.delete_text(at, text.graphemes(true).count());
+                }
+            }
         }
+    }
+
+    /// apply an edit and record it in the history
+    fn apply(&mut self, edit: &Edit) {
+        let rows = self.len();
+        self.replay(std::slice::from_ref(edit));
+        self.history.record(edit.clone(), rows);
+    }
 
-        if at.y == self.len() {
+    /// insert `text` at `at`, every '\n' in it will split the row
+    fn insert_text(&mut self, at: &Position, text: &str) {
+        if at.y >= self.len() {
             self.rows.push(Row::default());
-            return;
         }
+        let mut position = at.clone();
+        let mut lines = text.split('\n');
+        let first = lines.next().unwrap_or_default();
+        self.rows[position.y].insert_str(position.x, first);
+        position.x = position.x.saturating_add(first.graphemes(true).count());
+        for line in lines {
+            // the rest of current row will be moved to the new row
+            let mut new_row = self.rows[position.y].split(position.x);
+            new_row.insert_str(0, line);
+            position.y = position.y.saturating_add(1);
+            position.x = line.graphemes(true).count();
+            self.rows.insert(position.y, new_row);
+        }
+        // the highlighted rows after the edit are moved down with it
+        if at.y < self.highlighted {
+            let added = position.y.saturating_sub(at.y);
+            self.highlighted = self.highlighted.saturating_add(added);
+        }
+        self.highlight_rows(at.y, position.y);
+    }
 
-        // if we want to insert a newline at the middle of the row
-        // we should first split it at current cursor position
-        // and the last part become the next new row
-        #[allow(clippy::indexing_slicing)]
-        let mut new_row = self.rows[at.y].split(at.x);
+    /// remove `count` graphemes from `at` and return them, a line break is counted as one grapheme
+    fn delete_text(&mut self, at: &Position, count: usize) -> String {
+        let mut removed = String::new();
+        let mut remaining = count;
+        let mut joined: usize = 0;
+        while remaining > 0 && at.y < self.len() {
+            let row = &mut self.rows[at.y];
+            let available = row.len().saturating_sub(at.x);
+            if remaining <= available {
+                removed.push_str(&row.drain(at.x, at.x.saturating_add(remaining)));
+                break;
+            }
+            removed.push_str(&row.drain(at.x, row.len()));
+            remaining = remaining.saturating_sub(available);
+            let next = at.y.saturating_add(1);
+            if next >= self.len() {
+                break;
+            }
+            // remove the line break by joining the next row
+            let next_row = self.rows.remove(next);
+            let row = &mut self.rows[at.y];
+            row.append(next_row);
+            // the joined row has been highlighted with other settings, or not at all
+            if next >= self.highlighted {
+                row.forget_highlighting();
+            }
+            removed.push('\n');
+            remaining = remaining.saturating_sub(1);
+            joined = joined.saturating_add(1);
+        }
+        if at.y < self.highlighted {
+            self.highlighted = self
+                .highlighted
+                .saturating_sub(joined)
+                .max(at.y.saturating_add(1));
+        }
+        self.highlight_rows(at.y, at.y);
+        removed
+    }
+
+    /// highlight the rows from `start` to `end` after they are edited.
+    /// an edit may open or close a block comment, so we continue with the following rows
+    /// until the state at the end of a row is the same as before.
+    /// the rows which haven't been highlighted yet are left for `highlight_to`
+    fn highlight_rows(&mut self, start: usize, end: usize) {
         let opts = self.filetype.highlighting_opts();
-        self.rows[at.y].highlight(None, opts);
-        new_row.highlight(None, opts);
-        #[allow(clippy::integer_arithmetic)]
-        self.rows.insert(at.y + 1, new_row);
+        let mut state = start
+            .checked_sub(1)
+            .and_then(|index| self.rows.get(index))
+            .map_or(State::Normal, Row::hl_state);
+        let mut index = start;
+        while index < self.highlighted {
+            let Some(row) = self.rows.get_mut(index) else {
+                break;
+            };
+            let old_state = row.hl_state();
+            state = row.highlight_edits(self.query.as_ref(), opts, state);
+            if index >= end && state == old_state {
+                break;
+            }
+            index = index.saturating_add(1);
+        }
     }
 
+    /// # Errors
+    ///
+    /// will return an error if the file can't be created or written
     pub fn save(&mut self) -> Result<(), Error> {
         if let Some(filename) = &self.filename {
-            let mut file = fs::File::create(filename)?;
-            for row in &self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
-            }
-            self.filetype = FileType::from(filename.to_string());
-            self.dirty = false;
+            self.write_to(filename)?;
+            self.disk_modified = fs::metadata(filename).and_then(|m| m.modified()).ok();
+            self.reported = None;
+            self.history.mark_saved();
+            self.filetype = self.detect_filetype();
             self.highlight(None);
         }
         Ok(())
     }
 
+    /// write the rows to `filename`, the document still belongs to its own file
+    ///
+    /// # Errors
+    ///
+    /// will return an error if the file can't be created or written
+    pub fn write_to(&self, filename: &str) -> Result<(), Error> {
+        let mut file = fs::File::create(filename)?;
+        for row in self.rows.iter() {
+            writeln!(file, "{row}")?;
+        }
+        Ok(())
+    }
+
     /// find a segment equal to `query`.
     /// at and direction represent the position of start point and the direction of searching
     #[must_use]
-    pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
+    pub fn find(
+        &self,
+        query: &Query,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<Position> {
         if at.y >= self.rows.len() {
             return None;
         }
@@ -159,6 +398,68 @@ impl Document {
         None
     }
 
+    /// the start of the word before `at`, words on the rows above are reached across line breaks.
+    /// the start of the document if there is no word before `at`
+    #[must_use]
+    pub fn word_start_before(&self, at: &Position) -> Position {
+        let Position { mut x, mut y } = at.clone();
+        loop {
+            if let Some(x) = self.rows.get(y).and_then(|row| row.word_start_before(x)) {
+                return Position { x, y };
+            }
+            if y == 0 {
+                return Position::default();
+            }
+            y -= 1;
+            x = self.rows.get(y).map_or(0, Row::len);
+        }
+    }
+
+    /// the start of the word after the one at `at`, words on the rows below are reached across line breaks.
+    /// the end of the last row if there is no word after `at`
+    #[must_use]
+    pub fn word_start_after(&self, at: &Position) -> Position {
+        let Some(row) = self.rows.get(at.y) else {
+            return at.clone();
+        };
+        if let Some(x) = row.word_start_after(at.x) {
+            return Position { x, y: at.y };
+        }
+        for y in at.y.saturating_add(1)..self.rows.len() {
+            let row = &self.rows[y];
+            // the first word of the row, found back from its end
+            if let Some(x) = row
+                .word_end_after(0)
+                .and_then(|end| row.word_start_before(end))
+            {
+                return Position { x, y };
+            }
+        }
+        let y = self.rows.len().saturating_sub(1);
+        Position {
+            x: self.rows.get(y).map_or(0, Row::len),
+            y,
+        }
+    }
+
+    /// the end of the word after `at`, words on the rows below are reached across line breaks.
+    /// the end of the last row if there is no word after `at`
+    #[must_use]
+    pub fn word_end_after(&self, at: &Position) -> Position {
+        let Position { mut x, mut y } = at.clone();
+        while let Some(row) = self.rows.get(y) {
+            if let Some(x) = row.word_end_after(x) {
+                return Position { x, y };
+            }
+            if y.saturating_add(1) >= self.rows.len() {
+                return Position { x: row.len(), y };
+            }
+            y += 1;
+            x = 0;
+        }
+        at.clone()
+    }
+
     #[must_use]
     pub fn row(&self, index: usize) -> Option<&Row> {
         self.rows.get(index)
@@ -174,9 +475,41 @@ impl Document {
         self.rows.len()
     }
 
+    /// the modification time of the file, `None` if there is no file
+    fn modified_on_disk(&self) -> Option<SystemTime> {
+        let filename = self.filename.as_ref()?;
+        fs::metadata(filename).and_then(|m| m.modified()).ok()
+    }
+
+    /// whether the file has been modified by another program since we read or wrote it,
+    /// nothing is recorded so it can be asked any number of times
+    #[must_use]
+    pub fn is_stale(&self) -> bool {
+        self.modified_on_disk()
+            .is_some_and(|modified| Some(modified) != self.disk_modified)
+    }
+
+    /// whether the file has been modified by another program and the change hasn't been reported yet.
+    /// a change is reported only once, the document is in conflict from then on
+    pub fn changed_on_disk(&mut self) -> bool {
+        let modified = self.modified_on_disk();
+        if modified.is_none() || modified == self.disk_modified || modified == self.reported {
+            return false;
+        }
+        self.reported = modified;
+        true
+    }
+
+    /// whether a change made by another program has been reported
+    /// and the document hasn't been saved or read again since
+    #[must_use]
+    pub fn in_conflict(&self) -> bool {
+        self.reported.is_some()
+    }
+
     #[must_use]
     pub fn is_dirty(&self) -> bool {
-        self.dirty
+        self.history.is_modified()
     }
 
     #[must_use]
@@ -184,10 +517,281 @@ impl Document {
         self.filetype.name()
     }
 
-    pub fn highlight(&mut self, query: Option<&str>) {
+    /// highlight the rows again with the matches of `query`,
+    /// they are highlighted when they are shown
+    pub fn highlight(&mut self, query: Option<&Query>) {
+        self.query = query.cloned();
+        self.highlighted = 0;
+    }
+
+    /// make sure the rows before `end` are highlighted,
+    /// a row can't be highlighted before the rows above it
+    pub fn highlight_to(&mut self, end: usize) {
+        let end = end.min(self.len());
         let opts = self.filetype.highlighting_opts();
-        for row in &mut self.rows {
-            row.highlight(query, opts);
+        let mut state = self
+            .highlighted
+            .checked_sub(1)
+            .and_then(|index| self.rows.get(index))
+            .map_or(State::Normal, Row::hl_state);
+        for index in self.highlighted..end {
+            state = self.rows[index].highlight(self.query.as_ref(), opts, state);
         }
+        self.highlighted = self.highlighted.max(end);
+    }
+}
+
+#[cfg(test)]
+mod document_tests {
+    use std::{
+        env, fs, process,
+        time::{Duration, SystemTime},
+    };
+
+    use super::Document;
+    use crate::{highlighting::State, FileType, Position};
+
+    fn type_text(doc: &mut Document, at: &mut Position, text: &str) {
+        for c in text.chars() {
+            doc.insert(at, c);
+            if c == '\n' {
+                at.y += 1;
+                at.x = 0;
+            } else {
+                at.x += 1;
+            }
+        }
+    }
+
+    fn contents(doc: &Document) -> Vec<String> {
+        (0..doc.len())
+            .map(|i| doc.row(i).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn undo_typed_run() {
+        let mut doc = Document::default();
+        let mut at = Position::default();
+        type_text(&mut doc, &mut at, "hello");
+        assert!(doc.is_dirty());
+        assert_eq!(doc.undo(), Some(Position { x: 0, y: 0 }));
+        // the row added by the first character is gone too
+        assert!(doc.is_empty());
+        assert!(!doc.is_dirty());
+        assert_eq!(doc.redo(), Some(Position { x: 5, y: 0 }));
+        assert_eq!(contents(&doc), vec!["hello"]);
+    }
+
+    #[test]
+    fn undo_backspaces_and_deletes() {
+        let mut doc = Document::default();
+        let mut at = Position::default();
+        type_text(&mut doc, &mut at, "\nabcdef");
+        doc.history.commit();
+        // BACKSPACE twice from the end
+        doc.delete(&Position { x: 5, y: 1 });
+        doc.delete(&Position { x: 4, y: 1 });
+        assert_eq!(contents(&doc), vec!["", "abcd"]);
+        assert_eq!(doc.undo(), Some(Position { x: 6, y: 1 }));
+        assert_eq!(contents(&doc), vec!["", "abcdef"]);
+        // DELETE twice at the start of the row
+        doc.delete(&Position { x: 0, y: 1 });
+        doc.delete(&Position { x: 0, y: 1 });
+        assert_eq!(doc.undo(), Some(Position { x: 0, y: 1 }));
+        assert_eq!(contents(&doc), vec!["", "abcdef"]);
+        // the first row of an empty document is removed with the first character
+        while doc.undo().is_some() {}
+        assert!(doc.is_empty());
+        doc.redo();
+        assert_eq!(contents(&doc), vec!["", ""]);
+    }
+
+    #[test]
+    fn undo_newline_and_join() {
+        let mut doc = Document::default();
+        let mut at = Position::default();
+        type_text(&mut doc, &mut at, "ab");
+        doc.insert(&Position { x: 1, y: 0 }, '\n');
+        assert_eq!(contents(&doc), vec!["a", "b"]);
+        // delete at the end of the first row joins the rows
+        doc.delete(&Position { x: 1, y: 0 });
+        assert_eq!(contents(&doc), vec!["ab"]);
+        assert_eq!(doc.undo(), Some(Position { x: 1, y: 0 }));
+        assert_eq!(contents(&doc), vec!["a", "b"]);
+        assert_eq!(doc.undo(), Some(Position { x: 1, y: 0 }));
+        assert_eq!(contents(&doc), vec!["ab"]);
+    }
+
+    #[test]
+    fn undo_restores_clean_state() {
+        let mut doc = Document::default();
+        let mut at = Position::default();
+        type_text(&mut doc, &mut at, "ab");
+        doc.history.mark_saved();
+        doc.delete(&Position { x: 0, y: 0 });
+        assert!(doc.is_dirty());
+        doc.undo();
+        assert!(!doc.is_dirty());
+        assert_eq!(contents(&doc), vec!["ab"]);
+    }
+
+    #[test]
+    fn replace_in_one_step() {
+        let mut doc = Document::default();
+        let mut at = Position::default();
+        type_text(&mut doc, &mut at, "foo bar\nfoo");
+        doc.begin_transaction();
+        doc.replace(&Position { x: 0, y: 0 }, 3, "baz");
+        doc.replace(&Position { x: 0, y: 1 }, 3, "");
+        doc.commit_transaction();
+        assert_eq!(contents(&doc), vec!["baz bar", ""]);
+        assert_eq!(doc.undo(), Some(Position { x: 0, y: 0 }));
+        assert_eq!(contents(&doc), vec!["foo bar", "foo"]);
+        doc.redo();
+        assert_eq!(contents(&doc), vec!["baz bar", ""]);
+    }
+
+    #[test]
+    fn cut_and_paste_across_rows() {
+        let mut doc = Document::default();
+        let mut at = Position::default();
+        type_text(&mut doc, &mut at, "one\ntwo\nthree");
+        let start = Position { x: 1, y: 0 };
+        let end = Position { x: 2, y: 2 };
+        assert_eq!(doc.text(&start, &end), "ne\ntwo\nth");
+        assert_eq!(doc.remove(&start, &end), "ne\ntwo\nth");
+        assert_eq!(contents(&doc), vec!["oree"]);
+        let end = doc.insert_str(&Position { x: 4, y: 0 }, "ne\ntwo\nth");
+        assert_eq!(end, Position { x: 2, y: 2 });
+        assert_eq!(contents(&doc), vec!["oreene", "two", "th"]);
+        // pasting after the last row starts a new row
+        let end = doc.insert_str(&Position { x: 0, y: 3 }, "x\ny");
+        assert_eq!(end, Position { x: 1, y: 4 });
+        assert_eq!(contents(&doc), vec!["oreene", "two", "th", "x", "y"]);
+    }
+
+    #[test]
+    fn block_comment_state_follows_edits() {
+        let mut doc = Document {
+            filetype: FileType::from("a.rs"),
+            ..Document::default()
+        };
+        let mut at = Position::default();
+        type_text(&mut doc, &mut at, "a\nb\nc */\nd");
+        let states = |doc: &Document| -> Vec<State> {
+            (0..doc.len())
+                .map(|i| doc.row(i).unwrap().hl_state())
+                .collect()
+        };
+        doc.highlight_to(doc.len());
+        assert_eq!(states(&doc), vec![State::Normal; 4]);
+        // opening a comment in the first row changes the rows below
+        doc.insert_str(&Position { x: 0, y: 0 }, "/*");
+        assert_eq!(
+            states(&doc),
+            vec![
+                State::Comment(0),
+                State::Comment(0),
+                State::Normal,
+                State::Normal
+            ]
+        );
+        doc.undo();
+        assert_eq!(states(&doc), vec![State::Normal; 4]);
+
+        // the rows which haven't been shown are highlighted later
+        doc.highlight(None);
+        doc.highlight_to(1);
+        doc.insert_str(&Position { x: 0, y: 0 }, "/*");
+        assert_eq!(states(&doc)[1], State::Normal);
+        doc.insert_str(&Position { x: 1, y: 1 }, "\n");
+        doc.highlight_to(3);
+        assert_eq!(
+            states(&doc),
+            vec![
+                State::Comment(0),
+                State::Comment(0),
+                State::Comment(0),
+                State::Normal,
+                State::Normal
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_file_type_again_after_save_as() {
+        let dir = env::temp_dir().join(format!("document-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut doc = Document::default();
+        doc.insert_str(&Position::default(), "#!/bin/sh\necho 1");
+        assert_eq!(doc.file_type(), FileType::default().name());
+        doc.filename = Some(dir.join("script").display().to_string());
+        doc.save().unwrap();
+        assert_eq!(doc.file_type(), "Shell");
+
+        // the modeline wins over the suffix when the file is opened
+        let path = dir.join("notes.txt");
+        fs::write(&path, "x = 1\n# vim: ft=python").unwrap();
+        let doc = Document::open(&path.display().to_string()).unwrap();
+        assert_eq!(doc.file_type(), "Python");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn changed_on_disk_is_reported_once() {
+        let dir = env::temp_dir().join(format!("document-disk-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        fs::write(&path, "a").unwrap();
+        let mut doc = Document::open(&path.display().to_string()).unwrap();
+        assert!(!doc.changed_on_disk());
+
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert!(doc.is_stale());
+        // asking whether it is stale changes nothing
+        assert!(doc.is_stale());
+        assert!(!doc.in_conflict());
+        assert!(doc.changed_on_disk());
+        assert!(!doc.changed_on_disk());
+        assert!(doc.is_stale());
+        assert!(doc.in_conflict());
+        // our own writes are not changes
+        doc.save().unwrap();
+        assert!(!doc.changed_on_disk());
+        assert!(!doc.is_stale());
+        assert!(!doc.in_conflict());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn move_by_words() {
+        let mut doc = Document::default();
+        doc.insert_str(&Position::default(), "let foo_bar = 1;\n\n  héllo(x)");
+        let at = |x, y| Position { x, y };
+        assert_eq!(doc.word_end_after(&at(0, 0)), at(3, 0));
+        assert_eq!(doc.word_end_after(&at(3, 0)), at(11, 0));
+        assert_eq!(doc.word_end_after(&at(5, 0)), at(11, 0));
+        // the punctuation and the empty rows are skipped
+        assert_eq!(doc.word_end_after(&at(15, 0)), at(7, 2));
+        assert_eq!(doc.word_end_after(&at(7, 2)), at(9, 2));
+        assert_eq!(doc.word_end_after(&at(9, 2)), at(10, 2));
+
+        assert_eq!(doc.word_start_before(&at(10, 2)), at(8, 2));
+        assert_eq!(doc.word_start_before(&at(8, 2)), at(2, 2));
+        assert_eq!(doc.word_start_before(&at(2, 2)), at(14, 0));
+        assert_eq!(doc.word_start_before(&at(6, 0)), at(4, 0));
+        assert_eq!(doc.word_start_before(&at(2, 0)), at(0, 0));
+        // the row after the last one
+        assert_eq!(doc.word_start_before(&at(0, 3)), at(8, 2));
+
+        assert_eq!(doc.word_start_after(&at(0, 0)), at(4, 0));
+        assert_eq!(doc.word_start_after(&at(3, 0)), at(4, 0));
+        assert_eq!(doc.word_start_after(&at(5, 0)), at(14, 0));
+        assert_eq!(doc.word_start_after(&at(14, 0)), at(2, 2));
+        assert_eq!(doc.word_start_after(&at(2, 2)), at(8, 2));
+        assert_eq!(doc.word_start_after(&at(8, 2)), at(10, 2));
     }
 }
@@ -1,30 +1,97 @@
 use std::{
+    cmp::Ordering,
     env,
     error::Error,
-    io,
+    fs, io, mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
-use termion::{color, event::Key};
+use termion::{event::Key, style};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::{row, Document, Terminal};
+use crate::{
+    clipboard::Clipboard,
+    command_line::{self, History, Invocation, Line, Target, TARGET_FORMAT},
+    config::{self, Config, LineNumbers, Profile, SoftWrap},
+    event::{self, Event, EventLoop, Timer},
+    keymap::{key_names, Binding, Command},
+    languages, row,
+    search::{Query, SearchOptions},
+    theme::{self, Theme},
+    vim::{Action, Insert, Mode, Motion, Operator, Vim},
+    window::{self, Area, Direction, Layout, Split, View, Window},
+    Document, Terminal,
+};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+// how long the line moved to by `goto-line` is highlighted
+const HIGHLIGHT_TIME: Duration = Duration::from_secs(1);
+// shown once when another program changes the file of the current buffer
+const FILE_CHANGED: &str = "WARN: the file has been changed by another program";
 
-const QUIT_TIMES: u8 = 1;
-
-const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
-const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
-
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
+// positions are ordered by row first, then by column
+impl Ord for Position {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+
+impl PartialOrd for Position {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 struct StatusMessage {
     text: String,
-    time: Instant,
+    // `None` if the message stays until it is replaced, e.g. a prompt
+    shown: Option<Instant>,
+    // the column of the cursor when the message is a prompt the user is typing in
+    cursor: Option<usize>,
+}
+
+/// an open document with the place the user left it at
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    view: View,
+}
+
+impl From<Document> for Buffer {
+    fn from(document: Document) -> Self {
+        Self {
+            document,
+            ..Self::default()
+        }
+    }
+}
+
+/// a window on the screen with what it shows
+struct Pane<'a> {
+    document: &'a Document,
+    view: &'a View,
+    area: Area,
+    buffer: usize,
+    focused: bool,
+}
+
+/// what the command line adds to a prompt: the lines entered before and the completions of the input
+struct Recall<'a> {
+    history: &'a mut History,
+    complete: &'a dyn Fn(&str) -> Vec<String>,
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -37,9 +104,31 @@ impl StatusMessage {
     fn from(text: String) -> Self {
         Self {
             text,
-            time: Instant::now(),
+            shown: Some(Instant::now()),
+            cursor: None,
+        }
+    }
+
+    fn sticky(text: String) -> Self {
+        Self {
+            text,
+            shown: None,
+            cursor: None,
+        }
+    }
+
+    fn prompt(text: String, cursor: usize) -> Self {
+        Self {
+            text,
+            shown: None,
+            cursor: Some(cursor),
         }
     }
+
+    /// the message disappears `timeout` after it is shown
+    fn is_visible(&self, timeout: Duration) -> bool {
+        self.shown.is_none_or(|shown| shown.elapsed() < timeout)
+    }
 }
 
 impl From<&str> for StatusMessage {
@@ -51,185 +140,1115 @@ impl From<&str> for StatusMessage {
 pub struct Editor {
     stop: bool,
     terminal: Terminal,
-    // cusor position
-    position: Position,
+    events: EventLoop,
+    // the files of the buffers, checked for changes made by other programs by the watching job
+    watched_files: Arc<Mutex<Vec<PathBuf>>>,
+    // set when the editor stops, so does the watching job
+    stop_watching: Arc<AtomicBool>,
     document: Document,
-    // keep track of what row of the file the user is currently scrolled to
-    offset: Position,
+    // the place in `document` shown by the focused window
+    view: View,
+    // every open document, the one being edited is moved to `document` and `view`,
+    // its slot is empty until the user switches to another buffer
+    buffers: Vec<Buffer>,
+    current: usize,
+    // like buffers, the view of the focused window is moved to `view`
+    windows: Vec<Window>,
+    layout: Layout,
+    focus: usize,
     status_message: StatusMessage,
+    // regex, case and whole word switches are kept for the next search
+    search_options: SearchOptions,
+    // the other end of the selection, the selection is empty if it's `None`
+    anchor: Option<Position>,
+    // the anchor set by CTRL-SPACE won't be cleared by moving the cursor
+    mark_set: bool,
+    clipboard: Clipboard,
+    // the settings read at startup, line numbers, soft wrap and the theme can be switched by the user
+    config: Config,
+    // every theme the user can switch to, the one in use is `themes[theme]`
+    themes: Vec<Theme>,
+    theme: usize,
+    // the modes of the Vim profile, `None` unless the profile is chosen in the settings
+    vim: Option<Vim>,
+    // the command run by the last keys, the kills made one after another are joined
+    last_command: Option<Command>,
+    // the text pasted by the last yank, which an older text can replace
+    yanked: Option<(Position, Position)>,
+    // the lines run in the command line
+    command_history: History,
+    // the buffer and the row highlighted after going to a line, until the time it fades
+    highlighted: Option<(usize, usize, Instant)>,
     // confirmation of quit when the user presses CTRL-Q and there are some changes have not been saved
     quit_times: u8,
 }
 
 impl Editor {
+    /// open the files given on the command line
+    ///
+    /// # Panics
+    ///
+    /// will panic if the terminal can't be put into raw mode
+    // it takes over the terminal, so it is not a default value
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let args: Vec<String> = env::args().collect();
-        let mut init_status = String::from("HELP: CTRL-Q quit | CTRL-S save | CTRL-F search");
-        let document = if let Some(filename) = args.get(1) {
+        let (config, config_errors) = Config::load(&config::files());
+        let mut init_status = String::from(match config.profile {
+            Profile::Default | Profile::Vim => "HELP: CTRL-Q quit | CTRL-S save | CTRL-O open | ALT-X command | CTRL-G go to line | ALT-N/P/B next/previous/pick buffer | CTRL-F search | CTRL-R replace | CTRL-Z undo | CTRL-Y redo | SHIFT-arrows select | CTRL-X/C/V cut/copy/paste | CTRL-L line numbers | ALT-Z soft wrap | ALT-T theme | ALT-- / ALT-| split | ALT-arrows switch window | ALT-Q close window",
+            Profile::Emacs => "HELP: C-x C-c quit | C-x C-s save | C-x C-f open | M-x command | M-g g go to line | C-x b pick buffer | C-s/C-r search | M-% replace | C-x u undo | C-SPC mark | C-w/M-w cut/copy | C-k kill line | C-y/M-y yank | C-x 2/3/0 split/close window",
+        });
+        // a broken language definition is left out, tell the user why their file isn't highlighted
+        if let Some(error) = languages::registry().errors().first() {
+            init_status = format!("ERR: Invalid language definition {error}");
+        }
+        // an invalid setting keeps its default
+        if let Some(error) = config_errors.first() {
+            init_status = format!("ERR: Invalid setting {error}");
+        }
+        let (themes, errors) = theme::load(theme::user_dir().as_deref());
+        if let Some(error) = errors.first() {
+            init_status = format!("ERR: Invalid theme {error}");
+        }
+        let theme = themes.iter().position(|theme| theme.name() == config.theme);
+        if theme.is_none() {
+            init_status = format!("ERR: Unknown theme {}", config.theme);
+        }
+        // every file is opened in its own buffer
+        let mut documents = Vec::new();
+        for filename in args.iter().skip(1) {
             if let Ok(doc) = Document::open(filename) {
-                doc
+                documents.push(doc);
             } else {
                 init_status = format!("ERR: Cannot open file: {filename}");
-                Document::default()
             }
-        } else {
-            Document::default()
-        };
+        }
+        let mut documents = documents.into_iter();
+        let document = documents.next().unwrap_or_default();
+        let events = EventLoop::new();
+        let terminal = Terminal::new(&events).expect("can't create a terminal");
+        let mut editor = Self::with(terminal, events, document, StatusMessage::from(init_status));
+        editor.buffers.extend(documents.map(Buffer::from));
+        editor.quit_times = config.quit_times;
+        editor.clipboard.osc52 = config.osc52;
+        editor.config = config;
+        editor.themes = themes;
+        editor.theme = theme.unwrap_or_default();
+        editor.vim = (editor.config.profile == Profile::Vim).then(Vim::default);
+        editor.schedule_autosave();
+        editor.watch_files();
+        let watched_files = Arc::clone(&editor.watched_files);
+        let stop_watching = Arc::clone(&editor.stop_watching);
+        editor.events.spawn(move |sender| {
+            event::watch_files(&watched_files, &stop_watching, &sender);
+        });
+        editor
+    }
+
+    fn with(
+        terminal: Terminal,
+        events: EventLoop,
+        document: Document,
+        status_message: StatusMessage,
+    ) -> Self {
+        let config = Config::default();
         Self {
             stop: false,
-            terminal: Terminal::new().expect("can't create a terminal"),
-            position: Position::default(),
+            terminal,
+            events,
+            watched_files: Arc::new(Mutex::new(Vec::new())),
+            stop_watching: Arc::new(AtomicBool::new(false)),
             document,
-            offset: Position::default(),
-            status_message: StatusMessage::from(init_status),
-            quit_times: QUIT_TIMES,
+            view: View::default(),
+            buffers: vec![Buffer::default()],
+            current: 0,
+            windows: vec![Window::default()],
+            layout: Layout::Window(0),
+            focus: 0,
+            status_message,
+            search_options: SearchOptions::default(),
+            anchor: None,
+            mark_set: false,
+            clipboard: Clipboard::new(config.osc52),
+            quit_times: config.quit_times,
+            config,
+            themes: theme::load(None).0,
+            theme: 0,
+            vim: None,
+            last_command: None,
+            yanked: None,
+            command_history: History::default(),
+            highlighted: None,
         }
     }
 
     pub fn run(&mut self) {
         loop {
             if let Err(e) = self.refresh_screen() {
-                die(Box::new(e));
+                die(&e);
             }
 
             if self.stop {
+                self.stop_watching.store(true, atomic::Ordering::Relaxed);
                 break;
             }
 
             if let Err(e) = self.process_key() {
-                die(Box::new(e));
+                die(&e);
             }
         }
     }
 
-    pub fn draw_row(&self, row: &row::Row) {
-        let width = self.terminal.width() as usize;
-        let start = self.offset.x;
-        let end = self.offset.x.saturating_add(width);
-        let row = row.render(start, end);
-        println!("{row}\r");
+    /// draw the graphemes of `row` in `line`, one visual line of it.
+    /// return the number of columns taken
+    fn draw_row(
+        &self,
+        pane: &Pane,
+        row: &row::Row,
+        line: &Range<usize>,
+        selection: Option<&Range<usize>>,
+    ) -> usize {
+        let width = self.text_width_of(pane);
+        let (start, end) = if self.config.soft_wrap == SoftWrap::Off {
+            let offset = pane.view.offset.x;
+            (offset, offset.saturating_add(width))
+        } else {
+            let start = row.column(line.start, self.config.tab_width);
+            let mut end = row.column(line.end, self.config.tab_width);
+            // the last line has room for the line break
+            if line.end == row.len() {
+                end = end.saturating_add(1);
+            }
+            (start, end.min(start.saturating_add(width)))
+        };
+        print!(
+            "{}",
+            row.render(start, end, selection, self.config.tab_width, self.theme())
+        );
+        let row_end = row.column(row.len(), self.config.tab_width);
+        let line_break = row_end >= start
+            && row_end < end
+            && selection.is_some_and(|range| range.contains(&row.len()));
+        row_end
+            .saturating_add(usize::from(line_break))
+            .min(end)
+            .saturating_sub(start)
     }
 
-    fn draw_rows(&self) {
-        let height = self.terminal.height();
+    /// draw a window with its status bar, and the separator on its right if there is a window beside
+    fn draw_pane(&self, pane: &Pane) {
+        let Area {
+            x,
+            y,
+            width,
+            height,
+        } = pane.area;
+        let text_height = height.saturating_sub(1);
+        let mut lines = self.screen_lines_of(pane).into_iter();
 
-        for terminal_row in 0..height {
-            Terminal::clear_current_line();
+        for line_on_screen in 0..text_height {
+            Terminal::cursor_position(&Position {
+                x,
+                y: y.saturating_add(line_on_screen),
+            });
             // if there are some contents in current row, render it
             // if not, just render the ~ or welcome message
-            if let Some(row) = self
-                .document
-                .row(self.offset.y.saturating_add(terminal_row as usize))
-            {
-                self.draw_row(row);
-            } else if terminal_row == height / 3 && self.document.is_empty() {
-                self.draw_welcome_message();
+            let drawn = if let Some((row_y, line)) = lines.next() {
+                let Some(row) = pane.document.row(row_y) else {
+                    continue;
+                };
+                let highlighted = self.highlighted_row(pane) == Some(row_y);
+                let selection = if highlighted {
+                    // the whole row looks selected
+                    Some(0..row.len())
+                } else if pane.focused {
+                    self.selection_in_row(row_y, row)
+                } else {
+                    None
+                };
+                self.draw_gutter(pane, row_y, line.start > 0);
+                let drawn = self.draw_row(pane, row, &line, selection.as_ref());
+                let drawn = drawn.saturating_add(self.gutter_width_of(pane));
+                if highlighted {
+                    let rest = " ".repeat(width.saturating_sub(drawn));
+                    print!("{}{rest}{}", self.theme().selection, style::Reset);
+                    continue;
+                }
+                drawn
+            } else if line_on_screen == text_height / 3 && pane.document.is_empty() {
+                let message = welcome_message(width);
+                print!("{message}");
+                message.width()
             } else {
-                println!("~\r");
+                print!("~");
+                1
+            };
+            // clear the rest of the line, the window beside is left alone
+            print!("{}", " ".repeat(width.saturating_sub(drawn)));
+        }
+
+        Terminal::cursor_position(&Position {
+            x,
+            y: y.saturating_add(text_height),
+        });
+        self.draw_status_bar(pane);
+        if x.saturating_add(width) < self.terminal.width() as usize {
+            for line_on_screen in 0..height {
+                Terminal::cursor_position(&Position {
+                    x: x.saturating_add(width),
+                    y: y.saturating_add(line_on_screen),
+                });
+                print!("│");
             }
         }
     }
 
-    fn draw_welcome_message(&self) {
-        let mut msg = format!("Editor --version {VERSION}\r");
-        let width = self.terminal.width() as usize;
-        let len = msg.len();
+    /// the number of columns taken by the line numbers and the space after them
+    fn gutter_width_of(&self, pane: &Pane) -> usize {
+        if self.config.line_numbers == LineNumbers::Off {
+            return 0;
+        }
+        pane.document
+            .len()
+            .max(1)
+            .to_string()
+            .len()
+            .saturating_add(1)
+    }
 
-        // center the welcome message
-        let padding = width.saturating_sub(len) / 2;
-        let spaces = " ".repeat(padding.saturating_sub(1));
-        msg = format!("~{spaces}{msg}");
+    fn gutter_width(&self) -> usize {
+        self.gutter_width_of(&self.focused_pane())
+    }
 
-        // avoid our message being cutoff
-        msg.truncate(width);
-        println!("{msg}\r");
+    /// the number of columns left for the text
+    fn text_width_of(&self, pane: &Pane) -> usize {
+        pane.area.width.saturating_sub(self.gutter_width_of(pane))
     }
 
-    fn process_key(&mut self) -> Result<(), io::Error> {
-        let key = Terminal::read_key()?;
-        match key {
-            Key::Ctrl('q') => {
-                if self.quit_times > 0 && self.document.is_dirty() {
-                    self.status_message = StatusMessage::from(
-                        "WARNING! File has unsaved changes. Press CTRL-Q again to quit."
-                            .to_string(),
-                    );
-                    self.quit_times -= 1;
-                    return Ok(());
-                }
-                self.stop = true;
-            }
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('f') => self.search(),
-            Key::Char(c) => {
-                self.document.insert(&self.position, c);
-                self.move_cursor(Key::Right);
-            }
-            Key::Delete => self.document.delete(&self.position),
-            Key::Backspace => {
-                if self.position.x > 0 || self.position.y > 0 {
-                    self.move_cursor(Key::Left);
-                    self.document.delete(&self.position);
-                }
-            }
-            Key::Up
-            | Key::Down
-            | Key::Left
-            | Key::Right
-            | Key::PageUp
-            | Key::PageDown
-            | Key::Home
-            | Key::End => {
-                self.move_cursor(key);
+    fn text_width(&self) -> usize {
+        self.text_width_of(&self.focused_pane())
+    }
+
+    /// the number of lines of text in the focused window, the last line is its status bar
+    fn text_height(&self) -> usize {
+        self.area().height.saturating_sub(1)
+    }
+
+    /// the line number of row `y`, padded to the width of the gutter
+    fn line_number(&self, pane: &Pane, y: usize) -> String {
+        let cursor = pane.view.position.y;
+        let number = match self.config.line_numbers {
+            LineNumbers::Off => return String::new(),
+            LineNumbers::Relative if y != cursor => y.abs_diff(cursor),
+            LineNumbers::Absolute | LineNumbers::Relative => y.saturating_add(1),
+        };
+        let width = self.gutter_width_of(pane).saturating_sub(1);
+        format!("{number:>width$} ")
+    }
+
+    /// the continued visual lines of a wrapped row have no number
+    fn draw_gutter(&self, pane: &Pane, y: usize, continued: bool) {
+        if self.config.line_numbers == LineNumbers::Off {
+            return;
+        }
+        if continued {
+            print!("{}", " ".repeat(self.gutter_width_of(pane)));
+            return;
+        }
+        Terminal::set_style(&self.theme().gutter);
+        print!("{}", self.line_number(pane, y));
+        Terminal::reset_style();
+    }
+
+    fn theme(&self) -> &Theme {
+        &self.themes[self.theme]
+    }
+
+    /// use the next theme, in the order of their names with the default one first
+    fn cycle_theme(&mut self) {
+        self.theme = self.theme.saturating_add(1) % self.themes.len();
+        self.config.theme = self.theme().name().to_string();
+        self.status_message = StatusMessage::from(format!("Theme: {}", self.config.theme));
+    }
+
+    fn toggle_soft_wrap(&mut self) {
+        self.config.soft_wrap = match self.config.soft_wrap {
+            SoftWrap::Off => SoftWrap::Anywhere,
+            SoftWrap::Anywhere => SoftWrap::Words,
+            SoftWrap::Words => SoftWrap::Off,
+        };
+        let mode = match self.config.soft_wrap {
+            SoftWrap::Off => "off",
+            SoftWrap::Anywhere => "anywhere",
+            SoftWrap::Words => "at words",
+        };
+        self.status_message = StatusMessage::from(format!("Soft wrap: {mode}"));
+    }
+
+    /// the visual lines of row `y`, as ranges of graphemes. a row is one line unless soft wrap is on
+    #[allow(clippy::single_range_in_vec_init)]
+    fn visual_lines_of(&self, pane: &Pane, y: usize) -> Vec<Range<usize>> {
+        let Some(row) = pane.document.row(y) else {
+            return vec![0..0];
+        };
+        match self.config.soft_wrap {
+            SoftWrap::Off => vec![0..row.len()],
+            SoftWrap::Anywhere | SoftWrap::Words => row.wrap(
+                self.text_width_of(pane),
+                self.config.tab_width,
+                self.config.soft_wrap == SoftWrap::Words,
+            ),
+        }
+    }
+
+    fn visual_lines(&self, y: usize) -> Vec<Range<usize>> {
+        self.visual_lines_of(&self.focused_pane(), y)
+    }
+
+    /// the rows and their visual lines shown in a window, from the top
+    fn screen_lines_of(&self, pane: &Pane) -> Vec<(usize, Range<usize>)> {
+        let height = pane.area.height.saturating_sub(1);
+        let mut lines = Vec::new();
+        let mut skip = pane.view.top_line;
+        for y in pane.view.offset.y..pane.document.len() {
+            for line in self.visual_lines_of(pane, y).into_iter().skip(skip) {
+                if lines.len() >= height {
+                    return lines;
+                }
+                lines.push((y, line));
             }
-            _ => {
-                println!("{key:?}\r");
+            skip = 0;
+        }
+        lines
+    }
+
+    /// wait for the next key.
+    /// the screen is repainted meanwhile when the terminal is resized,
+    /// the status message expires or the file is changed by another program
+    fn read_key(&mut self) -> Result<Key, io::Error> {
+        loop {
+            // a timeout too long to count never expires
+            let expires = self
+                .status_message
+                .shown
+                .and_then(|shown| shown.checked_add(self.config.status_timeout));
+            if let Some(expires) = expires {
+                if expires > Instant::now() {
+                    self.events.schedule(Timer::StatusMessage, expires);
+                }
+            }
+            match self.events.next()? {
+                Event::Key(key) => return Ok(key),
+                Event::Resize => {
+                    if self.terminal.update_size()? {
+                        // keep the cursor on the screen
+                        self.scroll();
+                        Terminal::clear_screen();
+                    }
+                }
+                Event::Timer(Timer::StatusMessage | Timer::Highlight) => (),
+                Event::Timer(Timer::Autosave) => {
+                    self.autosave();
+                    self.schedule_autosave();
+                }
+                Event::FileChanged(path) => self.file_changed(&path),
             }
+            self.refresh_screen()?;
+        }
+    }
+
+    /// read keys until they are bound to a command or can't start a bound sequence,
+    /// return them with their command. the keys of an unfinished sequence are shown meanwhile
+    fn read_command(&mut self) -> Result<(Vec<Key>, Option<Command>), io::Error> {
+        let mut keys = Vec::new();
+        // the message replaced by the pending keys
+        let mut hidden = None;
+        let command = loop {
+            keys.push(self.read_key()?);
+            match self.config.keymap.lookup(&keys) {
+                Binding::Command(command) => break Some(command),
+                Binding::Unbound => break None,
+                Binding::Prefix => {
+                    let pending = StatusMessage::sticky(format!("{} -", key_names(&keys)));
+                    let message = mem::replace(&mut self.status_message, pending);
+                    hidden.get_or_insert(message);
+                    self.refresh_screen()?;
+                }
+            }
+        };
+        if let Some(message) = hidden {
+            self.status_message = message;
+        }
+        Ok((keys, command))
+    }
+
+    fn process_key(&mut self) -> Result<(), io::Error> {
+        let (keys, command) = self.read_command()?;
+        let quit_times = self.quit_times;
+        if command == Some(Command::Quit) {
+            if !self.quit() {
+                return Ok(());
+            }
+        } else {
+            self.run_keys(&keys, command);
         }
         self.scroll();
-        // if this code can be executed, that means user doesn't choose to quit
-        if self.quit_times < QUIT_TIMES {
-            self.quit_times = QUIT_TIMES;
+        // if this code can be executed, that means user doesn't choose to quit,
+        // unless `q` has been run in the command line
+        if self.quit_times == quit_times && self.quit_times < self.config.quit_times {
+            self.quit_times = self.config.quit_times;
             self.status_message = "".into();
         }
         Ok(())
     }
 
+    /// do what `keys` mean, `command` is the command they are bound to
+    fn run_keys(&mut self, keys: &[Key], command: Option<Command>) {
+        if !self.run_vim_keys(keys, command) {
+            match (command, keys) {
+                (Some(command), _) => self.run_command(command),
+                (None, [Key::Char(c)]) => self.insert_char(*c),
+                (None, _) => {
+                    self.status_message =
+                        StatusMessage::from(format!("{} is not bound", key_names(keys)));
+                }
+            }
+        }
+        self.last_command = command;
+        // like Vim, the cursor stays on the text outside of insert mode
+        if self
+            .vim
+            .as_ref()
+            .is_some_and(|vim| vim.mode() != Mode::Insert)
+        {
+            let y = self
+                .view
+                .position
+                .y
+                .min(self.document.len().saturating_sub(1));
+            let x = self.view.position.x.min(self.row_len(y).saturating_sub(1));
+            self.view.position = Position { x, y };
+        }
+    }
+
+    /// the keys typed in the Vim profile, return false for the keys it leaves to the keymap.
+    /// the text typed in insert mode is recorded, so that `.` can type it again
+    fn run_vim_keys(&mut self, keys: &[Key], command: Option<Command>) -> bool {
+        let Some(vim) = &mut self.vim else {
+            return false;
+        };
+        match (vim.mode(), keys) {
+            (Mode::Insert, [Key::Esc]) => {
+                vim.escape();
+                // the cursor goes back onto the last typed grapheme
+                self.view.position.x = self.view.position.x.saturating_sub(1);
+            }
+            (Mode::Insert, _) => {
+                vim.record((keys.to_vec(), command));
+                return false;
+            }
+            (_, [Key::Esc]) => {
+                vim.escape();
+                self.clear_selection();
+            }
+            (_, [Key::Char(c)]) => {
+                if let Some(action) = vim.key(*c) {
+                    self.run_vim(action);
+                }
+            }
+            (_, [Key::Ctrl('r')]) => self.run_command(Command::Redo),
+            _ => return false,
+        }
+        true
+    }
+
+    fn run_vim(&mut self, action: Action) {
+        match action {
+            Action::Move(motion, count) => self.view.position = self.vim_target(motion, count),
+            Action::Operate(operator, None, count) => {
+                let first = self.view.position.y;
+                let last = first.saturating_add(count.unwrap_or(1).saturating_sub(1));
+                self.operate_on_lines(operator, first, last);
+            }
+            Action::Operate(operator, Some(motion), count) => {
+                self.operate(operator, motion, count);
+            }
+            Action::OperateSelection(operator) => {
+                let Some(anchor) = self.anchor.clone() else {
+                    return;
+                };
+                let cursor = self.view.position.clone();
+                let (start, end) = if anchor < cursor {
+                    (anchor, cursor)
+                } else {
+                    (cursor, anchor)
+                };
+                // visual mode has been left, so the grapheme under the cursor is selected here
+                self.anchor = Some(start.clone());
+                self.view.position = self.next_position(&end);
+                if operator == Operator::Yank {
+                    self.copy();
+                } else {
+                    self.cut();
+                }
+                self.view.position = start;
+            }
+            Action::Insert(insert) => self.start_insert(insert),
+            Action::Paste { before, count } => self.paste_register(before, count),
+            Action::Undo(count) => {
+                for _ in 0..count {
+                    let Some(position) = self.document.undo() else {
+                        self.status_message = "Already at oldest change".into();
+                        break;
+                    };
+                    self.view.position = position;
+                }
+            }
+            Action::Repeat(count) => {
+                let change = self.vim.as_ref().map(Vim::last_change).unwrap_or_default();
+                for _ in 0..count {
+                    for (keys, command) in &change {
+                        self.run_keys(keys, *command);
+                    }
+                }
+            }
+            Action::CommandLine => self.command_line(),
+            Action::Visual => {
+                if self
+                    .vim
+                    .as_ref()
+                    .is_some_and(|vim| vim.mode() == Mode::Visual)
+                {
+                    self.anchor = Some(self.view.position.clone());
+                    self.mark_set = true;
+                } else {
+                    self.clear_selection();
+                }
+            }
+        }
+    }
+
+    /// where `motion` moves the cursor, `count` times
+    fn vim_target(&mut self, motion: Motion, count: Option<usize>) -> Position {
+        let times = count.unwrap_or(1);
+        let Position { x, y } = self.view.position.clone();
+        let last_row = self.document.len().saturating_sub(1);
+        let next = |document: &Document, position: &Position| match motion {
+            Motion::WordStart => document.word_start_after(position),
+            Motion::WordBack => document.word_start_before(position),
+            // from the grapheme after the cursor to the last grapheme of the word
+            _ => {
+                let after = Position {
+                    x: position.x.saturating_add(1),
+                    y: position.y,
+                };
+                let end = document.word_end_after(&after);
+                Position {
+                    x: end.x.saturating_sub(1),
+                    y: end.y,
+                }
+            }
+        };
+        match motion {
+            Motion::Left => Position {
+                x: x.saturating_sub(times),
+                y,
+            },
+            Motion::Right => Position {
+                x: x.saturating_add(times).min(self.row_len(y)),
+                y,
+            },
+            Motion::Up | Motion::Down => {
+                let old = self.view.position.clone();
+                let key = if motion == Motion::Up {
+                    Key::Up
+                } else {
+                    Key::Down
+                };
+                for _ in 0..times.min(self.document.len()) {
+                    self.move_cursor(key);
+                }
+                let mut target = mem::replace(&mut self.view.position, old);
+                target.y = target.y.min(last_row);
+                target
+            }
+            Motion::WordStart | Motion::WordBack | Motion::WordEnd => {
+                let mut position = self.view.position.clone();
+                for _ in 0..times {
+                    let moved = next(&self.document, &position);
+                    if moved == position {
+                        break;
+                    }
+                    position = moved;
+                }
+                position
+            }
+            Motion::LineStart => Position { x: 0, y },
+            Motion::LineEnd => Position {
+                x: self.row_len(y).saturating_sub(1),
+                y,
+            },
+            Motion::FirstLine | Motion::LastLine => {
+                let default = if motion == Motion::FirstLine {
+                    0
+                } else {
+                    last_row
+                };
+                Position {
+                    x: 0,
+                    y: count
+                        .map_or(default, |line| line.saturating_sub(1))
+                        .min(last_row),
+                }
+            }
+        }
+    }
+
+    /// delete, change or yank the text from the cursor to where `motion` moves it
+    fn operate(&mut self, operator: Operator, motion: Motion, count: Option<usize>) {
+        let cursor = self.view.position.clone();
+        // like Vim, `cw` changes to the end of the word and leaves the space after it
+        let motion = if operator == Operator::Change && motion == Motion::WordStart {
+            Motion::WordEnd
+        } else {
+            motion
+        };
+        let mut target = self.vim_target(motion, count);
+        if motion.is_linewise() {
+            let (first, last) = (cursor.y.min(target.y), cursor.y.max(target.y));
+            self.operate_on_lines(operator, first, last);
+            return;
+        }
+        // `dw` on the last word of a row stops at the end of the row
+        if motion == Motion::WordStart && target.y > cursor.y {
+            target = Position {
+                x: self.row_len(cursor.y),
+                y: cursor.y,
+            };
+        }
+        let (start, mut end) = if target < cursor {
+            (target, cursor)
+        } else {
+            (cursor, target)
+        };
+        if motion.is_inclusive() {
+            end.x = end.x.saturating_add(1).min(self.row_len(end.y));
+        }
+        let text = if operator == Operator::Yank {
+            self.document.text(&start, &end)
+        } else {
+            self.document.remove(&start, &end)
+        };
+        if !text.is_empty() {
+            self.clipboard.copy(text);
+        }
+        self.view.position = start;
+    }
+
+    /// delete, change or yank the rows from `first` to `last`.
+    /// the text is copied with a line break at its end, so that it is pasted as whole lines
+    fn operate_on_lines(&mut self, operator: Operator, first: usize, last: usize) {
+        let len = self.document.len();
+        if first >= len {
+            return;
+        }
+        let last = last.min(len.saturating_sub(1));
+        let start = Position { x: 0, y: first };
+        let end = Position {
+            x: self.row_len(last),
+            y: last,
+        };
+        self.clipboard
+            .copy(format!("{}\n", self.document.text(&start, &end)));
+        match operator {
+            Operator::Yank => {
+                if self.view.position.y != first {
+                    self.view.position = start;
+                }
+            }
+            // the line break after the rows goes with them, or the one before the last row
+            Operator::Delete if last.saturating_add(1) < len => {
+                let next = Position {
+                    x: 0,
+                    y: last.saturating_add(1),
+                };
+                self.document.remove(&start, &next);
+                self.view.position = start;
+            }
+            Operator::Delete if first > 0 => {
+                let y = first.saturating_sub(1);
+                let previous = Position {
+                    x: self.row_len(y),
+                    y,
+                };
+                self.document.remove(&previous, &end);
+                self.view.position = Position { x: 0, y };
+            }
+            // a change leaves an empty row to type in, so does deleting every row
+            Operator::Change | Operator::Delete => {
+                self.document.remove(&start, &end);
+                self.view.position = start;
+            }
+        }
+    }
+
+    /// move the cursor to where insert mode types the text, `o` and `O` open a row first
+    fn start_insert(&mut self, insert: Insert) {
+        let Position { x, y } = self.view.position.clone();
+        let len = self.row_len(y);
+        match insert {
+            Insert::BeforeCursor => (),
+            Insert::AfterCursor => self.view.position.x = x.saturating_add(1).min(len),
+            Insert::LineStart => self.view.position.x = 0,
+            Insert::LineEnd => self.view.position.x = len,
+            Insert::LineBelow => {
+                self.view.position = self.document.insert_str(&Position { x: len, y }, "\n");
+            }
+            Insert::LineAbove => {
+                self.document.insert_str(&Position { x: 0, y }, "\n");
+                self.view.position = Position { x: 0, y };
+            }
+        }
+    }
+
+    /// paste the clipboard `count` times after (or before) the cursor,
+    /// whole lines are pasted below (or above) the cursor row
+    fn paste_register(&mut self, before: bool, count: usize) {
+        let text = self.clipboard.text().to_string();
+        if text.is_empty() {
+            return;
+        }
+        let Position { x, y } = self.view.position.clone();
+        if let Some(lines) = text.strip_suffix('\n') {
+            let lines = vec![lines; count].join("\n");
+            if before || self.document.is_empty() {
+                let at = Position { x: 0, y };
+                self.document.insert_str(&at, &format!("{lines}\n"));
+                self.view.position = at;
+            } else {
+                let at = Position {
+                    x: self.row_len(y),
+                    y,
+                };
+                self.document.insert_str(&at, &format!("\n{lines}"));
+                self.view.position = Position {
+                    x: 0,
+                    y: y.saturating_add(1),
+                };
+            }
+        } else {
+            let x = if before {
+                x
+            } else {
+                x.saturating_add(1).min(self.row_len(y))
+            };
+            let end = self
+                .document
+                .insert_str(&Position { x, y }, &text.repeat(count));
+            // the cursor is put on the last pasted grapheme
+            self.view.position = Position {
+                x: end.x.saturating_sub(1),
+                y: end.y,
+            };
+        }
+    }
+
+    /// the number of graphemes in row `y`, 0 after the last row
+    fn row_len(&self, y: usize) -> usize {
+        self.document.row(y).map_or(0, row::Row::len)
+    }
+
+    /// the position of the grapheme after `position`, the line break at the end of a row is one too
+    fn next_position(&self, position: &Position) -> Position {
+        let Position { x, y } = position.clone();
+        if x < self.row_len(y) {
+            Position {
+                x: x.saturating_add(1),
+                y,
+            }
+        } else if y.saturating_add(1) < self.document.len() {
+            Position {
+                x: 0,
+                y: y.saturating_add(1),
+            }
+        } else {
+            position.clone()
+        }
+    }
+
+    fn run_command(&mut self, command: Command) {
+        match command {
+            Command::Quit => {
+                self.quit();
+            }
+            Command::Save => self.save(),
+            Command::OpenFile => self.open_file(),
+            Command::NextBuffer => self.cycle_buffer(true),
+            Command::PreviousBuffer => self.cycle_buffer(false),
+            Command::PickBuffer => self.pick_buffer(),
+            Command::Search => self.search(SearchDirection::Forward),
+            Command::SearchBackward => self.search(SearchDirection::Backward),
+            Command::KillLine => self.kill_line(),
+            Command::Yank => self.yank(),
+            Command::YankPop => self.yank_pop(),
+            Command::Palette => self.command_line(),
+            Command::GotoLine => self.goto_line(),
+            Command::Replace => self.replace(),
+            Command::Undo => {
+                if let Some(position) = self.document.undo() {
+                    self.view.position = position;
+                } else {
+                    self.status_message = "Already at oldest change".into();
+                }
+            }
+            Command::Redo => {
+                if let Some(position) = self.document.redo() {
+                    self.view.position = position;
+                } else {
+                    self.status_message = "Already at newest change".into();
+                }
+            }
+            Command::ToggleLineNumbers => {
+                self.config.line_numbers = match self.config.line_numbers {
+                    LineNumbers::Off => LineNumbers::Absolute,
+                    LineNumbers::Absolute => LineNumbers::Relative,
+                    LineNumbers::Relative => LineNumbers::Off,
+                };
+            }
+            Command::ToggleSoftWrap => self.toggle_soft_wrap(),
+            Command::NextTheme => self.cycle_theme(),
+            Command::SplitHorizontal => self.split_window(Split::Horizontal),
+            Command::SplitVertical => self.split_window(Split::Vertical),
+            Command::CloseWindow => self.close_window(),
+            Command::FocusUp => self.move_focus(Direction::Up),
+            Command::FocusDown => self.move_focus(Direction::Down),
+            Command::FocusLeft => self.move_focus(Direction::Left),
+            Command::FocusRight => self.move_focus(Direction::Right),
+            Command::Copy => self.copy(),
+            Command::Cut => self.cut(),
+            Command::Paste => self.paste(),
+            Command::ToggleMark => {
+                self.mark_set = !self.mark_set;
+                self.anchor = self.mark_set.then(|| self.view.position.clone());
+            }
+            Command::ClearSelection => self.clear_selection(),
+            Command::MoveUp => self.move_by(Key::Up, false),
+            Command::MoveDown => self.move_by(Key::Down, false),
+            Command::MoveLeft => self.move_by(Key::Left, false),
+            Command::MoveRight => self.move_by(Key::Right, false),
+            Command::MoveWordLeft => {
+                self.move_to(self.document.word_start_before(&self.view.position));
+            }
+            Command::MoveWordRight => {
+                self.move_to(self.document.word_end_after(&self.view.position));
+            }
+            Command::LineStart => self.move_by(Key::Home, false),
+            Command::LineEnd => self.move_by(Key::End, false),
+            Command::PageUp => self.move_by(Key::PageUp, false),
+            Command::PageDown => self.move_by(Key::PageDown, false),
+            Command::SelectUp => self.move_by(Key::Up, true),
+            Command::SelectDown => self.move_by(Key::Down, true),
+            Command::SelectLeft => self.move_by(Key::Left, true),
+            Command::SelectRight => self.move_by(Key::Right, true),
+            Command::DeleteBackward => self.delete(false),
+            Command::DeleteForward => self.delete(true),
+        }
+    }
+
+    /// type `c`, the selected text is replaced
+    fn insert_char(&mut self, c: char) {
+        // a transaction would seal the run of typed characters, which is undone in one step
+        let replacing = self.selection().is_some();
+        if replacing {
+            self.document.begin_transaction();
+            self.delete_selection();
+        }
+        self.document.insert(&self.view.position, c);
+        if replacing {
+            self.document.commit_transaction();
+        }
+        self.move_cursor(Key::Right);
+    }
+
+    /// delete the grapheme after (or before) the cursor, or the selected text if there is one
+    fn delete(&mut self, forward: bool) {
+        if self.delete_selection() {
+            return;
+        }
+        if forward {
+            self.document.delete(&self.view.position);
+        } else if self.view.position.x > 0 || self.view.position.y > 0 {
+            self.move_cursor(Key::Left);
+            self.document.delete(&self.view.position);
+        }
+    }
+
+    /// move the cursor like `key` does, extending the selection if `select` is true
+    fn move_by(&mut self, key: Key, select: bool) {
+        if select {
+            if self.anchor.is_none() {
+                self.anchor = Some(self.view.position.clone());
+            }
+        } else if !self.mark_set {
+            // the selection made by SHIFT is cancelled by moving without SHIFT
+            self.anchor = None;
+        }
+        self.move_cursor(key);
+    }
+
+    fn move_to(&mut self, position: Position) {
+        if !self.mark_set {
+            self.anchor = None;
+        }
+        self.view.position = position;
+    }
+
     /// Prompt the user for input. `f` is a closure that will be executed when the user presses a key
-    fn prompt<F>(&mut self, prompt: &str, mut f: F) -> Result<Option<String>, io::Error>
+    fn prompt<F>(&mut self, prompt: &str, f: F) -> Result<Option<String>, io::Error>
     where
         F: FnMut(&mut Self, Key, &String),
     {
-        let mut result = String::new();
+        let result = self.read_input(prompt, f)?;
+        Ok(result.filter(|input| !input.is_empty()))
+    }
+
+    /// same as `prompt`, but an empty input is accepted.
+    /// it only returns `None` when the user presses ESC
+    fn read_input<F>(&mut self, prompt: &str, f: F) -> Result<Option<String>, io::Error>
+    where
+        F: FnMut(&mut Self, Key, &String),
+    {
+        self.read_input_with(|_, _| prompt.to_string(), None, f)
+    }
+
+    /// same as `read_input`, but the prompt is produced by `label` every time the screen is refreshed,
+    /// so that it can show the state changed by `f`. with `recall`, UP and DOWN browse its history
+    /// and TAB and SHIFT-TAB go through the completions of the input
+    fn read_input_with<L, F>(
+        &mut self,
+        label: L,
+        mut recall: Option<Recall>,
+        mut f: F,
+    ) -> Result<Option<String>, io::Error>
+    where
+        L: Fn(&Self, &str) -> String,
+        F: FnMut(&mut Self, Key, &String),
+    {
+        let mut input = row::Row::default();
+        // the grapheme of `input` the cursor is on
+        let mut cursor = 0;
+        // the completions TAB goes through and the one shown
+        let mut completions: Option<(Vec<String>, usize)> = None;
+        if let Some(recall) = &mut recall {
+            recall.history.reset();
+        }
         loop {
-            self.status_message = StatusMessage::from(format!("{prompt}{result}"));
+            let text = input.slice(0, input.len()).to_string();
+            let prompt = label(self, &text);
+            let column = prompt
+                .width()
+                .saturating_add(input.slice(0, cursor).width());
+            self.status_message = StatusMessage::prompt(format!("{prompt}{text}"), column);
             self.refresh_screen()?;
-            let key = Terminal::read_key()?;
-            match key {
-                Key::Backspace => {
-                    if !result.is_empty() {
-                        result.truncate(result.len() - 1);
-                    }
+            // the input is edited by the commands bound in the keymap
+            let (keys, command) = self.read_command()?;
+            let completing = completions.take();
+            // the line of the history or the completion which replaces the input
+            let mut recalled = None;
+            match (command, keys.as_slice(), recall.as_mut()) {
+                (_, [Key::Char('\n')], _) => break,
+                (_, [Key::Esc], _) => {
+                    self.status_message = "".into();
+                    return Ok(None);
                 }
-                Key::Char('\n') => break,
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
+                (_, [key @ (Key::Char('\t') | Key::BackTab)], Some(recall)) => {
+                    let backward = *key == Key::BackTab;
+                    let (lines, shown) = match completing {
+                        Some((lines, shown)) if backward => {
+                            let last = lines.len().saturating_sub(1);
+                            (lines, shown.checked_sub(1).unwrap_or(last))
+                        }
+                        Some((lines, shown)) => {
+                            let next = shown.saturating_add(1) % lines.len();
+                            (lines, next)
+                        }
+                        None => {
+                            let lines = (recall.complete)(&text);
+                            let shown = if backward {
+                                lines.len().saturating_sub(1)
+                            } else {
+                                0
+                            };
+                            (lines, shown)
+                        }
+                    };
+                    recalled = lines.get(shown).cloned();
+                    // a single completion is kept, the next TAB completes it further
+                    if lines.len() > 1 {
+                        completions = Some((lines, shown));
                     }
                 }
-                Key::Esc => {
-                    result.truncate(0);
-                    break;
+                (Some(Command::MoveUp), _, Some(recall)) => {
+                    recalled = recall.history.older(&text).map(ToString::to_string);
+                }
+                (Some(Command::MoveDown), _, Some(recall)) => {
+                    recalled = recall.history.newer().map(ToString::to_string);
+                }
+                (Some(Command::MoveLeft), _, _) => cursor = cursor.saturating_sub(1),
+                (Some(Command::MoveRight), _, _) => {
+                    cursor = cursor.saturating_add(1).min(input.len());
+                }
+                (Some(Command::LineStart), _, _) => cursor = 0,
+                (Some(Command::LineEnd), _, _) => cursor = input.len(),
+                (Some(Command::MoveWordLeft), _, _) => {
+                    cursor = input.word_start_before(cursor).unwrap_or(0);
+                }
+                (Some(Command::MoveWordRight), _, _) => {
+                    cursor = input.word_end_after(cursor).unwrap_or(input.len());
+                }
+                (Some(Command::DeleteBackward), _, _) if cursor > 0 => {
+                    cursor = cursor.saturating_sub(1);
+                    input.drain(cursor, cursor.saturating_add(1));
+                }
+                (Some(Command::DeleteForward), _, _) => {
+                    input.drain(cursor, cursor.saturating_add(1));
+                }
+                (Some(Command::Paste), _, _) => {
+                    let text = self.clipboard.text().lines().next().unwrap_or_default();
+                    cursor = insert_in_input(&mut input, cursor, text);
+                }
+                (None, [Key::Char(c)], _) if !c.is_control() => {
+                    cursor = insert_in_input(&mut input, cursor, &c.to_string());
                 }
                 _ => (),
             }
-            f(self, key, &result);
+            if let Some(line) = recalled {
+                input = row::Row::from(line.as_str());
+                cursor = input.len();
+            }
+            if let Some(key) = keys.last() {
+                f(self, *key, &input.slice(0, input.len()).to_string());
+            }
         }
         self.status_message = "".into();
-        if result.is_empty() {
-            return Ok(None);
+        Ok(Some(input.slice(0, input.len()).to_string()))
+    }
+
+    /// ask the user a question, return the choice the user makes or `None` if ESC is pressed
+    fn prompt_choice(
+        &mut self,
+        question: &str,
+        choices: &[char],
+    ) -> Result<Option<char>, io::Error> {
+        loop {
+            self.status_message = StatusMessage::sticky(question.to_string());
+            self.refresh_screen()?;
+            match self.read_key()? {
+                Key::Char(c) if choices.contains(&c) => return Ok(Some(c)),
+                Key::Esc => return Ok(None),
+                _ => (),
+            }
         }
-        Ok(Some(result))
     }
 
     fn save(&mut self) {
@@ -243,60 +1262,833 @@ impl Editor {
         }
 
         if self.document.save().is_ok() {
+            self.watch_files();
             self.status_message = "File saved successfully".into();
         } else {
             self.status_message = "Err: writing file failed".into();
         }
     }
 
-    fn search(&mut self) {
+    /// save every modified buffer with a file, unless the file has been changed by another program.
+    /// such a buffer is left in conflict until it is saved or read again by the user
+    fn autosave(&mut self) {
+        let current = self.current;
+        let mut failed = Vec::new();
+        let mut changed = false;
+        for (index, buffer) in self.buffers.iter_mut().enumerate() {
+            let document = if index == current {
+                &mut self.document
+            } else {
+                &mut buffer.document
+            };
+            if document.filename.is_none() || !document.is_dirty() {
+                continue;
+            }
+            if document.is_stale() {
+                // the change is reported here if the watching job hasn't done it yet
+                changed |= document.changed_on_disk() && index == current;
+            } else if document.save().is_err() {
+                failed.push(index);
+            }
+        }
+        if changed {
+            self.status_message = FILE_CHANGED.into();
+        }
+        if let Some(index) = failed.first() {
+            self.status_message = StatusMessage::from(format!(
+                "ERR: autosave of {} failed",
+                self.buffer_name(*index)
+            ));
+        }
+    }
+
+    /// set the autosave timer to go off after the interval in the settings
+    fn schedule_autosave(&mut self) {
+        let deadline = self
+            .config
+            .autosave
+            .and_then(|interval| Instant::now().checked_add(interval));
+        if let Some(deadline) = deadline {
+            self.events.schedule(Timer::Autosave, deadline);
+        }
+    }
+
+    /// let the watching job check the files of every buffer
+    fn watch_files(&self) {
+        let files = (0..self.buffers.len())
+            .filter_map(|index| self.document_at(index).filename.as_ref())
+            .map(PathBuf::from)
+            .collect();
+        if let Ok(mut watched) = self.watched_files.lock() {
+            *watched = files;
+        }
+    }
+
+    /// mark the buffer of `path` as changed by another program, so that it isn't autosaved
+    /// and its status bar shows it. the change is reported once
+    fn file_changed(&mut self, path: &Path) {
+        let Some(index) = (0..self.buffers.len()).find(|index| {
+            self.document_at(*index)
+                .filename
+                .as_deref()
+                .is_some_and(|name| Path::new(name) == path)
+        }) else {
+            return;
+        };
+        if index == self.current {
+            if self.document.changed_on_disk() {
+                self.status_message = FILE_CHANGED.into();
+            }
+        } else if self.buffers[index].document.changed_on_disk() {
+            self.status_message = StatusMessage::from(format!(
+                "WARN: {} has been changed by another program",
+                self.buffer_name(index)
+            ));
+        }
+    }
+
+    /// the document of buffer `index`, the current one is being edited
+    fn document_at(&self, index: usize) -> &Document {
+        if index == self.current {
+            &self.document
+        } else {
+            &self.buffers[index].document
+        }
+    }
+
+    /// the name of buffer `index` shown to the user, a `*` marks unsaved changes
+    fn buffer_name(&self, index: usize) -> String {
+        let document = self.document_at(index);
+        let name = document.filename.as_deref().unwrap_or("[No Name]");
+        if document.is_dirty() {
+            format!("{name}*")
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn unsaved_buffers(&self) -> Vec<String> {
+        (0..self.buffers.len())
+            .filter(|index| self.document_at(*index).is_dirty())
+            .map(|index| self.buffer_name(index))
+            .collect()
+    }
+
+    /// stop unless some buffers have unsaved changes and the user hasn't confirmed it,
+    /// return whether the editor stops
+    fn quit(&mut self) -> bool {
+        let unsaved = self.unsaved_buffers();
+        if self.quit_times > 0 && !unsaved.is_empty() {
+            let again = self
+                .config
+                .keymap
+                .keys_for(Command::Quit)
+                .first()
+                .map_or_else(
+                    || "Run quit again".to_string(),
+                    |keys| format!("Press {} again", key_names(keys).to_uppercase()),
+                );
+            self.status_message = StatusMessage::from(format!(
+                "WARNING! Unsaved changes in {}. {again} to quit.",
+                unsaved.join(", ")
+            ));
+            self.quit_times -= 1;
+            return false;
+        }
+        self.stop = true;
+        true
+    }
+
+    /// switch to the next buffer, or the previous one if `forward` is false
+    fn cycle_buffer(&mut self, forward: bool) {
+        let len = self.buffers.len();
+        let step = if forward { 1 } else { len.saturating_sub(1) };
+        self.switch_buffer(self.current.saturating_add(step) % len);
+    }
+
+    /// leave the current buffer as it is and edit buffer `index` in the focused window
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.current || index >= self.buffers.len() {
+            return;
+        }
+        self.load_buffer(index);
+        self.scroll();
+        // the file may have been changed while the buffer was in the background
+        self.status_message = if self.document.changed_on_disk() || self.document.in_conflict() {
+            FILE_CHANGED.into()
+        } else {
+            StatusMessage::from(format!(
+                "Buffer {}/{}: {}",
+                index.saturating_add(1),
+                self.buffers.len(),
+                self.buffer_name(index)
+            ))
+        };
+    }
+
+    /// move buffer `index` into `document` and `view`, the current buffer keeps the view it is left with
+    fn load_buffer(&mut self, index: usize) {
+        if index == self.current {
+            return;
+        }
+        self.buffers[self.current] = Buffer {
+            document: mem::take(&mut self.document),
+            view: mem::take(&mut self.view),
+        };
+        let buffer = mem::take(&mut self.buffers[index]);
+        self.document = buffer.document;
+        self.view = buffer.view;
+        self.current = index;
+        self.clear_selection();
+    }
+
+    /// the area of the screen shared by the windows, the last line is left for the message bar
+    fn screen_area(&self) -> Area {
+        Area {
+            x: 0,
+            y: 0,
+            width: self.terminal.width() as usize,
+            height: (self.terminal.height() as usize).saturating_add(1),
+        }
+    }
+
+    /// the area of the focused window
+    fn area(&self) -> Area {
+        self.layout
+            .areas(self.screen_area())
+            .into_iter()
+            .find(|(index, _)| *index == self.focus)
+            .map_or_else(|| self.screen_area(), |(_, area)| area)
+    }
+
+    /// every window with what it shows
+    fn panes(&self) -> Vec<Pane<'_>> {
+        self.layout
+            .areas(self.screen_area())
+            .into_iter()
+            .map(|(index, area)| {
+                if index == self.focus {
+                    return Pane {
+                        area,
+                        ..self.focused_pane()
+                    };
+                }
+                let window = &self.windows[index];
+                Pane {
+                    document: self.document_at(window.buffer),
+                    view: &window.view,
+                    area,
+                    buffer: window.buffer,
+                    focused: false,
+                }
+            })
+            .collect()
+    }
+
+    /// highlight the rows every window is about to show
+    fn highlight_panes(&mut self) {
+        for (index, area) in self.layout.areas(self.screen_area()) {
+            let (buffer, view) = if index == self.focus {
+                (self.current, &self.view)
+            } else {
+                (self.windows[index].buffer, &self.windows[index].view)
+            };
+            // a wrapped row takes more than one line, so this is enough
+            let end = view.offset.y.saturating_add(area.height);
+            if buffer == self.current {
+                self.document.highlight_to(end);
+            } else {
+                self.buffers[buffer].document.highlight_to(end);
+            }
+        }
+    }
+
+    fn focused_pane(&self) -> Pane<'_> {
+        Pane {
+            document: &self.document,
+            view: &self.view,
+            area: self.area(),
+            buffer: self.current,
+            focused: true,
+        }
+    }
+
+    /// split the focused window in two, both show the current buffer and the new one gets the focus
+    fn split_window(&mut self, split: Split) {
+        let area = self.area();
+        // each window needs a line of text and its status bar, or a column beside the separator
+        let room = match split {
+            Split::Horizontal => area.height >= 4,
+            Split::Vertical => area.width >= 3,
+        };
+        if !room {
+            self.status_message = "Not enough room to split the window".into();
+            return;
+        }
+        let new = self.windows.len();
+        self.windows.push(Window {
+            buffer: self.current,
+            view: self.view.clone(),
+        });
+        self.layout.split(self.focus, new, split);
+        // keep the cursor inside the smaller window
+        self.scroll();
+        self.focus_window(new);
+    }
+
+    fn close_window(&mut self) {
+        let Some(next) = self.layout.close(self.focus) else {
+            self.status_message = "Can't close the last window".into();
+            return;
+        };
+        self.windows.remove(self.focus);
+        self.enter_window(next);
+    }
+
+    fn focus_window(&mut self, index: usize) {
+        if index == self.focus || index >= self.windows.len() {
+            return;
+        }
+        self.windows[self.focus] = Window {
+            buffer: self.current,
+            view: self.view.clone(),
+        };
+        self.enter_window(index);
+    }
+
+    /// move the buffer and the view of window `index` in, the focused window must have been saved or removed
+    fn enter_window(&mut self, index: usize) {
+        self.load_buffer(self.windows[index].buffer);
+        self.view = mem::take(&mut self.windows[index].view);
+        self.focus = index;
+        self.clear_selection();
+        // the document may have been edited in another window
+        let y = self.view.position.y.min(self.document.len());
+        let x = self
+            .document
+            .row(y)
+            .map_or(0, |row| self.view.position.x.min(row.len()));
+        self.view.position = Position { x, y };
+        self.scroll();
+    }
+
+    /// move the focus to the window next to the focused one in `direction`
+    fn move_focus(&mut self, direction: Direction) {
+        let areas = self.layout.areas(self.screen_area());
+        let cursor = self.cursor_on_screen();
+        if let Some(index) = window::neighbour(&areas, self.focus, direction, &cursor) {
+            self.focus_window(index);
+        }
+    }
+
+    /// ask for a file and open it in a new buffer, or switch to its buffer if it is open already
+    fn open_file(&mut self) {
+        let Some(filename) = self.prompt("Open: ", |_, _, _| {}).unwrap_or(None) else {
+            self.status_message = "Open aborted".into();
+            return;
+        };
+        self.edit_file(&filename);
+    }
+
+    /// open `filename` in a new buffer, or switch to its buffer
+    fn edit_file(&mut self, filename: &str) {
+        let open = (0..self.buffers.len()).find(|index| {
+            self.document_at(*index)
+                .filename
+                .as_deref()
+                .is_some_and(|name| same_file(name, filename))
+        });
+        if let Some(index) = open {
+            self.switch_buffer(index);
+            return;
+        }
+        if let Ok(document) = Document::open(filename) {
+            self.buffers.push(Buffer::from(document));
+            self.watch_files();
+            self.switch_buffer(self.buffers.len().saturating_sub(1));
+        } else {
+            self.status_message = StatusMessage::from(format!("ERR: Cannot open file: {filename}"));
+        }
+    }
+
+    /// read the file of the current buffer again, the unsaved changes are dropped if the user confirms it
+    fn reload(&mut self) {
+        let Some(filename) = self.document.filename.clone() else {
+            self.status_message = "ERR: the buffer has no file".into();
+            return;
+        };
+        if self.document.is_dirty() {
+            let question = format!("Discard the changes to {filename}? (y)es (n)o");
+            if self.prompt_choice(&question, &['y', 'n']).unwrap_or(None) != Some('y') {
+                self.status_message = "Reload aborted".into();
+                return;
+            }
+        }
+        let Ok(document) = Document::open(&filename) else {
+            self.status_message = StatusMessage::from(format!("ERR: Cannot open file: {filename}"));
+            return;
+        };
+        self.document = document;
+        self.clear_selection();
+        let y = self
+            .view
+            .position
+            .y
+            .min(self.document.len().saturating_sub(1));
+        let x = self.view.position.x.min(self.row_len(y));
+        self.move_to(Position { x, y });
+        self.scroll();
+        self.status_message = StatusMessage::from(format!("Read {filename} again"));
+    }
+
+    /// ask for the number or a part of the name of a buffer and switch to it
+    fn pick_buffer(&mut self) {
+        let buffers: Vec<String> = (0..self.buffers.len())
+            .map(|index| format!("{} {}", index.saturating_add(1), self.buffer_name(index)))
+            .collect();
+        let prompt = format!("Buffer ({}): ", buffers.join(" | "));
+        let Some(choice) = self.prompt(&prompt, |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        let index = choice
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| number.checked_sub(1))
+            .filter(|index| *index < self.buffers.len())
+            .or_else(|| {
+                (0..self.buffers.len()).find(|index| self.buffer_name(*index).contains(&choice))
+            });
+        if let Some(index) = index {
+            self.switch_buffer(index);
+        } else {
+            self.status_message = StatusMessage::from(format!("No buffer matches {choice}"));
+        }
+    }
+
+    /// read a command, like `w name`, `goto 120` or `set tab_width=4`, and run it.
+    /// the names are completed with TAB and the commands run before are recalled with UP
+    fn command_line(&mut self) {
+        let themes: Vec<String> = self
+            .themes
+            .iter()
+            .map(|theme| theme.name().to_string())
+            .collect();
+        let complete = |line: &str| command_line::complete(line, &themes);
+        let mut history = mem::take(&mut self.command_history);
+        let recall = Recall {
+            history: &mut history,
+            complete: &complete,
+        };
+        let line = self
+            .read_input_with(|_, _| ":".to_string(), Some(recall), |_, _, _| {})
+            .unwrap_or(None)
+            .filter(|line| !line.trim().is_empty());
+        if let Some(line) = &line {
+            history.add(line);
+        }
+        self.command_history = history;
+        let Some(line) = line else {
+            return;
+        };
+        match command_line::parse(&line) {
+            Ok(invocation) => self.invoke(invocation),
+            Err(e) => self.status_message = StatusMessage::from(format!("ERR: {e}")),
+        }
+    }
+
+    fn invoke(&mut self, invocation: Invocation) {
+        match invocation {
+            Invocation::Run(command) => self.run_command(command),
+            // like Vim, a copy is written unless the buffer has no file yet
+            Invocation::Write(Some(filename)) if self.document.filename.is_some() => {
+                self.status_message = if self.document.write_to(&filename).is_ok() {
+                    StatusMessage::from(format!("Written to {filename}"))
+                } else {
+                    "Err: writing file failed".into()
+                };
+            }
+            Invocation::Write(filename) => {
+                if filename.is_some() {
+                    self.document.filename = filename;
+                }
+                self.save();
+            }
+            Invocation::WriteQuit => {
+                self.save();
+                if !self.document.is_dirty() {
+                    self.quit();
+                }
+            }
+            Invocation::Edit(filename) => self.edit_file(&filename),
+            Invocation::Reload => self.reload(),
+            Invocation::Goto(target) => self.goto(target),
+            Invocation::Set(name, value) => self.set(&name, &value),
+        }
+    }
+
+    /// ask for a line, like `120`, `120:45` or `+10`, and go there
+    fn goto_line(&mut self) {
+        let Some(text) = self.prompt("Go to line: ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        if let Some(target) = command_line::parse_target(&text) {
+            self.goto(target);
+        } else {
+            self.status_message = StatusMessage::from(format!("ERR: {TARGET_FORMAT}"));
+        }
+    }
+
+    /// move the cursor to `target`, kept in the document and in its row,
+    /// and show the row highlighted in the middle of the window
+    fn goto(&mut self, target: Target) {
+        let y = match target.line {
+            Line::Number(line) => line.saturating_sub(1),
+            Line::Down(lines) => self.view.position.y.saturating_add(lines),
+            Line::Up(lines) => self.view.position.y.saturating_sub(lines),
+        }
+        .min(self.document.len().saturating_sub(1));
+        let x = target
+            .column
+            .map_or(0, |column| column.saturating_sub(1))
+            .min(self.row_len(y));
+        self.move_to(Position { x, y });
+        self.view.offset.y = y.saturating_sub(self.text_height() / 2);
+        self.view.top_line = 0;
+        if let Some(until) = Instant::now().checked_add(HIGHLIGHT_TIME) {
+            self.highlighted = Some((self.current, y, until));
+            self.events.schedule(Timer::Highlight, until);
+        }
+    }
+
+    /// the row highlighted after going to a line in `pane`, `None` once it has faded
+    fn highlighted_row(&self, pane: &Pane) -> Option<usize> {
+        self.highlighted
+            .filter(|(buffer, _, until)| {
+                pane.focused && *buffer == pane.buffer && *until > Instant::now()
+            })
+            .map(|(_, y, _)| y)
+    }
+
+    /// change a setting for this session, like the settings file would
+    fn set(&mut self, name: &str, value: &str) {
+        let theme = self.config.theme.clone();
+        if let Err(e) = self.config.set(name, value) {
+            self.status_message = StatusMessage::from(format!("ERR: Invalid setting {e}"));
+            return;
+        }
+        if let Some(index) = self
+            .themes
+            .iter()
+            .position(|theme| theme.name() == self.config.theme)
+        {
+            self.theme = index;
+        } else {
+            self.status_message =
+                StatusMessage::from(format!("ERR: Unknown theme {}", self.config.theme));
+            self.config.theme = theme;
+            return;
+        }
+        if (self.config.profile == Profile::Vim) != self.vim.is_some() {
+            self.vim = (self.config.profile == Profile::Vim).then(Vim::default);
+            self.clear_selection();
+        }
+        self.quit_times = self.config.quit_times;
+        self.clipboard.osc52 = self.config.osc52;
+        self.schedule_autosave();
+    }
+
+    /// search from the cursor in `direction`, the direction is switched by moving to the previous or next result
+    fn search(&mut self, mut direction: SearchDirection) {
         // save the old position so that we can go back to original position if user cancels searching
-        let old_position = self.position.clone();
-        let mut direction = SearchDirection::Forward;
+        let old_position = self.view.position.clone();
         // incremental search.
         // the position will be reset after typing a character according to the result of searching
         let query = self
-            .prompt(
-                "Search: (ESC to exit, Arrows to navigate): ",
+            .read_input_with(
+                |editor, query| {
+                    let options = editor.search_options;
+                    let invalid = if Query::new(query, options).is_err() {
+                        "(invalid regex) "
+                    } else {
+                        ""
+                    };
+                    format!("Search {options}{invalid}(ESC to exit, Up/Down to navigate, ALT-R/C/W regex/case/word): ")
+                },
+                None,
                 |editor, key, query| {
                     let mut moved = false;
-                    // if user types an arrow key, that means he want to move to the next/previous searching result
-                    match key {
-                        Key::Right | Key::Down => {
+                    // Up and Down move to the previous/next result, Left and Right move in the query.
+                    // the keys of the search commands do it too, like CTRL-S and CTRL-R of Emacs
+                    let command = match editor.config.keymap.lookup(&[key]) {
+                        Binding::Command(command) => Some(command),
+                        Binding::Prefix | Binding::Unbound => None,
+                    };
+                    match (command, key) {
+                        (Some(Command::Search), _) | (_, Key::Down) => {
                             direction = SearchDirection::Forward;
                             editor.move_cursor(Key::Right);
                             moved = true;
                         }
-                        Key::Left | Key::Up => {
+                        (Some(Command::SearchBackward), _) | (_, Key::Up) => {
                             direction = SearchDirection::Backward;
                             // editor.move_cursor(Key::Left);
                             moved = true;
                         }
+                        (_, Key::Alt('r')) => editor.search_options.regex ^= true,
+                        (_, Key::Alt('c')) => editor.search_options.ignore_case ^= true,
+                        (_, Key::Alt('w')) => editor.search_options.whole_word ^= true,
                         _ => (),
                     }
+                    let Ok(query) = Query::new(query, editor.search_options) else {
+                        editor.document.highlight(None);
+                        return;
+                    };
                     // if editor can find target, we should reset position
                     // else we just move back the cursor
-                    if let Some(position) = editor.document.find(query, &editor.position, direction)
+                    if let Some(position) = editor.document.find(&query, &editor.view.position, direction)
                     {
-                        editor.position = position;
+                        editor.view.position = position;
                         editor.scroll();
                     } else if moved {
                         editor.move_cursor(Key::Left);
                     }
-                    editor.document.highlight(Some(query));
+                    editor.document.highlight(Some(&query));
                 },
             )
-            .unwrap_or(None);
+            .unwrap_or(None)
+            .filter(|query| !query.is_empty());
         if query.is_none() {
-            self.position = old_position;
+            self.view.position = old_position;
             self.scroll();
         }
         self.document.highlight(None);
     }
 
+    /// replace the occurrences of a string after the cursor, then the ones before it from the top.
+    /// every occurrence needs to be confirmed unless the user chooses to replace all of them
+    fn replace(&mut self) {
+        let Some(query) = self.prompt("Replace: ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        let Some(replacement) = self
+            .read_input(&format!("Replace {query} with: "), |_, _, _| {})
+            .unwrap_or(None)
+        else {
+            self.status_message = "Replace aborted".into();
+            return;
+        };
+        let query_len = query.graphemes(true).count();
+        let replacement_len = replacement.graphemes(true).count();
+        let old_position = self.view.position.clone();
+        let mut at = self.view.position.clone();
+        let mut replace_all = false;
+        let query = Query::from(&query[..]);
+        let mut replaced = 0;
+        // after wrapping around, the occurrences before `end` are left
+        let mut end = None;
+
+        // all replacements can be undone in one step
+        self.document.begin_transaction();
+        loop {
+            let found = match (
+                self.document.find(&query, &at, SearchDirection::Forward),
+                &end,
+            ) {
+                (Some(found), Some(end)) if found >= *end => break,
+                (Some(found), _) => found,
+                (None, None) => {
+                    end = Some(old_position.clone());
+                    at = Position::default();
+                    continue;
+                }
+                (None, Some(_)) => break,
+            };
+            self.view.position = found.clone();
+            self.scroll();
+            if !replace_all {
+                self.document.highlight(Some(&query));
+                let choice = self
+                    .prompt_choice(
+                        "Replace this occurrence? (y)es (n)o (a)ll (q)uit",
+                        &['y', 'n', 'a', 'q'],
+                    )
+                    .unwrap_or(None);
+                match choice {
+                    Some('y') => (),
+                    Some('a') => replace_all = true,
+                    Some('n') => {
+                        at = Position {
+                            x: found.x.saturating_add(1),
+                            y: found.y,
+                        };
+                        continue;
+                    }
+                    _ => break,
+                }
+            }
+            self.document.replace(&found, query_len, &replacement);
+            replaced += 1;
+            // the start point is moved by the replacements before it in the same row
+            if let Some(end) = end.as_mut().filter(|end| end.y == found.y) {
+                end.x = (end.x.saturating_add(replacement_len)).saturating_sub(query_len);
+            }
+            // skip the replacement so that it won't be matched again
+            at = Position {
+                x: found.x.saturating_add(replacement_len),
+                y: found.y,
+            };
+        }
+        self.document.commit_transaction();
+        self.document.highlight(None);
+
+        if replaced == 0 {
+            self.view.position = old_position;
+        }
+        self.scroll();
+        self.status_message = StatusMessage::from(format!("Replaced {replaced} occurrence(s)"));
+    }
+
+    /// the ordered positions of the selection, `None` if nothing is selected
+    fn selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.anchor.as_ref()?;
+        let (start, end) = match anchor.cmp(&self.view.position) {
+            Ordering::Less | Ordering::Equal => (anchor, &self.view.position),
+            Ordering::Greater => (&self.view.position, anchor),
+        };
+        // the visual mode of Vim selects the grapheme under the cursor too
+        if self
+            .vim
+            .as_ref()
+            .is_some_and(|vim| vim.mode() == Mode::Visual)
+        {
+            return Some((start.clone(), self.next_position(end)));
+        }
+        (start != end).then(|| (start.clone(), end.clone()))
+    }
+
+    /// the graphemes of the row at `y` which are selected.
+    /// the line break of a row is selected if the selection continues on the next row
+    fn selection_in_row(&self, y: usize, row: &row::Row) -> Option<Range<usize>> {
+        let (start, end) = self.selection()?;
+        if y < start.y || y > end.y {
+            return None;
+        }
+        let from = if y == start.y { start.x } else { 0 };
+        let to = if y == end.y {
+            end.x
+        } else {
+            row.len().saturating_add(1)
+        };
+        Some(from..to)
+    }
+
+    fn clear_selection(&mut self) {
+        self.anchor = None;
+        self.mark_set = false;
+    }
+
+    /// remove the selected text, return false if nothing is selected
+    fn delete_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection() else {
+            return false;
+        };
+        self.document.remove(&start, &end);
+        self.view.position = start;
+        self.clear_selection();
+        true
+    }
+
+    fn copy(&mut self) {
+        if let Some((start, end)) = self.selection() {
+            self.clipboard.copy(self.document.text(&start, &end));
+            self.clear_selection();
+            self.status_message = "Copied".into();
+        } else {
+            self.status_message = "Nothing is selected".into();
+        }
+    }
+
+    fn cut(&mut self) {
+        if let Some((start, end)) = self.selection() {
+            let text = self.document.remove(&start, &end);
+            self.clipboard.copy(text);
+            self.view.position = start;
+            self.clear_selection();
+        } else {
+            self.status_message = "Nothing is selected".into();
+        }
+    }
+
+    /// cut the rest of the cursor row, or the line break at its end.
+    /// the lines killed one after another are pasted together
+    fn kill_line(&mut self) {
+        let start = self.view.position.clone();
+        let len = self.row_len(start.y);
+        let end = if start.x < len {
+            Position { x: len, y: start.y }
+        } else if start.y.saturating_add(1) < self.document.len() {
+            Position {
+                x: 0,
+                y: start.y.saturating_add(1),
+            }
+        } else {
+            return;
+        };
+        let text = self.document.remove(&start, &end);
+        if self.last_command == Some(Command::KillLine) {
+            self.clipboard.append(&text);
+        } else {
+            self.clipboard.copy(text);
+        }
+    }
+
+    /// paste the text of the kill ring at the cursor, `yank_pop` can replace it with an older one
+    fn yank(&mut self) {
+        let text = self.clipboard.text().to_string();
+        if text.is_empty() {
+            return;
+        }
+        let start = self.view.position.clone();
+        self.view.position = self.document.insert_str(&start, &text);
+        self.yanked = Some((start, self.view.position.clone()));
+    }
+
+    /// replace the text pasted by the last yank with the one killed before it
+    fn yank_pop(&mut self) {
+        let yanked = self.yanked.take();
+        let Some((start, end)) =
+            yanked.filter(|_| matches!(self.last_command, Some(Command::Yank | Command::YankPop)))
+        else {
+            self.status_message = "The last command was not a yank".into();
+            return;
+        };
+        let text = self.clipboard.rotate().to_string();
+        self.document.begin_transaction();
+        self.document.remove(&start, &end);
+        self.view.position = self.document.insert_str(&start, &text);
+        self.document.commit_transaction();
+        self.yanked = Some((start, self.view.position.clone()));
+    }
+
+    /// paste the clipboard at the cursor, the selected text will be replaced
+    fn paste(&mut self) {
+        let text = self.clipboard.text().to_string();
+        if text.is_empty() {
+            return;
+        }
+        self.document.begin_transaction();
+        self.delete_selection();
+        self.view.position = self.document.insert_str(&self.view.position, &text);
+        self.document.commit_transaction();
+    }
+
     fn move_cursor(&mut self, key: Key) {
-        let terminal_height = self.terminal.height() as usize;
-        let Position { mut x, mut y } = self.position;
+        if self.config.soft_wrap != SoftWrap::Off && matches!(key, Key::Up | Key::Down) {
+            self.view.position = self.move_visual_line(key == Key::Down);
+            return;
+        }
+        let terminal_height = self.text_height();
+        let Position { mut x, mut y } = self.view.position;
         let height = self.document.len();
         let mut width = if let Some(row) = self.document.row(y) {
             row.len()
@@ -349,6 +2141,14 @@ impl Editor {
             _ => (),
         }
 
+        // moving to another row keeps the display column, not the grapheme index
+        if matches!(key, Key::Up | Key::Down | Key::PageUp | Key::PageDown) {
+            let column = self.cursor_columns().start;
+            if let Some(row) = self.document.row(y) {
+                x = row.index_at_column(column, self.config.tab_width);
+            }
+        }
+
         // since width may be modified, so we need to recalculate it
         width = if let Some(row) = self.document.row(y) {
             row.len()
@@ -360,14 +2160,78 @@ impl Editor {
             x = width;
         }
 
-        self.position = Position { x, y };
+        self.view.position = Position { x, y };
+    }
+
+    /// the position one visual line above or below the cursor, at the same column of the screen
+    fn move_visual_line(&self, down: bool) -> Position {
+        let Position { x, y } = self.view.position;
+        let lines = self.visual_lines(y);
+        let line = visual_line(&lines, x);
+        let line_start = self.document.row(y).map_or(0, |row| {
+            row.column(lines[line].start, self.config.tab_width)
+        });
+        let column = self.cursor_columns().start.saturating_sub(line_start);
+
+        let (y, line) = if down {
+            if line.saturating_add(1) < lines.len() {
+                (y, line.saturating_add(1))
+            } else if y < self.document.len() {
+                (y.saturating_add(1), 0)
+            } else {
+                return self.view.position.clone();
+            }
+        } else if line > 0 {
+            (y, line.saturating_sub(1))
+        } else if y > 0 {
+            let y = y.saturating_sub(1);
+            (y, self.visual_lines(y).len().saturating_sub(1))
+        } else {
+            return self.view.position.clone();
+        };
+
+        let Some(row) = self.document.row(y) else {
+            return Position { x: 0, y };
+        };
+        let lines = self.visual_lines(y);
+        let range = &lines[line];
+        let start = row.column(range.start, self.config.tab_width);
+        let x = row.index_at_column(start.saturating_add(column), self.config.tab_width);
+        // only the last visual line has room for the cursor after its last grapheme
+        let x = if line.saturating_add(1) < lines.len() {
+            x.min(range.end.saturating_sub(1))
+        } else {
+            x
+        };
+        Position { x, y }
+    }
+
+    /// the display columns taken by the grapheme under the cursor, one column at the end of a row
+    fn cursor_columns(&self) -> Range<usize> {
+        let Position { x, y } = self.view.position;
+        let Some(row) = self.document.row(y) else {
+            return 0..1;
+        };
+        let start = row.column(x, self.config.tab_width);
+        let end = if x < row.len() {
+            row.column(x.saturating_add(1), self.config.tab_width)
+        } else {
+            start.saturating_add(1)
+        };
+        start..end
     }
 
     fn scroll(&mut self) {
-        let Position { x, y } = self.position;
-        let width = self.terminal.width() as usize;
-        let height = self.terminal.height() as usize;
-        let mut offset = &mut self.offset;
+        if self.config.soft_wrap != SoftWrap::Off {
+            self.scroll_visual_lines();
+            return;
+        }
+        self.view.top_line = 0;
+        let y = self.view.position.y;
+        let columns = self.cursor_columns();
+        let width = self.text_width();
+        let height = self.text_height();
+        let offset = &mut self.view.offset;
 
         // modify offset when cursor move out of the screen
         // make sure the cursor always in the screen
@@ -382,15 +2246,97 @@ impl Editor {
             offset.y = y.saturating_sub(height).saturating_add(1);
         }
 
-        // same as above
-        if x < offset.x {
-            offset.x = x;
-        } else if x >= offset.x.saturating_add(width) {
-            offset.x = x.saturating_sub(width).saturating_add(1);
+        // same as above, but the whole grapheme under the cursor should be shown
+        if columns.start < offset.x {
+            offset.x = columns.start;
+        } else if columns.end > offset.x.saturating_add(width) {
+            offset.x = columns.end.saturating_sub(width).min(columns.start);
+        }
+    }
+
+    /// like `scroll`, but the screen moves by visual lines and never horizontally
+    fn scroll_visual_lines(&mut self) {
+        let height = self.text_height();
+        let Position { x, y } = self.view.position;
+        let cursor = (y, visual_line(&self.visual_lines(y), x));
+
+        // the screen can't start before the visual line `height - 1` lines above the cursor
+        let mut earliest = cursor;
+        let mut above = height.saturating_sub(1);
+        while above > 0 {
+            if earliest.1 >= above {
+                earliest.1 = earliest.1.saturating_sub(above);
+                break;
+            } else if earliest.0 == 0 {
+                earliest.1 = 0;
+                break;
+            }
+            above = above.saturating_sub(earliest.1.saturating_add(1));
+            earliest.0 = earliest.0.saturating_sub(1);
+            earliest.1 = self.visual_lines(earliest.0).len().saturating_sub(1);
+        }
+
+        // a row may have fewer lines after the screen is resized
+        let top_lines = self.visual_lines(self.view.offset.y).len();
+        let top = (
+            self.view.offset.y,
+            self.view.top_line.min(top_lines.saturating_sub(1)),
+        );
+        (self.view.offset.y, self.view.top_line) = if cursor < top {
+            cursor
+        } else {
+            top.max(earliest)
+        };
+        self.view.offset.x = 0;
+    }
+
+    /// where the cursor is on the screen, the text starts after the gutter
+    fn cursor_on_screen(&self) -> Position {
+        let area = self.area();
+        if self.config.soft_wrap != SoftWrap::Off {
+            let Position { x, y } = self.view.position;
+            let lines = self.visual_lines(y);
+            let line = visual_line(&lines, x);
+            let line_start = self.document.row(y).map_or(0, |row| {
+                row.column(lines[line].start, self.config.tab_width)
+            });
+            let above: usize = (self.view.offset.y..y)
+                .map(|y| self.visual_lines(y).len())
+                .sum();
+            // the cursor after the last grapheme of a full line stays on the screen
+            let column = self
+                .cursor_columns()
+                .start
+                .saturating_sub(line_start)
+                .min(self.text_width().saturating_sub(1));
+            return Position {
+                x: column
+                    .saturating_add(self.gutter_width())
+                    .saturating_add(area.x),
+                y: above
+                    .saturating_add(line)
+                    .saturating_sub(self.view.top_line)
+                    .saturating_add(area.y),
+            };
+        }
+        Position {
+            x: self
+                .cursor_columns()
+                .start
+                .saturating_sub(self.view.offset.x)
+                .saturating_add(self.gutter_width())
+                .saturating_add(area.x),
+            y: self
+                .view
+                .position
+                .y
+                .saturating_sub(self.view.offset.y)
+                .saturating_add(area.y),
         }
     }
 
-    fn refresh_screen(&self) -> Result<(), io::Error> {
+    fn refresh_screen(&mut self) -> Result<(), io::Error> {
+        self.highlight_panes();
         Terminal::hide_cursor();
         Terminal::cursor_position(&Position::default());
 
@@ -398,51 +2344,91 @@ impl Editor {
             Terminal::clear_screen();
             println!("goodbye\r");
         } else {
-            self.draw_rows();
-            self.draw_status_bar();
+            for pane in self.panes() {
+                self.draw_pane(&pane);
+            }
             self.draw_message_bar();
-            // after draw `~`, we need to put the cursor back
-            Terminal::cursor_position(&Position {
-                x: self.position.x.saturating_sub(self.offset.x),
-                y: self.position.y.saturating_sub(self.offset.y),
-            });
+            // after draw `~`, we need to put the cursor back,
+            // or into the message bar when the user is typing in a prompt
+            let cursor = match self.status_message.cursor {
+                Some(x) => Position {
+                    x: x.min((self.terminal.width() as usize).saturating_sub(1)),
+                    y: (self.terminal.height() as usize).saturating_add(1),
+                },
+                None => self.cursor_on_screen(),
+            };
+            Terminal::cursor_position(&cursor);
         }
 
         Terminal::show_cursor();
         Terminal::flush()
     }
 
-    fn draw_status_bar(&self) {
+    fn draw_status_bar(&self, pane: &Pane) {
         let mut status;
-        let width = self.terminal.width() as usize;
+        let width = pane.area.width;
+        let document = pane.document;
 
-        let modified_indicator = if self.document.is_dirty() {
+        let modified_indicator = if document.is_dirty() {
             " (modified)"
         } else {
             ""
         };
+        // kept until the buffer is saved or read again
+        let conflict_indicator = if document.in_conflict() {
+            " (changed on disk)"
+        } else {
+            ""
+        };
 
         let mut filename = "[No Name]".to_string();
-        if let Some(name) = &self.document.filename {
-            filename = name.clone();
-            filename.truncate(20);
+        if let Some(name) = &document.filename {
+            filename.clone_from(name);
+            truncate_to_width(&mut filename, 20);
         }
 
+        // the number of the buffer when there are more than one
+        let buffer_indicator = if self.buffers.len() > 1 {
+            format!(
+                " [{}/{}]",
+                pane.buffer.saturating_add(1),
+                self.buffers.len()
+            )
+        } else {
+            String::new()
+        };
+
+        // the mode of Vim and the command being typed, in the focused window
+        let mode_indicator = match &self.vim {
+            Some(vim) if pane.focused => {
+                let pending = vim.pending();
+                if pending.is_empty() {
+                    format!("{} | ", vim.mode().name())
+                } else {
+                    format!("{} {pending} | ", vim.mode().name())
+                }
+            }
+            _ => String::new(),
+        };
+
         status = format!(
-            "{} - {} lines{}",
+            "{}{} - {} lines{}{}{}",
+            mode_indicator,
             filename,
-            self.document.len(),
-            modified_indicator
+            document.len(),
+            modified_indicator,
+            conflict_indicator,
+            buffer_indicator
         );
 
         let line_indicator = format!(
             "{} | {}/{}",
-            self.document.file_type(),
-            self.position.y.saturating_add(1),
-            self.document.len()
+            document.file_type(),
+            pane.view.position.y.saturating_add(1),
+            document.len()
         );
-        #[allow(clippy::integer_arithmetic)]
-        let len = status.len() + line_indicator.len();
+        #[allow(clippy::arithmetic_side_effects)]
+        let len = status.width() + line_indicator.width();
 
         // fill the status bar if its content is shorter than screen
         if width > len {
@@ -450,33 +2436,943 @@ impl Editor {
         }
 
         status = format!("{status}{line_indicator}");
-        status.truncate(width);
+        truncate_to_width(&mut status, width);
 
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{status}\r");
-
-        Terminal::reset_fg_color();
-        Terminal::reset_bg_color();
+        // the focused window stands out
+        Terminal::set_style(if pane.focused {
+            &self.theme().status_bar
+        } else {
+            &self.theme().inactive_status_bar
+        });
+        print!("{status}");
+        Terminal::reset_style();
     }
 
     fn draw_message_bar(&self) {
+        Terminal::cursor_position(&Position {
+            x: 0,
+            y: (self.terminal.height() as usize).saturating_add(1),
+        });
         Terminal::clear_current_line();
         let msg = &self.status_message;
-        // editor will show status message only for 5 seconds
-        if msg.time.elapsed() < Duration::from_secs(5) {
+        if msg.is_visible(self.config.status_timeout) {
             // we must clone it because we want to truncate the text
             // when the message is showed on screen
             // but we don't want to change the text in the data structure
             let mut text = msg.text.clone();
-            text.truncate(self.terminal.width() as usize);
-            print!("{text}\r");
+            truncate_to_width(&mut text, self.terminal.width() as usize);
+            print!("{text}");
         }
     }
 }
 
-fn die(e: Box<dyn Error>) {
+/// insert `text` in the input of a prompt at grapheme `at`, return the index right after it
+fn insert_in_input(input: &mut row::Row, at: usize, text: &str) -> usize {
+    let len = input.len();
+    input.insert_str(at, text);
+    // a combining character doesn't make a new grapheme
+    at.saturating_add(input.len().saturating_sub(len))
+}
+
+/// the welcome message centered in a window of `width` columns
+fn welcome_message(width: usize) -> String {
+    let message = format!("Editor --version {VERSION}");
+    // center the welcome message
+    let padding = width.saturating_sub(message.len()) / 2;
+    let spaces = " ".repeat(padding.saturating_sub(1));
+    let mut message = format!("~{spaces}{message}");
+    // avoid our message being cutoff
+    truncate_to_width(&mut message, width);
+    message
+}
+
+/// the index of the visual line in `lines` where the grapheme at `x` is shown,
+/// the end of a row belongs to its last line
+fn visual_line(lines: &[Range<usize>], x: usize) -> usize {
+    lines
+        .iter()
+        .position(|line| x < line.end)
+        .unwrap_or(lines.len().saturating_sub(1))
+}
+
+/// whether two names refer to the same file, e.g. `src/main.rs` and `./src/main.rs`
+fn same_file(a: &str, b: &str) -> bool {
+    a == b
+        || matches!(
+            (fs::canonicalize(a), fs::canonicalize(b)),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+/// cut `text` to the graphemes that fit in `width` columns of the screen
+fn truncate_to_width(text: &mut String, width: usize) {
+    let mut columns: usize = 0;
+    let end = text
+        .grapheme_indices(true)
+        .find(|(_, g)| {
+            columns = columns.saturating_add(g.width());
+            columns > width
+        })
+        .map(|(index, _)| index);
+    if let Some(end) = end {
+        text.truncate(end);
+    }
+}
+
+fn die(e: &dyn Error) {
     Terminal::clear_screen();
     panic!("{}", e);
 }
+
+#[cfg(test)]
+mod editor_tests {
+    use std::{
+        cell::Cell,
+        env, fs,
+        path::Path,
+        process,
+        rc::Rc,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use termion::event::Key;
+
+    use super::{truncate_to_width, Buffer, Editor};
+    use crate::{
+        clipboard::Clipboard,
+        command_line::Invocation,
+        config::{LineNumbers, SoftWrap},
+        event::{Event, EventLoop},
+        keymap::{Command, Keymap},
+        vim::{Mode, Vim},
+        window::{Area, Direction, Layout, Split},
+        Document, Position, Terminal,
+    };
+
+    /// an editor on a fake terminal of 40x12, with `text` typed into its document
+    fn editor(text: &str) -> Editor {
+        let terminal = Terminal::fake(Rc::new(Cell::new((40, 12))));
+        let mut document = Document::default();
+        document.insert_str(&Position::default(), text);
+        Editor::with(terminal, EventLoop::new(), document, "".into())
+    }
+
+    /// give `editor` a terminal of `size`, the returned cell resizes it again
+    fn resize(editor: &mut Editor, size: (u16, u16)) -> Rc<Cell<(u16, u16)>> {
+        let size = Rc::new(Cell::new(size));
+        editor.terminal = Terminal::fake(Rc::clone(&size));
+        size
+    }
+
+    /// queue `keys` to be read by `editor`
+    fn send(editor: &Editor, keys: &[Key]) {
+        let sender = editor.events.sender();
+        for key in keys {
+            sender.send(Ok(Event::Key(*key))).unwrap();
+        }
+    }
+
+    /// the text of every row of the document
+    fn rows(editor: &Editor) -> Vec<String> {
+        (0..editor.document.len())
+            .map(|y| {
+                let row = editor.document.row(y).unwrap();
+                row.slice(0, row.len()).to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resize_keeps_cursor_on_screen() {
+        let mut editor = editor(&"a long row\n".repeat(30));
+        // 2 rows are taken by the status bar and the message bar
+        let size = resize(&mut editor, (20, 12));
+        let sender = editor.events.sender();
+        editor.view.position = Position { x: 9, y: 9 };
+        editor.scroll();
+        assert_eq!(editor.view.offset, Position::default());
+
+        size.set((5, 7));
+        sender.send(Ok(Event::Resize)).unwrap();
+        sender.send(Ok(Event::Key(Key::Char('x')))).unwrap();
+        assert_eq!(editor.read_key().unwrap(), Key::Char('x'));
+        assert_eq!((editor.terminal.width(), editor.terminal.height()), (5, 5));
+        assert_eq!(editor.view.offset, Position { x: 5, y: 5 });
+
+        // a resize signal without a change of size is ignored
+        sender.send(Ok(Event::Resize)).unwrap();
+        sender.send(Ok(Event::Key(Key::Esc))).unwrap();
+        assert_eq!(editor.read_key().unwrap(), Key::Esc);
+        assert_eq!(editor.view.offset, Position { x: 5, y: 5 });
+    }
+
+    #[test]
+    fn gutter_adapts_to_document() {
+        let mut editor = editor(&"0123456789abcdef\n".repeat(120));
+        resize(&mut editor, (20, 12));
+        editor.view.position = Position { x: 16, y: 99 };
+        editor.scroll();
+        assert_eq!(editor.gutter_width(), 0);
+        assert_eq!(editor.view.offset, Position { x: 0, y: 90 });
+        assert_eq!(editor.cursor_on_screen(), Position { x: 16, y: 9 });
+
+        // 121 rows need 3 digits and a space
+        editor.config.line_numbers = LineNumbers::Absolute;
+        assert_eq!(editor.gutter_width(), 4);
+        assert_eq!(editor.line_number(&editor.focused_pane(), 0), "  1 ");
+        assert_eq!(editor.line_number(&editor.focused_pane(), 99), "100 ");
+        // the text is narrower now, so the cursor scrolls the row horizontally
+        editor.scroll();
+        assert_eq!(editor.view.offset, Position { x: 1, y: 90 });
+        assert_eq!(editor.cursor_on_screen(), Position { x: 19, y: 9 });
+
+        editor.config.line_numbers = LineNumbers::Relative;
+        assert_eq!(editor.line_number(&editor.focused_pane(), 97), "  2 ");
+        assert_eq!(editor.line_number(&editor.focused_pane(), 99), "100 ");
+        assert_eq!(editor.line_number(&editor.focused_pane(), 101), "  2 ");
+    }
+
+    #[test]
+    fn tabs_take_display_columns() {
+        let mut editor = editor("\t\tx\nabcdefghij\n");
+        resize(&mut editor, (10, 12));
+        editor.view.position = Position { x: 2, y: 0 };
+        editor.scroll();
+        assert_eq!(editor.cursor_on_screen(), Position { x: 8, y: 0 });
+
+        // moving down keeps the column, moving up lands on the tab covering it
+        editor.move_cursor(Key::Down);
+        assert_eq!(editor.view.position, Position { x: 8, y: 1 });
+        editor.move_cursor(Key::Left);
+        editor.move_cursor(Key::Up);
+        assert_eq!(editor.view.position, Position { x: 1, y: 0 });
+        assert_eq!(editor.cursor_on_screen(), Position { x: 4, y: 0 });
+
+        // the horizontal offset is counted in columns
+        editor.config.tab_width = 8;
+        editor.view.position = Position { x: 3, y: 0 };
+        editor.scroll();
+        assert_eq!(editor.view.offset, Position { x: 8, y: 0 });
+        assert_eq!(editor.cursor_on_screen(), Position { x: 9, y: 0 });
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        let mut editor = editor("中文字符abc\nabcdefghi中\n");
+        resize(&mut editor, (10, 12));
+        editor.view.position = Position { x: 2, y: 0 };
+        editor.scroll();
+        assert_eq!(editor.cursor_on_screen(), Position { x: 4, y: 0 });
+
+        // `c` is at column 10, so the row scrolls by one column
+        editor.view.position = Position { x: 6, y: 0 };
+        editor.scroll();
+        assert_eq!(editor.view.offset, Position { x: 1, y: 0 });
+        assert_eq!(editor.cursor_on_screen(), Position { x: 9, y: 0 });
+
+        // moving down keeps the column, the wide character under the cursor is shown in full
+        editor.view.offset = Position::default();
+        editor.move_cursor(Key::Down);
+        assert_eq!(editor.view.position, Position { x: 9, y: 1 });
+        editor.scroll();
+        assert_eq!(editor.view.offset, Position { x: 1, y: 0 });
+        assert_eq!(editor.cursor_on_screen(), Position { x: 8, y: 1 });
+        // column 3 is the second half of `文`
+        editor.view.position.x = 3;
+        editor.move_cursor(Key::Up);
+        assert_eq!(editor.view.position, Position { x: 1, y: 0 });
+    }
+
+    #[test]
+    fn truncate_wide_characters() {
+        let mut text = String::from("ab中文");
+        truncate_to_width(&mut text, 4);
+        assert_eq!(text, "ab中");
+        truncate_to_width(&mut text, 3);
+        assert_eq!(text, "ab");
+        truncate_to_width(&mut text, 10);
+        assert_eq!(text, "ab");
+    }
+
+    #[test]
+    fn soft_wrap_moves_by_visual_lines() {
+        let mut editor = editor(&format!("{}\nb\n{}\n", "a".repeat(25), "c".repeat(12)));
+        resize(&mut editor, (10, 7));
+        editor.config.soft_wrap = SoftWrap::Anywhere;
+        editor.view.position = Position { x: 15, y: 0 };
+        editor.scroll();
+        assert_eq!(editor.view.offset, Position::default());
+        assert_eq!(editor.cursor_on_screen(), Position { x: 5, y: 1 });
+
+        let mut visited = Vec::new();
+        for _ in 0..4 {
+            editor.move_cursor(Key::Down);
+            editor.scroll();
+            visited.push((editor.view.position.clone(), editor.cursor_on_screen()));
+        }
+        let at = |x, y| Position { x, y };
+        assert_eq!(
+            visited,
+            [
+                // the last line of a row has room after its last grapheme
+                (at(25, 0), at(5, 2)),
+                (at(1, 1), at(1, 3)),
+                (at(1, 2), at(1, 4)),
+                // the 6th visual line scrolls the screen by one line, not one row
+                (at(11, 2), at(1, 4)),
+            ]
+        );
+        assert_eq!((editor.view.offset.y, editor.view.top_line), (0, 1));
+        let lines: Vec<usize> = editor
+            .screen_lines_of(&editor.focused_pane())
+            .iter()
+            .map(|(y, _)| *y)
+            .collect();
+        assert_eq!(lines, [0, 0, 1, 2, 2]);
+
+        // moving up past the top shows the first visual line again
+        for _ in 0..5 {
+            editor.move_cursor(Key::Up);
+        }
+        assert_eq!(editor.view.position, at(1, 0));
+        editor.scroll();
+        assert_eq!((editor.view.offset.y, editor.view.top_line), (0, 0));
+
+        // without soft wrap the rows scroll horizontally again
+        editor.config.soft_wrap = SoftWrap::Off;
+        editor.view.position = at(25, 0);
+        editor.scroll();
+        assert_eq!((editor.view.offset, editor.view.top_line), (at(16, 0), 0));
+    }
+
+    #[test]
+    fn buffers_keep_their_own_state() {
+        let mut editor = editor("first\n");
+        let dir = env::temp_dir().join(format!("buffers-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("second.rs");
+        fs::write(&path, "fn main() {}\n").unwrap();
+        editor.buffers.push(Buffer::from(
+            Document::open(path.to_str().unwrap()).unwrap(),
+        ));
+        editor.view.position = Position { x: 3, y: 0 };
+
+        editor.cycle_buffer(true);
+        assert_eq!(editor.current, 1);
+        assert_eq!(editor.view.position, Position::default());
+        assert_eq!(editor.document.file_type(), "Rust");
+        editor.view.position = Position { x: 2, y: 0 };
+        // going back from the first buffer wraps around
+        editor.cycle_buffer(false);
+        editor.cycle_buffer(false);
+        assert_eq!(editor.current, 1);
+        editor.cycle_buffer(true);
+        assert_eq!(editor.view.position, Position { x: 3, y: 0 });
+        assert_eq!(editor.document.file_type(), "No file type");
+
+        // every unsaved buffer is named when quitting
+        assert!(!editor.quit());
+        assert_eq!(
+            editor.status_message.text,
+            "WARNING! Unsaved changes in [No Name]*. Press CTRL-Q again to quit."
+        );
+
+        // pick a buffer by a part of its name
+        let chars = |text: String| -> Vec<Key> { text.chars().map(Key::Char).collect() };
+        send(&editor, &chars("second\n".to_string()));
+        editor.pick_buffer();
+        assert_eq!(editor.current, 1);
+        assert_eq!(editor.view.position, Position { x: 2, y: 0 });
+
+        // a file that is open already isn't opened twice
+        let other = dir
+            .join("..")
+            .join(dir.file_name().unwrap())
+            .join("second.rs");
+        send(&editor, &chars(format!("{}\n", other.display())));
+        editor.cycle_buffer(true);
+        editor.open_file();
+        assert_eq!((editor.current, editor.buffers.len()), (1, 2));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn switch_themes() {
+        let mut editor = editor("");
+        assert_eq!(editor.theme().name(), "default");
+        editor.cycle_theme();
+        assert_eq!(editor.config.theme, "light");
+        assert_eq!(editor.status_message.text, "Theme: light");
+        // the last theme is followed by the first one
+        for _ in 1..editor.themes.len() {
+            editor.cycle_theme();
+        }
+        assert_eq!(editor.theme().name(), "default");
+    }
+
+    #[test]
+    fn autosave_modified_files() {
+        let dir = env::temp_dir().join(format!("autosave-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("first.txt");
+        let second = dir.join("second.txt");
+        fs::write(&first, "one\n").unwrap();
+        fs::write(&second, "two\n").unwrap();
+        let open = |path: &Path| Document::open(path.to_str().unwrap()).unwrap();
+        let mut editor = editor("");
+        editor.document = open(&first);
+        editor.buffers.push(Buffer::from(open(&second)));
+        editor.buffers.push(Buffer::from(Document::default()));
+
+        editor.document.insert_str(&Position::default(), "1 ");
+        editor.switch_buffer(1);
+        editor.document.insert_str(&Position::default(), "2 ");
+        editor.switch_buffer(2);
+        // a buffer without a file is left alone
+        editor.document.insert_str(&Position::default(), "3 ");
+        editor.autosave();
+        assert_eq!(fs::read_to_string(&first).unwrap(), "1 one\n");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "2 two\n");
+        assert_eq!(editor.unsaved_buffers(), ["[No Name]*"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn autosave_keeps_changes_made_on_disk() {
+        let dir = env::temp_dir().join(format!("autosave-conflict-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        fs::write(&path, "ours\n").unwrap();
+        let mut editor = editor("");
+        editor.document = Document::open(path.to_str().unwrap()).unwrap();
+        editor.document.insert_str(&Position::default(), "1 ");
+
+        fs::write(&path, "theirs\n").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        // the second time must not take the first one as the report of the change
+        editor.autosave();
+        editor.autosave();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "theirs\n");
+        assert_eq!(
+            editor.status_message.text,
+            "WARN: the file has been changed by another program"
+        );
+        assert!(editor.document.in_conflict());
+        // the watching job has nothing left to report
+        assert!(!editor.document.changed_on_disk());
+
+        // reading the file again drops our changes once confirmed
+        send(&editor, &[Key::Char('y')]);
+        editor.invoke(Invocation::Reload);
+        assert_eq!(rows(&editor), ["theirs"]);
+        assert!(!editor.document.in_conflict());
+        editor.document.insert_str(&Position::default(), "2 ");
+        editor.autosave();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2 theirs\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn background_buffers_are_watched() {
+        let dir = env::temp_dir().join(format!("watch-buffers-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("first.txt");
+        let second = dir.join("second.txt");
+        fs::write(&first, "one\n").unwrap();
+        fs::write(&second, "two\n").unwrap();
+        let open = |path: &Path| Document::open(path.to_str().unwrap()).unwrap();
+        let mut editor = editor("");
+        editor.document = open(&first);
+        editor.buffers.push(Buffer::from(open(&second)));
+        editor.watch_files();
+        assert_eq!(
+            *editor.watched_files.lock().unwrap(),
+            [first.clone(), second.clone()]
+        );
+
+        editor.switch_buffer(1);
+        editor.document.insert_str(&Position::default(), "2 ");
+        editor.switch_buffer(0);
+        let file = fs::File::options().write(true).open(&second).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        editor.file_changed(&second);
+        assert_eq!(
+            editor.status_message.text,
+            format!(
+                "WARN: {}* has been changed by another program",
+                second.display()
+            )
+        );
+        assert!(editor.buffers[1].document.in_conflict());
+        editor.autosave();
+        assert_eq!(fs::read_to_string(&second).unwrap(), "two\n");
+        // the mark is kept until the buffer is saved
+        editor.switch_buffer(1);
+        assert_eq!(
+            editor.status_message.text,
+            "WARN: the file has been changed by another program"
+        );
+        editor.save();
+        assert!(!editor.document.in_conflict());
+        assert_eq!(fs::read_to_string(&second).unwrap(), "2 two\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn windows_share_buffers() {
+        let mut editor = editor(&"row\n".repeat(30));
+        resize(&mut editor, (41, 12));
+        editor.view.position = Position { x: 1, y: 8 };
+        editor.scroll();
+
+        let area = |x, y, width, height| Area {
+            x,
+            y,
+            width,
+            height,
+        };
+        // 11 lines are shared by two windows with a status bar each
+        editor.split_window(Split::Horizontal);
+        assert_eq!((editor.focus, editor.windows.len()), (1, 2));
+        assert_eq!(editor.area(), area(0, 6, 41, 5));
+        assert_eq!(editor.view.offset.y, 5);
+        assert_eq!(editor.cursor_on_screen(), Position { x: 1, y: 9 });
+
+        // each window has its own cursor
+        editor.view.position = Position { x: 0, y: 20 };
+        editor.scroll();
+        editor.split_window(Split::Vertical);
+        assert_eq!(editor.area(), area(21, 6, 20, 5));
+        editor.move_focus(Direction::Left);
+        assert_eq!(editor.focus, 1);
+        editor.move_focus(Direction::Up);
+        assert_eq!((editor.focus, editor.view.position.y), (0, 8));
+
+        // an edit shows in every window on the document
+        editor
+            .document
+            .insert_str(&Position { x: 0, y: 20 }, "new ");
+        let panes = editor.panes();
+        assert_eq!(panes.len(), 3);
+        assert!(panes
+            .iter()
+            .all(|pane| pane.document.row(20).unwrap().len() == 7));
+
+        // another buffer in the focused window leaves the others alone
+        editor.buffers.push(Buffer::from(Document::default()));
+        editor.switch_buffer(1);
+        let buffers: Vec<usize> = editor.panes().iter().map(|pane| pane.buffer).collect();
+        assert_eq!(buffers, [1, 0, 0]);
+        editor.move_focus(Direction::Down);
+        assert_eq!((editor.focus, editor.current), (1, 0));
+        assert_eq!(editor.view.position, Position { x: 0, y: 20 });
+
+        // the window beside takes the place of the closed one
+        editor.close_window();
+        assert_eq!((editor.focus, editor.windows.len()), (1, 2));
+        assert_eq!(editor.area(), area(0, 6, 41, 5));
+        editor.close_window();
+        editor.close_window();
+        assert_eq!(editor.status_message.text, "Can't close the last window");
+        assert_eq!((editor.focus, editor.current), (0, 1));
+        assert_eq!(editor.area().height, 11);
+    }
+
+    #[test]
+    fn key_sequences_run_commands() {
+        let mut editor = editor("");
+        let chord = vec![Key::Ctrl('k'), Key::Ctrl('h')];
+        editor.config.keymap.bind(chord.clone(), Command::LineStart);
+
+        let mut keys: Vec<Key> = "foo bar".chars().map(Key::Char).collect();
+        keys.extend([Key::CtrlLeft, Key::Backspace]);
+        keys.extend(chord);
+        keys.extend([Key::Ctrl('k'), Key::Char('x')]);
+        send(&editor, &keys);
+        for _ in 0..9 {
+            editor.process_key().unwrap();
+        }
+        assert_eq!(rows(&editor), ["foobar"]);
+        assert_eq!(editor.view.position, Position { x: 3, y: 0 });
+        editor.process_key().unwrap();
+        assert_eq!(editor.view.position, Position::default());
+
+        // an unbound sequence doesn't type its last key
+        editor.process_key().unwrap();
+        assert_eq!(editor.status_message.text, "ctrl-k x is not bound");
+        assert_eq!(editor.document.row(0).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn prompt_input_is_edited_with_commands() {
+        let mut editor = editor("");
+        editor
+            .config
+            .keymap
+            .bind(vec![Key::Ctrl('k'), Key::Ctrl('h')], Command::LineStart);
+
+        let keys = [
+            Key::Char('a'),
+            Key::Char('b'),
+            Key::Char('d'),
+            Key::Left,
+            Key::Char('c'),
+            Key::Home,
+            Key::Char('x'),
+            Key::Backspace,
+            Key::Delete,
+            Key::CtrlRight,
+            Key::Char(' '),
+            Key::Char('e'),
+            Key::CtrlLeft,
+            Key::Ctrl('k'),
+            Key::Ctrl('h'),
+            Key::Char('<'),
+            Key::Char('\n'),
+        ];
+        send(&editor, &keys);
+        let mut columns = Vec::new();
+        let input = editor
+            .prompt("Name: ", |editor, _, _| {
+                columns.push(editor.status_message.cursor);
+            })
+            .unwrap();
+        assert_eq!(input.as_deref(), Some("<bcd e"));
+        // the cursor is put after the prompt, in the message bar
+        assert_eq!(columns[..4], [Some(6), Some(7), Some(8), Some(9)]);
+        assert_eq!(editor.status_message.cursor, None);
+
+        send(&editor, &[Key::Char('q'), Key::Esc]);
+        assert_eq!(editor.prompt("Name: ", |_, _, _| {}).unwrap(), None);
+    }
+
+    #[test]
+    fn vim_modal_editing() {
+        let mut editor = editor("one two three\nfour five\nsix");
+        editor.vim = Some(Vim::default());
+        // ESC is written as `|`
+        let type_keys = |editor: &mut Editor, keys: &str| {
+            for c in keys.chars() {
+                let key = if c == '|' { Key::Esc } else { Key::Char(c) };
+                send(editor, &[key]);
+                editor.process_key().unwrap();
+            }
+        };
+        let mode = |editor: &Editor| editor.vim.as_ref().unwrap().mode();
+
+        type_keys(&mut editor, "d");
+        assert_eq!(editor.vim.as_ref().unwrap().pending(), "d");
+        type_keys(&mut editor, "w.");
+        assert_eq!(rows(&editor)[0], "three");
+        type_keys(&mut editor, "u");
+        assert_eq!(rows(&editor)[0], "two three");
+
+        // a change is repeated with the text typed in insert mode
+        type_keys(&mut editor, "cwxy");
+        assert_eq!(mode(&editor), Mode::Insert);
+        type_keys(&mut editor, "|");
+        assert_eq!(mode(&editor), Mode::Normal);
+        assert_eq!(editor.view.position, Position { x: 1, y: 0 });
+        type_keys(&mut editor, "j0.");
+        assert_eq!(rows(&editor), ["xy three", "xy five", "six"]);
+
+        // whole lines are yanked and pasted below the cursor row
+        type_keys(&mut editor, "ggyyjp");
+        assert_eq!(rows(&editor), ["xy three", "xy five", "xy three", "six"]);
+        type_keys(&mut editor, "Gdk");
+        assert_eq!(rows(&editor), ["xy three", "xy five"]);
+        assert_eq!(editor.view.position, Position { x: 0, y: 1 });
+
+        // the visual selection takes the grapheme under the cursor
+        type_keys(&mut editor, "ggve");
+        assert_eq!(mode(&editor), Mode::Visual);
+        type_keys(&mut editor, "d");
+        assert_eq!(rows(&editor)[0], " three");
+        assert_eq!(mode(&editor), Mode::Normal);
+
+        // the cursor stays on the last grapheme outside of insert mode
+        type_keys(&mut editor, "$x");
+        assert_eq!(rows(&editor)[0], " thre");
+        assert_eq!(editor.view.position, Position { x: 4, y: 0 });
+        type_keys(&mut editor, "2b9l");
+        assert_eq!(editor.view.position, Position { x: 4, y: 0 });
+        type_keys(&mut editor, "P");
+        assert_eq!(rows(&editor)[0], " three");
+    }
+
+    #[test]
+    fn emacs_kill_ring_and_search() {
+        let mut editor = editor("one two\nthree\nfour");
+        editor.clipboard = Clipboard::new(false);
+        editor.config.keymap = Keymap::emacs();
+        let run = |editor: &mut Editor, keys: &[Key]| {
+            for key in keys {
+                send(editor, &[*key]);
+                editor.process_key().unwrap();
+            }
+        };
+
+        // the lines killed one after another are joined
+        run(&mut editor, &[Key::Ctrl('k'), Key::Ctrl('k')]);
+        assert_eq!(rows(&editor), ["three", "four"]);
+        assert_eq!(editor.clipboard.text(), "one two\n");
+        run(
+            &mut editor,
+            &[
+                Key::Alt('f'),
+                Key::Ctrl('b'),
+                Key::Ctrl('b'),
+                Key::Ctrl('k'),
+            ],
+        );
+        assert_eq!(rows(&editor), ["thr", "four"]);
+
+        // M-y replaces the yanked text with the older kills in turn
+        run(&mut editor, &[Key::Ctrl('y')]);
+        assert_eq!(rows(&editor), ["three", "four"]);
+        run(&mut editor, &[Key::Alt('y')]);
+        assert_eq!(rows(&editor), ["throne two", "", "four"]);
+        run(&mut editor, &[Key::Alt('y')]);
+        assert_eq!(rows(&editor), ["three", "four"]);
+        assert_eq!(editor.view.position, Position { x: 5, y: 0 });
+        run(&mut editor, &[Key::Ctrl('a'), Key::Alt('y')]);
+        assert_eq!(
+            editor.status_message.text,
+            "The last command was not a yank"
+        );
+
+        // C-s and C-r search forward and backward
+        send(&editor, &[Key::Ctrl('s'), Key::Char('o'), Key::Char('\n')]);
+        editor.process_key().unwrap();
+        assert_eq!(editor.view.position, Position { x: 1, y: 1 });
+        // and move to the next match in the prompt
+        send(
+            &editor,
+            &[
+                Key::Ctrl('r'),
+                Key::Char('e'),
+                Key::Ctrl('r'),
+                Key::Char('\n'),
+            ],
+        );
+        editor.process_key().unwrap();
+        assert_eq!(editor.view.position, Position { x: 3, y: 0 });
+    }
+
+    #[test]
+    fn command_line_runs_typed_commands() {
+        let mut editor = editor("one\ntwo\nthree\nfour");
+        // run the line typed after ALT-X
+        let run = |editor: &mut Editor, keys: &[Key]| {
+            send(
+                editor,
+                &[&[Key::Alt('x')], keys, &[Key::Char('\n')]].concat(),
+            );
+            editor.process_key().unwrap();
+        };
+        let chars = |text: &str| -> Vec<Key> { text.chars().map(Key::Char).collect() };
+
+        run(&mut editor, &chars("set tabwidth=8"));
+        assert_eq!(editor.config.tab_width, 8);
+        run(&mut editor, &chars("goto 3"));
+        assert_eq!(editor.view.position, Position { x: 0, y: 2 });
+        // the line is clamped to the document
+        run(&mut editor, &chars(":120"));
+        assert_eq!(editor.view.position, Position { x: 0, y: 3 });
+
+        // UP recalls the lines run before
+        run(&mut editor, &[Key::Up, Key::Up]);
+        assert_eq!(editor.view.position, Position { x: 0, y: 2 });
+
+        // TAB goes through the completions
+        run(
+            &mut editor,
+            &[&chars("split")[..], &[Key::Char('\t'), Key::Char('\t')]].concat(),
+        );
+        assert_eq!(editor.windows.len(), 2);
+        assert!(matches!(editor.layout, Layout::Split(Split::Vertical, ..)));
+        run(
+            &mut editor,
+            &[
+                &chars("set so")[..],
+                &[Key::Char('\t'), Key::Char('w'), Key::Char('\t')],
+            ]
+            .concat(),
+        );
+        assert_eq!(editor.config.soft_wrap, SoftWrap::Words);
+
+        run(&mut editor, &chars("fly"));
+        assert_eq!(editor.status_message.text, "ERR: unknown command `fly`");
+        run(&mut editor, &chars("set theme=dark"));
+        assert_eq!(editor.status_message.text, "ERR: Unknown theme dark");
+        assert_eq!(editor.config.theme, "default");
+    }
+
+    #[test]
+    fn write_a_copy() {
+        let dir = env::temp_dir().join(format!("editor-write-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (original, copy) = (dir.join("original.txt"), dir.join("copy.txt"));
+        fs::write(&original, "one\n").unwrap();
+        let mut editor = editor("");
+        editor.document = Document::open(original.to_str().unwrap()).unwrap();
+        editor.document.insert_str(&Position::default(), "zero\n");
+        let write = |editor: &mut Editor, filename: &Path| {
+            let mut keys = vec![Key::Alt('x')];
+            keys.extend(format!("w {}\n", filename.display()).chars().map(Key::Char));
+            send(editor, &keys);
+            editor.process_key().unwrap();
+        };
+
+        write(&mut editor, &copy);
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "zero\none\n");
+        assert_eq!(
+            editor.status_message.text,
+            format!("Written to {}", copy.display())
+        );
+        // the buffer still belongs to its file, which isn't saved
+        assert_eq!(editor.document.filename.as_deref(), original.to_str());
+        assert!(editor.document.is_dirty());
+        assert_eq!(fs::read_to_string(&original).unwrap(), "one\n");
+
+        // a buffer without a file is saved to it
+        editor.document = Document::default();
+        editor.document.insert_str(&Position::default(), "new");
+        let named = dir.join("named.txt");
+        write(&mut editor, &named);
+        assert_eq!(editor.document.filename.as_deref(), named.to_str());
+        assert!(!editor.document.is_dirty());
+        assert_eq!(fs::read_to_string(&named).unwrap(), "new\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn goto_line_and_column() {
+        let text: Vec<String> = (1..=100).map(|line| format!("line {line}")).collect();
+        let mut editor = editor(&text.join("\n"));
+        // go to the target typed after CTRL-G
+        let goto = |editor: &mut Editor, target: &str| {
+            let mut keys = vec![Key::Ctrl('g')];
+            keys.extend(target.chars().map(Key::Char));
+            keys.push(Key::Char('\n'));
+            send(editor, &keys);
+            editor.process_key().unwrap();
+        };
+
+        goto(&mut editor, "50:3");
+        assert_eq!(editor.view.position, Position { x: 2, y: 49 });
+        // the row is in the middle of the 10 lines of text
+        assert_eq!(editor.view.offset.y, 44);
+        assert_eq!(editor.highlighted_row(&editor.focused_pane()), Some(49));
+
+        goto(&mut editor, "-9");
+        assert_eq!(editor.view.position, Position { x: 0, y: 40 });
+        goto(&mut editor, "+5:100");
+        assert_eq!(editor.view.position, Position { x: 7, y: 45 });
+
+        // the target is kept in the document
+        goto(&mut editor, "+1000");
+        assert_eq!(editor.view.position, Position { x: 0, y: 99 });
+        goto(&mut editor, "-1000:2");
+        assert_eq!(editor.view.position, Position { x: 1, y: 0 });
+        assert_eq!(editor.view.offset.y, 0);
+        goto(&mut editor, "0");
+        assert_eq!(editor.view.position, Position { x: 0, y: 0 });
+
+        goto(&mut editor, "12:x");
+        assert_eq!(
+            editor.status_message.text,
+            "ERR: expected a line like 120, 120:45, +10 or -10"
+        );
+
+        // the highlight fades
+        editor.highlighted = Some((0, 0, Instant::now()));
+        assert_eq!(editor.highlighted_row(&editor.focused_pane()), None);
+    }
+
+    #[test]
+    fn typed_word_is_undone_in_one_step() {
+        let mut editor = editor("");
+        let mut keys: Vec<Key> = "word".chars().map(Key::Char).collect();
+        keys.push(Key::Ctrl('z'));
+        send(&editor, &keys);
+        for _ in 0..4 {
+            editor.process_key().unwrap();
+        }
+        assert_eq!(rows(&editor), ["word"]);
+        editor.process_key().unwrap();
+        assert!(editor.document.is_empty());
+        assert_eq!(editor.view.position, Position::default());
+
+        // the selection replaced by a character is restored with it
+        send(&editor, &[Key::Ctrl('y'), Key::ShiftLeft, Key::ShiftLeft]);
+        for _ in 0..3 {
+            editor.process_key().unwrap();
+        }
+        send(&editor, &[Key::Char('x'), Key::Char('y'), Key::Ctrl('z')]);
+        editor.process_key().unwrap();
+        editor.process_key().unwrap();
+        assert_eq!(rows(&editor), ["woxy"]);
+        send(&editor, &[Key::Ctrl('z')]);
+        editor.process_key().unwrap();
+        assert_eq!(rows(&editor), ["wox"]);
+        editor.process_key().unwrap();
+        assert_eq!(rows(&editor), ["word"]);
+    }
+
+    #[test]
+    fn replace_wraps_around() {
+        let mut editor = editor("ab ab\nab");
+        editor.view.position = Position { x: 3, y: 0 };
+        let mut keys: Vec<Key> = "ab\nxyz\n".chars().map(Key::Char).collect();
+        keys.extend([Key::Char('y'), Key::Char('n'), Key::Char('y')]);
+        send(&editor, &keys);
+        editor.replace();
+        // the occurrence before the cursor is found after the ones below it
+        assert_eq!(rows(&editor), ["xyz xyz", "ab"]);
+        assert_eq!(editor.view.position, Position { x: 0, y: 0 });
+        assert_eq!(editor.status_message.text, "Replaced 2 occurrence(s)");
+        editor.document.undo();
+        assert_eq!(rows(&editor), ["ab ab", "ab"]);
+    }
+
+    #[test]
+    fn quit_names_the_bound_keys() {
+        let mut editor = editor("unsaved");
+        editor.quit_times = 3;
+        editor
+            .config
+            .keymap
+            .bind(vec![Key::Ctrl('q')], Command::Save);
+        editor
+            .config
+            .keymap
+            .bind(vec![Key::Ctrl('k'), Key::Char('q')], Command::Quit);
+        assert!(!editor.quit());
+        assert_eq!(
+            editor.status_message.text,
+            "WARNING! Unsaved changes in [No Name]*. Press CTRL-K Q again to quit."
+        );
+        // the shortest sequence is shown
+        editor.config.keymap.bind(vec![Key::F(10)], Command::Quit);
+        assert!(!editor.quit());
+        assert!(editor
+            .status_message
+            .text
+            .ends_with("Press F10 again to quit."));
+        // the command can still be run from the command line
+        editor.config.keymap = Keymap::default();
+        editor
+            .config
+            .keymap
+            .bind(vec![Key::Ctrl('q')], Command::Save);
+        assert!(!editor.quit());
+        assert!(editor
+            .status_message
+            .text
+            .ends_with("Run quit again to quit."));
+    }
+}
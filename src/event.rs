@@ -0,0 +1,248 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use termion::{event::Key, input::TermRead};
+
+/// how often the files being edited are checked for changes made by other programs
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// everything the editor reacts to
+pub enum Event {
+    Key(Key),
+    // the window has been resized, e.g. a tmux pane is split
+    Resize,
+    Timer(Timer),
+    // a file being edited has been modified by another program
+    FileChanged(PathBuf),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Timer {
+    // the status message should disappear
+    StatusMessage,
+    // the modified buffers should be saved
+    Autosave,
+    // the line moved to should not be highlighted anymore
+    Highlight,
+}
+
+pub type EventSender = Sender<Result<Event, io::Error>>;
+
+/// keys, signals and the messages of background jobs are sent to one channel,
+/// timers are kept here and go off while waiting for it.
+/// so the editor can sleep until something happens instead of polling
+pub struct EventLoop {
+    sender: EventSender,
+    receiver: Receiver<Result<Event, io::Error>>,
+    // each timer with the time it goes off
+    timers: Vec<(Timer, Instant)>,
+}
+
+impl EventLoop {
+    #[must_use]
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            timers: Vec::new(),
+        }
+    }
+
+    /// run `job` in the background, it can send events to the editor through the given sender
+    pub fn spawn<F>(&self, job: F)
+    where
+        F: FnOnce(EventSender) + Send + 'static,
+    {
+        let sender = self.sender.clone();
+        thread::spawn(move || job(sender));
+    }
+
+    /// set `timer` to go off at `deadline`, the earlier deadline of it is forgotten
+    pub fn schedule(&mut self, timer: Timer, deadline: Instant) {
+        self.timers.retain(|(scheduled, _)| *scheduled != timer);
+        self.timers.push((timer, deadline));
+    }
+
+    /// wait for the next event
+    ///
+    /// # Errors
+    ///
+    /// will return an error if the input can't be read
+    pub fn next(&mut self) -> Result<Event, io::Error> {
+        loop {
+            let received = match self.timers.iter().min_by_key(|(_, deadline)| *deadline) {
+                None => self
+                    .receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+                Some(&(timer, deadline)) => {
+                    let now = Instant::now();
+                    if deadline <= now {
+                        self.timers.retain(|(scheduled, _)| *scheduled != timer);
+                        return Ok(Event::Timer(timer));
+                    }
+                    self.receiver.recv_timeout(deadline.duration_since(now))
+                }
+            };
+            match received {
+                Ok(event) => return event,
+                // a timer goes off
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return Err(input_closed()),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn sender(&self) -> EventSender {
+        self.sender.clone()
+    }
+}
+
+fn input_closed() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "the input is closed")
+}
+
+/// send every key read from `input`.
+/// the event loop keeps a sender of its own, so the end of the input has to be sent as well
+pub fn read_keys(input: impl Read, sender: &EventSender) {
+    for key in input.keys() {
+        if sender.send(key.map(Event::Key)).is_err() {
+            return;
+        }
+    }
+    let _ = sender.send(Err(input_closed()));
+}
+
+/// poll the modification times of the files in `paths` and report every change of them, until `stop` is set.
+/// `paths` is shared with the editor, so that files can be opened, closed and renamed by "Save as"
+pub fn watch_files(paths: &Mutex<Vec<PathBuf>>, stop: &AtomicBool, sender: &EventSender) {
+    let modified = || -> Vec<(PathBuf, SystemTime)> {
+        let paths = paths.lock().map(|paths| paths.clone()).unwrap_or_default();
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let time = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some((path, time))
+            })
+            .collect()
+    };
+    let mut last = modified();
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(WATCH_INTERVAL);
+        let current = modified();
+        // a file which has just been added is not a change
+        for (path, time) in &current {
+            let changed = last.iter().any(|(a, t)| a == path && t != time);
+            if changed && sender.send(Ok(Event::FileChanged(path.clone()))).is_err() {
+                return;
+            }
+        }
+        last = current;
+    }
+}
+
+#[cfg(test)]
+mod event_tests {
+    use std::{
+        env, fs, io,
+        path::PathBuf,
+        process,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use termion::event::Key;
+
+    use super::{read_keys, watch_files, Event, EventLoop, Timer};
+
+    #[test]
+    fn timers_go_off_while_waiting() {
+        let mut events = EventLoop::new();
+        let start = Instant::now();
+        events.schedule(Timer::StatusMessage, start + Duration::from_secs(30));
+        // rescheduling replaces the earlier deadline
+        events.schedule(Timer::StatusMessage, start + Duration::from_millis(20));
+        events
+            .sender()
+            .send(Ok(Event::Key(Key::Char('a'))))
+            .unwrap();
+        assert!(matches!(events.next(), Ok(Event::Key(Key::Char('a')))));
+        assert!(matches!(
+            events.next(),
+            Ok(Event::Timer(Timer::StatusMessage))
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // background jobs talk to the editor through the channel
+        events.spawn(|sender| {
+            sender
+                .send(Ok(Event::FileChanged(PathBuf::from("a.txt"))))
+                .unwrap();
+        });
+        assert!(
+            matches!(events.next(), Ok(Event::FileChanged(path)) if path.as_os_str() == "a.txt")
+        );
+    }
+
+    #[test]
+    fn background_jobs_stop() {
+        let mut events = EventLoop::new();
+        events.spawn(|sender| read_keys(&b"q"[..], &sender));
+        assert!(matches!(events.next(), Ok(Event::Key(Key::Char('q')))));
+        // the end of the input is reported instead of waiting forever
+        assert!(matches!(events.next(), Err(e) if e.kind() == io::ErrorKind::UnexpectedEof));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let watching = Arc::clone(&stop);
+        let watcher = thread::spawn(move || {
+            watch_files(&Mutex::new(Vec::new()), &watching, &events.sender());
+        });
+        stop.store(true, Ordering::Relaxed);
+        watcher.join().unwrap();
+    }
+
+    #[test]
+    fn every_file_is_watched() {
+        let dir = env::temp_dir().join(format!("watch-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<PathBuf> = ["a.txt", "b.txt"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+        for path in &paths {
+            fs::write(path, "x").unwrap();
+        }
+        let mut events = EventLoop::new();
+        let stop = Arc::new(AtomicBool::new(false));
+        let watching = Arc::clone(&stop);
+        let files = Mutex::new(paths.clone());
+        let sender = events.sender();
+        let watcher = thread::spawn(move || watch_files(&files, &watching, &sender));
+
+        // not the first file, which would be the one in the focused window
+        thread::sleep(Duration::from_millis(200));
+        let file = fs::File::options().write(true).open(&paths[1]).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert!(matches!(events.next(), Ok(Event::FileChanged(path)) if path == paths[1]));
+        stop.store(true, Ordering::Relaxed);
+        watcher.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,22 +1,95 @@
-use std::fs::File;
+use std::{ffi::OsStr, fmt, path::Path};
 
+use serde_json::{Map, Value};
+
+use crate::languages;
+
+#[derive(Clone)]
 pub struct FileType {
     name: String,
+    // suffixes of the files in this language, e.g. `rs`
+    extensions: Vec<String>,
+    // glob patterns of the whole file name, e.g. `Makefile` or `*.mk`
+    filenames: Vec<String>,
+    // glob patterns of the interpreter in the shebang, e.g. `python*`
+    shebangs: Vec<String>,
+    // other names used by modelines, e.g. `make` in `vim: ft=make`
+    aliases: Vec<String>,
     hl_opts: HighlightingOptions,
 }
 
-/// this structure will hold a series of bool value representing the highlighting options
+/// this structure holds the rules used to highlight a row
 #[derive(Default, Clone, Debug)]
 pub struct HighlightingOptions {
-    // whether numbers need to be highlighted
-    numbers: bool,
-    strings: bool,
+    // `None` if numbers don't need to be highlighted
+    numbers: Option<NumberStyle>,
+    strings: Vec<StringDelimiter>,
+    // the character which escapes the next one in a string, e.g. `\`
+    escape: Option<char>,
+    // character literals like `'a'`
     characters: bool,
-    comments: bool,
+    // e.g. `//`
+    line_comments: Vec<String>,
+    // e.g. `/*` and `*/`
+    block_comments: Vec<(String, String)>,
     primary_keys: Vec<String>,
     secondary_keys: Vec<String>,
 }
 
+/// the kinds of number literals of a language besides plain decimals
+#[derive(Default, Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct NumberStyle {
+    // `0x1f`
+    pub hex: bool,
+    // `0b101`
+    pub binary: bool,
+    // `0o17`
+    pub octal: bool,
+    // `1.5` and `1e-3`
+    pub float: bool,
+    // type suffixes like `u32`
+    pub suffixes: Vec<String>,
+    // digits can be grouped by it, e.g. `_` in `1_000`
+    pub separator: Option<char>,
+}
+
+/// a token which opens and closes a string, e.g. `"`
+#[derive(Clone, Debug)]
+pub struct StringDelimiter {
+    pub token: String,
+    // whether the string can continue on the next row
+    pub multiline: bool,
+}
+
+/// a mistake in a language definition.
+/// `path` tells where it is, e.g. `rs.strings.escape`
+#[derive(Debug, PartialEq)]
+pub struct SchemaError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl SchemaError {
+    #[must_use]
+    pub fn new(path: &str, message: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
 impl FileType {
     #[must_use]
     pub fn name(&self) -> String {
@@ -27,57 +100,387 @@ impl FileType {
     pub fn highlighting_opts(&self) -> &HighlightingOptions {
         &self.hl_opts
     }
+
+    /// whether a file called `filename` is written in this language,
+    /// judging by its suffix or the whole name
+    #[must_use]
+    pub fn matches(&self, filename: &str) -> bool {
+        let Some(name) = Path::new(filename).file_name().and_then(OsStr::to_str) else {
+            return false;
+        };
+        let suffix = Path::new(name).extension().and_then(OsStr::to_str);
+        suffix.is_some_and(|suffix| self.extensions.iter().any(|ext| ext == suffix))
+            || self
+                .filenames
+                .iter()
+                .any(|pattern| glob_match(pattern, name))
+    }
+
+    /// whether a script run by `interpreter`, e.g. `python3`, is written in this language
+    #[must_use]
+    pub fn runs_on(&self, interpreter: &str) -> bool {
+        self.shebangs
+            .iter()
+            .any(|pattern| glob_match(pattern, interpreter))
+    }
+
+    /// whether `name` from a modeline refers to this language.
+    /// the display name, the key of the definition, the suffixes and the aliases are all accepted
+    #[must_use]
+    pub fn is_called(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self
+                .aliases
+                .iter()
+                .chain(&self.extensions)
+                .any(|alias| alias.eq_ignore_ascii_case(name))
+    }
+}
+
+/// the name of the program in the shebang of `line`,
+/// e.g. `python3` in `#!/usr/bin/env python3` or `bash` in `#!/bin/bash -e`
+#[must_use]
+pub fn interpreter(line: &str) -> Option<&str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    if program != "env" {
+        return Some(program);
+    }
+    // skip the options and variables of env, e.g. `env -S VAR=1 python3`
+    words.find(|word| !word.starts_with('-') && !word.contains('='))
+}
+
+/// the language set by an Emacs modeline like `-*- mode: python -*-`.
+/// like Emacs, it is only looked for in the first row, or in the second one after a shebang
+#[must_use]
+pub fn emacs_modeline(line: &str) -> Option<&str> {
+    let start = line.find("-*-")?;
+    let rest = &line[start.saturating_add(3)..];
+    let settings = &rest[..rest.find("-*-")?];
+    if !settings.contains(':') {
+        // `-*- python -*-`
+        return Some(settings.trim()).filter(|mode| !mode.is_empty());
+    }
+    settings.split(';').find_map(|setting| {
+        let (key, value) = setting.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case("mode")
+            .then_some(value.trim())
+    })
+}
+
+/// the language set by a Vim modeline like `vim: set ft=python:`.
+/// only the leader of a comment, like `#` or `//`, may come before the marker,
+/// so that a sentence mentioning `vim:` isn't taken for a modeline
+#[must_use]
+pub fn vim_modeline(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(index, _)| {
+                let before = &line[..*index];
+                before.chars().next_back().is_none_or(char::is_whitespace)
+                    && before.trim().chars().all(|c| c.is_ascii_punctuation())
+            })
+            .map(|(index, _)| index.saturating_add(marker.len()))
+    })?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            option
+                .strip_prefix("ft=")
+                .or_else(|| option.strip_prefix("filetype="))
+        })
+        .filter(|name| !name.is_empty())
 }
 
 impl Default for FileType {
     fn default() -> Self {
         Self {
             name: String::from("No file type"),
+            extensions: Vec::new(),
+            filenames: Vec::new(),
+            shebangs: Vec::new(),
+            aliases: Vec::new(),
             hl_opts: HighlightingOptions::default(),
         }
     }
 }
 
-impl From<String> for FileType {
-    fn from(filename: String) -> Self {
+impl FileType {
+    /// parse the languages defined in `source`, a JSON object like `{ "rs": { ... } }`.
+    /// every key of the definition is checked, so that a typo doesn't go unnoticed
+    ///
+    /// # Errors
+    ///
+    /// will return the first mistake found in the definition
+    pub fn parse(source: &str) -> Result<Vec<Self>, SchemaError> {
+        let value: Value =
+            serde_json::from_str(source).map_err(|e| SchemaError::new("", &e.to_string()))?;
+        let languages = as_object(&value, "")?;
+        languages
+            .iter()
+            .map(|(name, language)| Self::parse_language(name, language))
+            .collect()
+    }
 
-        if let Some(suffix) = std::path::Path::new(&filename).extension() {
-            if suffix.to_str().is_none() {
-                return FileType::default();
-            }
-            let suffix = suffix.to_str().unwrap();
-            let kw_path = "src/highlightkeys/".to_string() + suffix + ".json";
-            let mut primary_keys = vec![];
-            let mut secondary_keys = vec![];
-            let keywords: serde_json::Value =
-                serde_json::from_reader(File::open(kw_path).unwrap()).unwrap();
-            keywords[suffix]["primary_keys"]
-                .as_array()
-                .unwrap()
-                .iter()
-                .for_each(|key| {
-                    primary_keys.push(key.as_str().unwrap().to_string());
-                });
-            keywords[suffix]["secondary_keys"]
-                .as_array()
-                .unwrap()
-                .iter()
-                .for_each(|key| {
-                    secondary_keys.push(key.as_str().unwrap().to_string());
-                });
-            return Self {
-                name: suffix.to_string(),
-                hl_opts: HighlightingOptions {
-                    numbers: true,
-                    strings: true,
-                    characters: true,
-                    comments: true,
-                    primary_keys,
-                    secondary_keys,
+    fn parse_language(name: &str, value: &Value) -> Result<Self, SchemaError> {
+        let language = as_object(value, name)?;
+        check_keys(
+            language,
+            name,
+            &[
+                "name",
+                "extensions",
+                "filenames",
+                "shebangs",
+                "aliases",
+                "comments",
+                "strings",
+                "characters",
+                "numbers",
+                "primary_keys",
+                "secondary_keys",
+            ],
+        )?;
+        let path = |key: &str| format!("{name}.{key}");
+        let display_name = match language.get("name") {
+            None => name.to_string(),
+            Some(value) => value
+                .as_str()
+                .map(String::from)
+                .ok_or_else(|| SchemaError::new(&path("name"), "expected a string"))?,
+        };
+        let mut extensions = as_strings(language.get("extensions"), &path("extensions"))?;
+        if language.get("extensions").is_none() {
+            extensions.push(name.to_string());
+        }
+        let filenames = as_strings(language.get("filenames"), &path("filenames"))?;
+        let shebangs = as_strings(language.get("shebangs"), &path("shebangs"))?;
+        let mut aliases = as_strings(language.get("aliases"), &path("aliases"))?;
+        aliases.push(name.to_string());
+
+        let (line_comments, block_comments) = match language.get("comments") {
+            None => (Vec::new(), Vec::new()),
+            Some(value) => parse_comments(value, &path("comments"))?,
+        };
+        let (strings, escape) = match language.get("strings") {
+            None => (
+                vec![StringDelimiter {
+                    token: String::from("\""),
+                    multiline: false,
+                }],
+                Some('\\'),
+            ),
+            Some(value) => parse_strings(value, &path("strings"))?,
+        };
+        let numbers = match language.get("numbers") {
+            None | Some(Value::Bool(true)) => Some(NumberStyle {
+                float: true,
+                ..NumberStyle::default()
+            }),
+            Some(Value::Bool(false)) => None,
+            Some(value) => Some(parse_numbers(value, &path("numbers"))?),
+        };
+        Ok(Self {
+            name: display_name,
+            extensions,
+            filenames,
+            shebangs,
+            aliases,
+            hl_opts: HighlightingOptions {
+                numbers,
+                strings,
+                escape,
+                characters: as_bool(language.get("characters"), &path("characters"), false)?,
+                line_comments,
+                block_comments,
+                primary_keys: as_strings(language.get("primary_keys"), &path("primary_keys"))?,
+                secondary_keys: as_strings(
+                    language.get("secondary_keys"),
+                    &path("secondary_keys"),
+                )?,
+            },
+        })
+    }
+}
+
+type Object = Map<String, Value>;
+// the line comment tokens and the block comment pairs
+type Comments = (Vec<String>, Vec<(String, String)>);
+
+fn parse_comments(value: &Value, path: &str) -> Result<Comments, SchemaError> {
+    let comments = as_object(value, path)?;
+    check_keys(comments, path, &["line", "block"])?;
+    let line = non_empty(
+        as_strings(comments.get("line"), &format!("{path}.line"))?,
+        path,
+    )?;
+    let path = format!("{path}.block");
+    let block = match comments.get("block") {
+        None => Vec::new(),
+        Some(Value::Array(pairs)) => pairs
+            .iter()
+            .map(|pair| match as_strings(Some(pair), &path)?.as_slice() {
+                [start, end] if !start.is_empty() && !end.is_empty() => {
+                    Ok((start.clone(), end.clone()))
                 }
-            };
+                _ => Err(SchemaError::new(
+                    &path,
+                    "expected pairs of start and end tokens",
+                )),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => {
+            return Err(SchemaError::new(
+                &path,
+                "expected an array of [start, end] pairs",
+            ))
+        }
+    };
+    Ok((line, block))
+}
+
+fn parse_strings(
+    value: &Value,
+    path: &str,
+) -> Result<(Vec<StringDelimiter>, Option<char>), SchemaError> {
+    let strings = as_object(value, path)?;
+    check_keys(strings, path, &["delimiters", "escape", "multiline"])?;
+    let delimiters = non_empty(
+        as_strings(strings.get("delimiters"), &format!("{path}.delimiters"))?,
+        path,
+    )?;
+    let escape = match strings.get("escape") {
+        None => Some('\\'),
+        Some(Value::Null) => None,
+        Some(value) => Some(as_char(value, &format!("{path}.escape"))?),
+    };
+    // either all strings can span rows, or only those opened by the listed delimiters
+    let multiline_path = format!("{path}.multiline");
+    let multiline: Vec<String> = match strings.get("multiline") {
+        None | Some(Value::Bool(false)) => Vec::new(),
+        Some(Value::Bool(true)) => delimiters.clone(),
+        value => as_strings(value, &multiline_path)?,
+    };
+    if let Some(unknown) = multiline.iter().find(|token| !delimiters.contains(token)) {
+        return Err(SchemaError::new(
+            &multiline_path,
+            &format!("`{unknown}` is not one of the delimiters"),
+        ));
+    }
+    let delimiters = delimiters
+        .into_iter()
+        .map(|token| StringDelimiter {
+            multiline: multiline.contains(&token),
+            token,
+        })
+        .collect();
+    Ok((delimiters, escape))
+}
+
+fn parse_numbers(value: &Value, path: &str) -> Result<NumberStyle, SchemaError> {
+    let numbers = as_object(value, path)?;
+    check_keys(
+        numbers,
+        path,
+        &["hex", "binary", "octal", "float", "suffixes", "separator"],
+    )?;
+    let flag =
+        |key: &str, default: bool| as_bool(numbers.get(key), &format!("{path}.{key}"), default);
+    Ok(NumberStyle {
+        hex: flag("hex", false)?,
+        binary: flag("binary", false)?,
+        octal: flag("octal", false)?,
+        float: flag("float", true)?,
+        suffixes: non_empty(
+            as_strings(numbers.get("suffixes"), &format!("{path}.suffixes"))?,
+            path,
+        )?,
+        separator: numbers
+            .get("separator")
+            .map(|value| as_char(value, &format!("{path}.separator")))
+            .transpose()?,
+    })
+}
+
+fn as_object<'a>(value: &'a Value, path: &str) -> Result<&'a Object, SchemaError> {
+    value
+        .as_object()
+        .ok_or_else(|| SchemaError::new(path, "expected an object"))
+}
+
+fn as_bool(value: Option<&Value>, path: &str, default: bool) -> Result<bool, SchemaError> {
+    value.map_or(Ok(default), |value| {
+        value
+            .as_bool()
+            .ok_or_else(|| SchemaError::new(path, "expected true or false"))
+    })
+}
+
+fn as_char(value: &Value, path: &str) -> Result<char, SchemaError> {
+    let mut chars = value.as_str().unwrap_or_default().chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(SchemaError::new(path, "expected a single character")),
+    }
+}
+
+/// a single string is accepted as an array of one string
+fn as_strings(value: Option<&Value>, path: &str) -> Result<Vec<String>, SchemaError> {
+    let error = || SchemaError::new(path, "expected a string or an array of strings");
+    match value {
+        None => Ok(Vec::new()),
+        Some(Value::String(s)) => Ok(vec![s.clone()]),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| value.as_str().map(String::from).ok_or_else(error))
+            .collect(),
+        Some(_) => Err(error()),
+    }
+}
+
+/// tokens are matched against the text, an empty one would match everywhere
+fn non_empty(tokens: Vec<String>, path: &str) -> Result<Vec<String>, SchemaError> {
+    if tokens.iter().any(String::is_empty) {
+        return Err(SchemaError::new(path, "tokens must not be empty"));
+    }
+    Ok(tokens)
+}
+
+fn check_keys(object: &Object, path: &str, known: &[&str]) -> Result<(), SchemaError> {
+    match object.keys().find(|key| !known.contains(&key.as_str())) {
+        Some(key) => Err(SchemaError::new(
+            &format!("{path}.{key}"),
+            &format!("unknown key, expected one of {}", known.join(", ")),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// match `name` against a pattern where `*` stands for any text and `?` for any character
+fn glob_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+            Some((c, rest)) => name
+                .split_first()
+                .is_some_and(|(n, name)| (*c == '?' || c == n) && matches(rest, name)),
         }
-        FileType::default()
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
+impl From<String> for FileType {
+    /// find out the file type by the name of `filename` only,
+    /// a file of an unknown language gets the default file type
+    fn from(filename: String) -> Self {
+        languages::registry()
+            .detect(Some(&filename), &[])
+            .cloned()
+            .unwrap_or_default()
     }
 }
 
@@ -88,36 +491,50 @@ impl From<&str> for FileType {
 }
 
 impl HighlightingOptions {
-    // we can just use self here instead of &self
-    // because rust can deal with value faster if it's small enough
-    // TODO: why?
-    pub fn numbers(&self) -> bool {
-        self.numbers
+    #[must_use]
+    pub fn numbers(&self) -> Option<&NumberStyle> {
+        self.numbers.as_ref()
     }
 
-    pub fn strings(&self) -> bool {
-        self.strings
+    #[must_use]
+    pub fn strings(&self) -> &[StringDelimiter] {
+        &self.strings
     }
 
+    #[must_use]
+    pub fn escape(&self) -> Option<char> {
+        self.escape
+    }
+
+    #[must_use]
     pub fn characters(&self) -> bool {
         self.characters
     }
 
-    pub fn comments(&self) -> bool {
-        self.comments
+    #[must_use]
+    pub fn line_comments(&self) -> &[String] {
+        &self.line_comments
+    }
+
+    #[must_use]
+    pub fn block_comments(&self) -> &[(String, String)] {
+        &self.block_comments
     }
 
+    #[must_use]
     pub fn primary_keys(&self) -> &Vec<String> {
         &self.primary_keys
     }
 
+    #[must_use]
     pub fn secondary_keys(&self) -> &Vec<String> {
         &self.secondary_keys
     }
 }
 
+#[cfg(test)]
 mod test {
-    #[warn(unused_imports)]
+    use super::{glob_match, SchemaError};
     use crate::FileType;
 
     #[test]
@@ -125,4 +542,92 @@ mod test {
         let filetype = FileType::from("editor.rs");
         println!("{:#?}", filetype.highlighting_opts());
     }
+
+    #[test]
+    fn unknown_suffix_falls_back_to_default() {
+        assert_eq!(
+            FileType::from("main.unknown").name(),
+            FileType::default().name()
+        );
+        assert_eq!(FileType::from("dir/editor.rs").name(), "Rust");
+        assert_eq!(FileType::from("dir/Makefile").name(), "Makefile");
+    }
+
+    #[test]
+    fn parse_definition() {
+        let languages = FileType::parse(
+            r##"{
+                "py": {
+                    "name": "Python",
+                    "extensions": ["py", "pyw"],
+                    "filenames": ["SConstruct", "*.pythonrc"],
+                    "shebangs": ["python*"],
+                    "comments": { "line": "#" },
+                    "strings": {
+                        "delimiters": ["\"\"\"", "\"", "'"],
+                        "multiline": ["\"\"\""]
+                    },
+                    "numbers": { "hex": true, "suffixes": ["j"], "separator": "_" }
+                },
+                "txt": { "strings": { "delimiters": [], "escape": null }, "numbers": false }
+            }"##,
+        )
+        .unwrap();
+        let python = &languages[0];
+        assert_eq!(python.name(), "Python");
+        assert!(python.matches("dir/a.pyw"));
+        assert!(python.matches("SConstruct"));
+        assert!(python.matches(".pythonrc"));
+        assert!(!python.matches("a.rs"));
+        let opts = python.highlighting_opts();
+        assert_eq!(opts.line_comments(), ["#"]);
+        assert!(opts.strings()[0].multiline && !opts.strings()[1].multiline);
+        assert_eq!(opts.escape(), Some('\\'));
+        let numbers = opts.numbers().unwrap();
+        assert!(numbers.hex && numbers.float && !numbers.binary);
+        assert_eq!(numbers.separator, Some('_'));
+
+        // the suffix is the name of the language by default
+        let text = &languages[1];
+        assert!(text.matches("a.txt"));
+        assert!(text.highlighting_opts().strings().is_empty());
+        assert!(text.highlighting_opts().numbers().is_none());
+    }
+
+    #[test]
+    fn report_schema_errors() {
+        let error = |source: &str| FileType::parse(source).err().unwrap();
+        assert_eq!(
+            error(r#"{"rs": {"line_comment": "//"}}"#).path,
+            "rs.line_comment"
+        );
+        assert_eq!(
+            error(r#"{"rs": {"strings": {"escape": "ab"}}}"#),
+            SchemaError::new("rs.strings.escape", "expected a single character")
+        );
+        assert_eq!(
+            error(r#"{"rs": {"comments": {"block": [["/*"]]}}}"#).path,
+            "rs.comments.block"
+        );
+        assert_eq!(
+            error(r#"{"rs": {"strings": {"delimiters": "'", "multiline": ["\""]}}}"#).path,
+            "rs.strings.multiline"
+        );
+        assert_eq!(
+            error(r#"{"rs": {"primary_keys": 1}}"#).path,
+            "rs.primary_keys"
+        );
+        assert_eq!(error(r#"{"rs": []}"#).path, "rs");
+        assert!(error("{").path.is_empty());
+    }
+
+    #[test]
+    fn glob_match_test() {
+        assert!(glob_match("Makefile", "Makefile"));
+        assert!(glob_match("*.mk", "rules.mk"));
+        assert!(glob_match("Dockerfile*", "Dockerfile.dev"));
+        assert!(glob_match("?.c", "a.c"));
+        assert!(!glob_match("*.mk", "rules.mkx"));
+        assert!(!glob_match("?.c", "ab.c"));
+    }
 }
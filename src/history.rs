@@ -0,0 +1,325 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::Position;
+
+/// max number of undo steps we keep, the oldest step will be dropped when it is exceeded
+const HISTORY_LIMIT: usize = 1000;
+
+/// a single reversible modification of the document.
+/// `text` may contain '\n', which means the edit crosses row boundaries
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    Insert { at: Position, text: String },
+    Delete { at: Position, text: String },
+}
+
+impl Edit {
+    /// the edit which can cancel the effect of this edit
+    #[must_use]
+    pub fn inverse(&self) -> Self {
+        match self {
+            Edit::Insert { at, text } => Edit::Delete {
+                at: at.clone(),
+                text: text.clone(),
+            },
+            Edit::Delete { at, text } => Edit::Insert {
+                at: at.clone(),
+                text: text.clone(),
+            },
+        }
+    }
+
+    /// the position where the edit happens
+    #[must_use]
+    pub fn start(&self) -> Position {
+        let (Edit::Insert { at, .. } | Edit::Delete { at, .. }) = self;
+        at.clone()
+    }
+
+    /// the position right after the text of the edit
+    #[must_use]
+    pub fn end(&self) -> Position {
+        let (Edit::Insert { at, text } | Edit::Delete { at, text }) = self;
+        let mut end = at.clone();
+        for g in text.graphemes(true) {
+            if g == "\n" {
+                end.y = end.y.saturating_add(1);
+                end.x = 0;
+            } else {
+                end.x = end.x.saturating_add(1);
+            }
+        }
+        end
+    }
+
+    /// whether this edit is a single typed (or deleted) character on one row
+    fn is_single_char(&self) -> bool {
+        let (Edit::Insert { text, .. } | Edit::Delete { text, .. }) = self;
+        text != "\n" && text.graphemes(true).count() == 1
+    }
+}
+
+/// a group of edits which will be undone or redone in one step
+struct Transaction {
+    // used to find out whether the document is at the saved state
+    id: usize,
+    edits: Vec<Edit>,
+    // a sealed transaction won't accept new edits
+    sealed: bool,
+    // the number of rows before the first edit, the first edit of an empty document adds a row
+    rows: usize,
+}
+
+impl Transaction {
+    /// runs of typed characters, backspaces or deletes are merged into one undo step
+    fn can_merge(&self, edit: &Edit) -> bool {
+        if self.sealed || !edit.is_single_char() {
+            return false;
+        }
+        let Some(last) = self.edits.last() else {
+            return false;
+        };
+        if !last.is_single_char() {
+            return false;
+        }
+        match (last, edit) {
+            (Edit::Insert { .. }, Edit::Insert { at, .. }) => *at == last.end(),
+            (Edit::Delete { at: last_at, .. }, Edit::Delete { at, .. }) => {
+                // DELETE removes the character at the same position,
+                // BACKSPACE removes the character before the last one
+                at.y == last_at.y && (at.x == last_at.x || at.x.saturating_add(1) == last_at.x)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// keep track of edits so that they can be undone and redone
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    // id of the transaction on top of the undo stack when the document was saved
+    saved: Option<usize>,
+    // id of the last transaction dropped from the bottom of the undo stack,
+    // undoing everything left comes back to the state after it, not to the opened one
+    base: Option<usize>,
+    next_id: usize,
+    // all edits are merged into one undo step during a transaction
+    in_transaction: bool,
+}
+
+impl History {
+    /// record an edit that has been applied to the document, which had `rows` rows before it.
+    /// all redoable edits are dropped because they are based on an outdated state
+    pub fn record(&mut self, edit: Edit, rows: usize) {
+        self.redo_stack.clear();
+        if let Some(last) = self.undo_stack.last_mut() {
+            if (self.in_transaction && !last.sealed) || last.can_merge(&edit) {
+                last.edits.push(edit);
+                return;
+            }
+        }
+        self.next_id = self.next_id.saturating_add(1);
+        self.undo_stack.push(Transaction {
+            id: self.next_id,
+            edits: vec![edit],
+            sealed: false,
+            rows,
+        });
+        if self.undo_stack.len() > HISTORY_LIMIT {
+            let dropped = self.undo_stack.remove(0);
+            self.base = Some(dropped.id);
+        }
+    }
+
+    /// edits recorded before `commit` will be undone in one step
+    pub fn begin(&mut self) {
+        self.seal();
+        self.in_transaction = true;
+    }
+
+    pub fn commit(&mut self) {
+        self.in_transaction = false;
+        self.seal();
+    }
+
+    /// the following edits won't be merged into the current undo step
+    pub fn seal(&mut self) {
+        if let Some(last) = self.undo_stack.last_mut() {
+            last.sealed = true;
+        }
+    }
+
+    /// take the edits that can cancel the last undo step, the last edit comes first,
+    /// with the number of rows before the step
+    pub fn undo(&mut self) -> Option<(Vec<Edit>, usize)> {
+        let mut transaction = self.undo_stack.pop()?;
+        transaction.sealed = true;
+        let edits = transaction.edits.iter().rev().map(Edit::inverse).collect();
+        let rows = transaction.rows;
+        self.redo_stack.push(transaction);
+        Some((edits, rows))
+    }
+
+    /// take the edits of the last undone step so that they can be applied again
+    pub fn redo(&mut self) -> Option<Vec<Edit>> {
+        let transaction = self.redo_stack.pop()?;
+        let edits = transaction.edits.clone();
+        self.undo_stack.push(transaction);
+        Some(edits)
+    }
+
+    /// remember current state as the saved one
+    pub fn mark_saved(&mut self) {
+        self.seal();
+        self.saved = self.current();
+    }
+
+    /// whether the document has been modified since it was saved (or opened)
+    #[must_use]
+    pub fn is_modified(&self) -> bool {
+        self.current() != self.saved
+    }
+
+    fn current(&self) -> Option<usize> {
+        self.undo_stack
+            .last()
+            .map(|transaction| transaction.id)
+            .or(self.base)
+    }
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::{Edit, History, HISTORY_LIMIT};
+    use crate::Position;
+
+    fn insert(x: usize, y: usize, text: &str) -> Edit {
+        Edit::Insert {
+            at: Position { x, y },
+            text: text.to_string(),
+        }
+    }
+
+    fn delete(x: usize, y: usize, text: &str) -> Edit {
+        Edit::Delete {
+            at: Position { x, y },
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn typed_characters_are_grouped() {
+        let mut history = History::default();
+        history.record(insert(0, 0, "a"), 1);
+        history.record(insert(1, 0, "b"), 1);
+        history.record(insert(2, 0, "c"), 1);
+        let edits = history.undo().unwrap().0;
+        assert_eq!(
+            edits,
+            vec![delete(2, 0, "c"), delete(1, 0, "b"), delete(0, 0, "a")]
+        );
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn newline_breaks_group() {
+        let mut history = History::default();
+        history.record(insert(0, 0, "a"), 1);
+        history.record(insert(1, 0, "\n"), 1);
+        history.record(insert(0, 1, "b"), 1);
+        assert_eq!(history.undo().unwrap().0, vec![delete(0, 1, "b")]);
+        assert_eq!(history.undo().unwrap().0, vec![delete(1, 0, "\n")]);
+        assert_eq!(history.undo().unwrap().0, vec![delete(0, 0, "a")]);
+    }
+
+    #[test]
+    fn backspaces_are_grouped() {
+        let mut history = History::default();
+        history.record(delete(2, 0, "c"), 1);
+        history.record(delete(1, 0, "b"), 1);
+        history.record(insert(1, 0, "x"), 1);
+        assert_eq!(history.undo().unwrap().0, vec![delete(1, 0, "x")]);
+        assert_eq!(
+            history.undo().unwrap().0,
+            vec![insert(1, 0, "b"), insert(2, 0, "c")]
+        );
+    }
+
+    #[test]
+    fn transaction_is_one_step() {
+        let mut history = History::default();
+        history.record(insert(0, 0, "a"), 1);
+        history.begin();
+        history.record(delete(0, 0, "a"), 1);
+        history.record(insert(0, 0, "bc"), 1);
+        history.record(insert(5, 1, "\n"), 1);
+        history.commit();
+        history.record(insert(0, 2, "d"), 1);
+        assert_eq!(history.undo().unwrap().0, vec![delete(0, 2, "d")]);
+        assert_eq!(
+            history.undo().unwrap().0,
+            vec![delete(5, 1, "\n"), delete(0, 0, "bc"), insert(0, 0, "a")]
+        );
+        assert_eq!(history.undo().unwrap().0, vec![delete(0, 0, "a")]);
+    }
+
+    #[test]
+    fn redo_is_dropped_after_new_edit() {
+        let mut history = History::default();
+        history.record(insert(0, 0, "a"), 1);
+        history.undo();
+        assert_eq!(history.redo().unwrap(), vec![insert(0, 0, "a")]);
+        history.undo();
+        history.record(insert(0, 0, "b"), 1);
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn saved_state() {
+        let mut history = History::default();
+        assert!(!history.is_modified());
+        history.record(insert(0, 0, "a"), 1);
+        assert!(history.is_modified());
+        history.mark_saved();
+        assert!(!history.is_modified());
+        // the saved step is sealed, so the new character starts another step
+        history.record(insert(1, 0, "b"), 1);
+        assert!(history.is_modified());
+        history.undo();
+        assert!(!history.is_modified());
+        history.undo();
+        assert!(history.is_modified());
+        history.redo();
+        assert!(!history.is_modified());
+    }
+
+    #[test]
+    fn saved_state_after_dropping_old_steps() {
+        // every row is a step of its own
+        let mut history = History::default();
+        for y in 0..=HISTORY_LIMIT {
+            history.record(insert(0, y, "\n"), 1);
+        }
+        for _ in 0..HISTORY_LIMIT {
+            history.undo().unwrap();
+        }
+        assert!(history.undo().is_none());
+        // the first step can't be undone, so the opened state can't come back
+        assert!(history.is_modified());
+
+        // the saved state is still found at the bottom of the stack
+        let mut history = History::default();
+        history.record(insert(0, 0, "\n"), 1);
+        history.mark_saved();
+        for y in 1..=HISTORY_LIMIT {
+            history.record(insert(0, y, "\n"), 1);
+        }
+        assert!(history.is_modified());
+        while history.undo().is_some() {}
+        assert!(!history.is_modified());
+        history.redo();
+        assert!(history.is_modified());
+    }
+}
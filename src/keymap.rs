@@ -0,0 +1,561 @@
+use std::collections::HashMap;
+
+use termion::event::Key;
+
+/// the actions of the editor which can be bound to keys
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    Quit,
+    Save,
+    OpenFile,
+    NextBuffer,
+    PreviousBuffer,
+    PickBuffer,
+    Search,
+    Replace,
+    Undo,
+    Redo,
+    ToggleLineNumbers,
+    ToggleSoftWrap,
+    NextTheme,
+    SplitHorizontal,
+    SplitVertical,
+    CloseWindow,
+    FocusUp,
+    FocusDown,
+    FocusLeft,
+    FocusRight,
+    Copy,
+    Cut,
+    Paste,
+    ToggleMark,
+    ClearSelection,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveWordLeft,
+    MoveWordRight,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    SelectUp,
+    SelectDown,
+    SelectLeft,
+    SelectRight,
+    DeleteBackward,
+    DeleteForward,
+    SearchBackward,
+    KillLine,
+    Yank,
+    YankPop,
+    Palette,
+    GotoLine,
+}
+
+/// every command with the name it has in the settings file
+pub const REGISTRY: &[(&str, Command)] = &[
+    ("quit", Command::Quit),
+    ("save", Command::Save),
+    ("open-file", Command::OpenFile),
+    ("next-buffer", Command::NextBuffer),
+    ("previous-buffer", Command::PreviousBuffer),
+    ("pick-buffer", Command::PickBuffer),
+    ("search", Command::Search),
+    ("replace", Command::Replace),
+    ("undo", Command::Undo),
+    ("redo", Command::Redo),
+    ("toggle-line-numbers", Command::ToggleLineNumbers),
+    ("toggle-soft-wrap", Command::ToggleSoftWrap),
+    ("next-theme", Command::NextTheme),
+    ("split-horizontal", Command::SplitHorizontal),
+    ("split-vertical", Command::SplitVertical),
+    ("close-window", Command::CloseWindow),
+    ("focus-up", Command::FocusUp),
+    ("focus-down", Command::FocusDown),
+    ("focus-left", Command::FocusLeft),
+    ("focus-right", Command::FocusRight),
+    ("copy", Command::Copy),
+    ("cut", Command::Cut),
+    ("paste", Command::Paste),
+    ("toggle-mark", Command::ToggleMark),
+    ("clear-selection", Command::ClearSelection),
+    ("move-up", Command::MoveUp),
+    ("move-down", Command::MoveDown),
+    ("move-left", Command::MoveLeft),
+    ("move-right", Command::MoveRight),
+    ("move-word-left", Command::MoveWordLeft),
+    ("move-word-right", Command::MoveWordRight),
+    ("line-start", Command::LineStart),
+    ("line-end", Command::LineEnd),
+    ("page-up", Command::PageUp),
+    ("page-down", Command::PageDown),
+    ("select-up", Command::SelectUp),
+    ("select-down", Command::SelectDown),
+    ("select-left", Command::SelectLeft),
+    ("select-right", Command::SelectRight),
+    ("delete-backward", Command::DeleteBackward),
+    ("delete-forward", Command::DeleteForward),
+    ("search-backward", Command::SearchBackward),
+    ("kill-line", Command::KillLine),
+    ("yank", Command::Yank),
+    ("yank-pop", Command::YankPop),
+    ("palette", Command::Palette),
+    ("goto-line", Command::GotoLine),
+];
+
+const DEFAULT_BINDINGS: &[(Key, Command)] = &[
+    (Key::Ctrl('q'), Command::Quit),
+    (Key::Ctrl('s'), Command::Save),
+    (Key::Ctrl('o'), Command::OpenFile),
+    (Key::Alt('n'), Command::NextBuffer),
+    (Key::Alt('p'), Command::PreviousBuffer),
+    (Key::Alt('b'), Command::PickBuffer),
+    (Key::Ctrl('f'), Command::Search),
+    (Key::Ctrl('r'), Command::Replace),
+    (Key::Ctrl('z'), Command::Undo),
+    (Key::Ctrl('y'), Command::Redo),
+    (Key::Ctrl('l'), Command::ToggleLineNumbers),
+    (Key::Alt('z'), Command::ToggleSoftWrap),
+    (Key::Alt('t'), Command::NextTheme),
+    (Key::Alt('-'), Command::SplitHorizontal),
+    (Key::Alt('|'), Command::SplitVertical),
+    (Key::Alt('q'), Command::CloseWindow),
+    (Key::AltUp, Command::FocusUp),
+    (Key::AltDown, Command::FocusDown),
+    (Key::AltLeft, Command::FocusLeft),
+    (Key::AltRight, Command::FocusRight),
+    (Key::Ctrl('c'), Command::Copy),
+    (Key::Ctrl('x'), Command::Cut),
+    (Key::Ctrl('v'), Command::Paste),
+    // CTRL-SPACE
+    (Key::Null, Command::ToggleMark),
+    (Key::Esc, Command::ClearSelection),
+    (Key::Up, Command::MoveUp),
+    (Key::Down, Command::MoveDown),
+    (Key::Left, Command::MoveLeft),
+    (Key::Right, Command::MoveRight),
+    (Key::CtrlLeft, Command::MoveWordLeft),
+    (Key::CtrlRight, Command::MoveWordRight),
+    (Key::Home, Command::LineStart),
+    (Key::End, Command::LineEnd),
+    (Key::PageUp, Command::PageUp),
+    (Key::PageDown, Command::PageDown),
+    (Key::ShiftUp, Command::SelectUp),
+    (Key::ShiftDown, Command::SelectDown),
+    (Key::ShiftLeft, Command::SelectLeft),
+    (Key::ShiftRight, Command::SelectRight),
+    (Key::Backspace, Command::DeleteBackward),
+    (Key::Delete, Command::DeleteForward),
+    (Key::Alt('x'), Command::Palette),
+    (Key::Ctrl('g'), Command::GotoLine),
+];
+
+// the keys of the Emacs profile, they replace the default keys they clash with
+const EMACS_BINDINGS: &[(&[Key], Command)] = &[
+    (&[Key::Ctrl('a')], Command::LineStart),
+    (&[Key::Ctrl('e')], Command::LineEnd),
+    (&[Key::Ctrl('f')], Command::MoveRight),
+    (&[Key::Ctrl('b')], Command::MoveLeft),
+    (&[Key::Ctrl('n')], Command::MoveDown),
+    (&[Key::Ctrl('p')], Command::MoveUp),
+    (&[Key::Alt('f')], Command::MoveWordRight),
+    (&[Key::Alt('b')], Command::MoveWordLeft),
+    (&[Key::Ctrl('v')], Command::PageDown),
+    (&[Key::Alt('v')], Command::PageUp),
+    (&[Key::Ctrl('d')], Command::DeleteForward),
+    (&[Key::Ctrl('k')], Command::KillLine),
+    (&[Key::Ctrl('w')], Command::Cut),
+    (&[Key::Alt('w')], Command::Copy),
+    (&[Key::Ctrl('y')], Command::Yank),
+    (&[Key::Alt('y')], Command::YankPop),
+    (&[Key::Ctrl('g')], Command::ClearSelection),
+    (&[Key::Ctrl('s')], Command::Search),
+    (&[Key::Ctrl('r')], Command::SearchBackward),
+    (&[Key::Alt('%')], Command::Replace),
+    (&[Key::Alt('g'), Key::Char('g')], Command::GotoLine),
+    (&[Key::Alt('g'), Key::Alt('g')], Command::GotoLine),
+    (&[Key::Ctrl('x'), Key::Ctrl('s')], Command::Save),
+    (&[Key::Ctrl('x'), Key::Ctrl('f')], Command::OpenFile),
+    (&[Key::Ctrl('x'), Key::Ctrl('c')], Command::Quit),
+    (&[Key::Ctrl('x'), Key::Char('b')], Command::PickBuffer),
+    (&[Key::Ctrl('x'), Key::Char('u')], Command::Undo),
+    // CTRL-Y yanks, so redo needs a key of its own
+    (&[Key::Ctrl('x'), Key::Char('r')], Command::Redo),
+    (&[Key::Ctrl('x'), Key::Char('0')], Command::CloseWindow),
+    (&[Key::Ctrl('x'), Key::Char('2')], Command::SplitHorizontal),
+    (&[Key::Ctrl('x'), Key::Char('3')], Command::SplitVertical),
+];
+
+impl Command {
+    /// the command called `name` in the settings file
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        REGISTRY
+            .iter()
+            .find(|(command, _)| *command == name)
+            .map(|(_, command)| *command)
+    }
+}
+
+/// what the keys pressed so far mean
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Binding {
+    Command(Command),
+    // the keys start a longer sequence, like CTRL-K of CTRL-K CTRL-C
+    Prefix,
+    Unbound,
+}
+
+/// which command each sequence of keys runs, the keys without a command edit the text
+#[derive(Clone, PartialEq, Debug)]
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, Command>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: DEFAULT_BINDINGS
+                .iter()
+                .map(|(key, command)| (vec![*key], *command))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// the default keys with the ones of Emacs on top
+    #[must_use]
+    pub fn emacs() -> Self {
+        let mut keymap = Self::default();
+        for (keys, command) in EMACS_BINDINGS {
+            keymap.bind(keys.to_vec(), *command);
+        }
+        keymap
+    }
+
+    /// `keys` run `command` from now on, the other keys bound to `command` still run it.
+    /// a sequence can't be both a command and the start of a longer one,
+    /// so the bindings `keys` start or start with are removed
+    pub fn bind(&mut self, keys: Vec<Key>, command: Command) {
+        self.bindings
+            .retain(|bound, _| !bound.starts_with(&keys) && !keys.starts_with(bound));
+        self.bindings.insert(keys, command);
+    }
+
+    #[must_use]
+    pub fn lookup(&self, keys: &[Key]) -> Binding {
+        if let Some(command) = self.bindings.get(keys) {
+            Binding::Command(*command)
+        } else if self.bindings.keys().any(|bound| bound.starts_with(keys)) {
+            Binding::Prefix
+        } else {
+            Binding::Unbound
+        }
+    }
+
+    /// the sequences of keys bound to `command`, the shortest first
+    #[must_use]
+    pub fn keys_for(&self, command: Command) -> Vec<&[Key]> {
+        let mut keys: Vec<&[Key]> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == command)
+            .map(|(keys, _)| &keys[..])
+            .collect();
+        keys.sort_by_key(|keys| (keys.len(), key_names(keys)));
+        keys
+    }
+}
+
+/// the keys called `names` in the settings file, separated by spaces, e.g. `ctrl-k ctrl-c`
+#[must_use]
+pub fn parse_keys(names: &str) -> Option<Vec<Key>> {
+    let keys: Option<Vec<Key>> = names.split_whitespace().map(parse_key).collect();
+    keys.filter(|keys| !keys.is_empty())
+}
+
+/// the key called `name` in the settings file, e.g. `ctrl-s`, `alt-left`, `f5` or `esc`
+#[must_use]
+pub fn parse_key(name: &str) -> Option<Key> {
+    let single = |text: &str| {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    let lowercase = name.to_lowercase();
+    if let Some(rest) = lowercase.strip_prefix("ctrl-") {
+        return match rest {
+            "space" => Some(Key::Null),
+            "up" => Some(Key::CtrlUp),
+            "down" => Some(Key::CtrlDown),
+            "left" => Some(Key::CtrlLeft),
+            "right" => Some(Key::CtrlRight),
+            "home" => Some(Key::CtrlHome),
+            "end" => Some(Key::CtrlEnd),
+            // the terminal can't tell CTRL-A from CTRL-SHIFT-A
+            _ => single(rest).map(Key::Ctrl),
+        };
+    }
+    if let Some(rest) = lowercase.strip_prefix("alt-") {
+        return match rest {
+            "up" => Some(Key::AltUp),
+            "down" => Some(Key::AltDown),
+            "left" => Some(Key::AltLeft),
+            "right" => Some(Key::AltRight),
+            // ALT-Z and ALT-z are different keys
+            _ => name.get(4..).and_then(single).map(Key::Alt),
+        };
+    }
+    if let Some(rest) = lowercase.strip_prefix("shift-") {
+        return match rest {
+            "up" => Some(Key::ShiftUp),
+            "down" => Some(Key::ShiftDown),
+            "left" => Some(Key::ShiftLeft),
+            "right" => Some(Key::ShiftRight),
+            "tab" => Some(Key::BackTab),
+            _ => None,
+        };
+    }
+    match lowercase.as_str() {
+        "esc" => Some(Key::Esc),
+        "enter" => Some(Key::Char('\n')),
+        "tab" => Some(Key::Char('\t')),
+        "backspace" => Some(Key::Backspace),
+        "delete" => Some(Key::Delete),
+        "insert" => Some(Key::Insert),
+        "home" => Some(Key::Home),
+        "end" => Some(Key::End),
+        "pageup" => Some(Key::PageUp),
+        "pagedown" => Some(Key::PageDown),
+        "up" => Some(Key::Up),
+        "down" => Some(Key::Down),
+        "left" => Some(Key::Left),
+        "right" => Some(Key::Right),
+        _ => lowercase
+            .strip_prefix('f')
+            .and_then(|number| number.parse().ok())
+            .filter(|number| (1..=12).contains(number))
+            .map(Key::F)
+            .or_else(|| single(name).map(Key::Char)),
+    }
+}
+
+/// the names of `keys` as they are written in the settings file, e.g. `ctrl-k ctrl-c`
+#[must_use]
+pub fn key_names(keys: &[Key]) -> String {
+    let names: Vec<String> = keys.iter().map(|key| key_name(*key)).collect();
+    names.join(" ")
+}
+
+fn key_name(key: Key) -> String {
+    let name = match key {
+        Key::Null => "ctrl-space",
+        Key::Ctrl(c) => return format!("ctrl-{c}"),
+        Key::Alt(c) => return format!("alt-{c}"),
+        Key::F(number) => return format!("f{number}"),
+        Key::Char('\n') => "enter",
+        Key::Char('\t') => "tab",
+        Key::Char(c) => return c.to_string(),
+        Key::CtrlUp => "ctrl-up",
+        Key::CtrlDown => "ctrl-down",
+        Key::CtrlLeft => "ctrl-left",
+        Key::CtrlRight => "ctrl-right",
+        Key::CtrlHome => "ctrl-home",
+        Key::CtrlEnd => "ctrl-end",
+        Key::AltUp => "alt-up",
+        Key::AltDown => "alt-down",
+        Key::AltLeft => "alt-left",
+        Key::AltRight => "alt-right",
+        Key::ShiftUp => "shift-up",
+        Key::ShiftDown => "shift-down",
+        Key::ShiftLeft => "shift-left",
+        Key::ShiftRight => "shift-right",
+        Key::BackTab => "shift-tab",
+        Key::Esc => "esc",
+        Key::Backspace => "backspace",
+        Key::Delete => "delete",
+        Key::Insert => "insert",
+        Key::Home => "home",
+        Key::End => "end",
+        Key::PageUp => "pageup",
+        Key::PageDown => "pagedown",
+        Key::Up => "up",
+        Key::Down => "down",
+        Key::Left => "left",
+        Key::Right => "right",
+        _ => return format!("{key:?}").to_lowercase(),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod keymap_tests {
+    use termion::event::Key;
+
+    use super::{key_names, parse_key, parse_keys, Binding, Command, Keymap, REGISTRY};
+
+    #[test]
+    fn key_names_are_parsed() {
+        assert_eq!(parse_key("ctrl-s"), Some(Key::Ctrl('s')));
+        assert_eq!(parse_key("Ctrl-S"), Some(Key::Ctrl('s')));
+        assert_eq!(parse_key("ctrl-space"), Some(Key::Null));
+        assert_eq!(parse_key("alt-Z"), Some(Key::Alt('Z')));
+        assert_eq!(parse_key("alt-left"), Some(Key::AltLeft));
+        assert_eq!(parse_key("shift-tab"), Some(Key::BackTab));
+        assert_eq!(parse_key("f5"), Some(Key::F(5)));
+        assert_eq!(parse_key("PageDown"), Some(Key::PageDown));
+        assert_eq!(parse_key("x"), Some(Key::Char('x')));
+        assert_eq!(parse_key("f13"), None);
+        assert_eq!(parse_key("ctrl-ss"), None);
+        assert_eq!(parse_key("hyper-a"), None);
+
+        assert_eq!(
+            parse_keys(" ctrl-k  ctrl-c "),
+            Some(vec![Key::Ctrl('k'), Key::Ctrl('c')])
+        );
+        assert_eq!(parse_keys("ctrl-k nope"), None);
+        assert_eq!(parse_keys(""), None);
+
+        // the names are read back as the same keys
+        let keys = [
+            Key::Ctrl('k'),
+            Key::Null,
+            Key::Alt('Z'),
+            Key::AltRight,
+            Key::ShiftUp,
+            Key::BackTab,
+            Key::F(12),
+            Key::Char('\n'),
+            Key::Char('q'),
+            Key::PageUp,
+        ];
+        assert_eq!(
+            key_names(&keys),
+            "ctrl-k ctrl-space alt-Z alt-right shift-up shift-tab f12 enter q pageup"
+        );
+        assert_eq!(parse_keys(&key_names(&keys)), Some(keys.to_vec()));
+    }
+
+    #[test]
+    fn registry_names_every_command_once() {
+        for (index, (name, command)) in REGISTRY.iter().enumerate() {
+            assert_eq!(Command::from_name(name), Some(*command));
+            assert!(REGISTRY[..index].iter().all(|(_, other)| other != command));
+        }
+        assert_eq!(
+            Command::from_name("move-word-left"),
+            Some(Command::MoveWordLeft)
+        );
+    }
+
+    #[test]
+    fn rebinding() {
+        let mut keymap = Keymap::default();
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('s')]),
+            Binding::Command(Command::Save)
+        );
+        assert_eq!(keymap.lookup(&[Key::Ctrl('w')]), Binding::Unbound);
+        keymap.bind(
+            vec![Key::Ctrl('w')],
+            Command::from_name("close-window").unwrap(),
+        );
+        keymap.bind(vec![Key::Ctrl('s')], Command::Search);
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('w')]),
+            Binding::Command(Command::CloseWindow)
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Alt('q')]),
+            Binding::Command(Command::CloseWindow)
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('s')]),
+            Binding::Command(Command::Search)
+        );
+        assert_eq!(Command::from_name("close_window"), None);
+    }
+
+    #[test]
+    fn chords() {
+        let mut keymap = Keymap::default();
+        let chord = vec![Key::Ctrl('k'), Key::Ctrl('c')];
+        keymap.bind(chord.clone(), Command::Copy);
+        keymap.bind(vec![Key::Ctrl('k'), Key::Char('s')], Command::Save);
+        assert_eq!(keymap.lookup(&[Key::Ctrl('k')]), Binding::Prefix);
+        assert_eq!(keymap.lookup(&chord), Binding::Command(Command::Copy));
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('k'), Key::Ctrl('x')]),
+            Binding::Unbound
+        );
+        // CTRL-C still copies on its own
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('c')]),
+            Binding::Command(Command::Copy)
+        );
+
+        // a key replaces the chords it starts
+        keymap.bind(vec![Key::Ctrl('k')], Command::Cut);
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('k')]),
+            Binding::Command(Command::Cut)
+        );
+        assert_eq!(keymap.lookup(&chord), Binding::Unbound);
+        // and a chord replaces the key it starts with
+        keymap.bind(vec![Key::Esc, Key::Char('x')], Command::Cut);
+        assert_eq!(keymap.lookup(&[Key::Esc]), Binding::Prefix);
+    }
+
+    #[test]
+    fn emacs_keys() {
+        let keymap = Keymap::emacs();
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('f')]),
+            Binding::Command(Command::MoveRight)
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('r')]),
+            Binding::Command(Command::SearchBackward)
+        );
+        // CTRL-X starts the sequences of Emacs instead of cutting
+        assert_eq!(keymap.lookup(&[Key::Ctrl('x')]), Binding::Prefix);
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('x'), Key::Ctrl('s')]),
+            Binding::Command(Command::Save)
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('g')]),
+            Binding::Command(Command::ClearSelection)
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Alt('g'), Key::Char('g')]),
+            Binding::Command(Command::GotoLine)
+        );
+        // the default keys which don't clash are kept
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('q')]),
+            Binding::Command(Command::Quit)
+        );
+        assert_eq!(
+            keymap.lookup(&[Key::Alt('n')]),
+            Binding::Command(Command::NextBuffer)
+        );
+        // redo is still reachable after CTRL-Y became yank
+        assert_eq!(
+            keymap.keys_for(Command::Redo),
+            [&[Key::Ctrl('x'), Key::Char('r')][..]]
+        );
+        assert_eq!(
+            keymap.keys_for(Command::Quit),
+            [&[Key::Ctrl('q')][..], &[Key::Ctrl('x'), Key::Ctrl('c')]]
+        );
+        assert!(keymap
+            .keys_for(Command::Palette)
+            .contains(&&[Key::Alt('x')][..]));
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::{
+    config::config_dir,
+    filetype::{emacs_modeline, interpreter, vim_modeline},
+    FileType,
+};
+
+// `BUNDLED` is generated by build.rs from the files in src/highlightkeys
+include!(concat!(env!("OUT_DIR"), "/languages.rs"));
+
+/// the directory where users can add their own language definitions
+/// or override the bundled ones
+#[must_use]
+pub fn user_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("languages"))
+}
+
+/// the source of every language definition with the name of its file.
+/// a file in `user_dir` replaces the bundled file of the same name,
+/// and comes first, so that it wins when two languages claim the same files
+fn definitions(user_dir: Option<&Path>) -> Vec<(String, Cow<'static, str>)> {
+    let mut definitions: Vec<(String, Cow<'static, str>)> = Vec::new();
+    if let Some(entries) = user_dir.and_then(|dir| fs::read_dir(dir).ok()) {
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            if let Ok(source) = fs::read_to_string(&path) {
+                let file = path.file_name().unwrap_or_default().to_string_lossy();
+                definitions.push((file.into_owned(), Cow::Owned(source)));
+            }
+        }
+    }
+    for (name, source) in BUNDLED {
+        let file = format!("{name}.json");
+        if !definitions.iter().any(|(user_file, _)| *user_file == file) {
+            definitions.push((file, Cow::Borrowed(*source)));
+        }
+    }
+    definitions
+}
+
+/// every language we know, parsed once when it is first needed
+pub struct Registry {
+    file_types: Vec<FileType>,
+    // mistakes in the definitions, those languages are left out
+    errors: Vec<String>,
+}
+
+impl Registry {
+    fn load(user_dir: Option<&Path>) -> Self {
+        let mut file_types = Vec::new();
+        let mut errors = Vec::new();
+        for (file, source) in definitions(user_dir) {
+            match FileType::parse(&source) {
+                Ok(languages) => file_types.extend(languages),
+                Err(e) => errors.push(format!("{file}: {e}")),
+            }
+        }
+        Self { file_types, errors }
+    }
+
+    /// the language of a file called `filename`, `lines` are its first and last rows.
+    /// a modeline in `lines` comes first, then the name of the file,
+    /// and at last the shebang of the first row
+    #[must_use]
+    pub fn detect(&self, filename: Option<&str>, lines: &[&str]) -> Option<&FileType> {
+        let shebang = lines.first().is_some_and(|line| line.starts_with("#!"));
+        lines
+            .iter()
+            .take(if shebang { 2 } else { 1 })
+            .find_map(|line| emacs_modeline(line))
+            .or_else(|| lines.iter().find_map(|line| vim_modeline(line)))
+            .and_then(|name| self.find(|ft| ft.is_called(name)))
+            .or_else(|| self.find(|ft| filename.is_some_and(|filename| ft.matches(filename))))
+            .or_else(|| {
+                let program = interpreter(lines.first()?)?;
+                self.find(|ft| ft.runs_on(program))
+            })
+    }
+
+    fn find(&self, predicate: impl Fn(&FileType) -> bool) -> Option<&FileType> {
+        self.file_types.iter().find(|ft| predicate(ft))
+    }
+
+    #[must_use]
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+}
+
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| Registry::load(user_dir().as_deref()))
+}
+
+#[cfg(test)]
+mod languages_tests {
+    use std::{env, fs, process};
+
+    use super::Registry;
+    use crate::FileType;
+
+    /// the name of the language detected for a file, "" if unknown
+    fn detect(registry: &Registry, filename: Option<&str>, lines: &[&str]) -> String {
+        registry
+            .detect(filename, lines)
+            .map(FileType::name)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn bundled_definition() {
+        let registry = Registry::load(None);
+        assert!(registry.errors().is_empty(), "{:?}", registry.errors());
+        assert!(!registry
+            .detect(Some("main.rs"), &[])
+            .unwrap()
+            .highlighting_opts()
+            .primary_keys()
+            .is_empty());
+        assert!(registry
+            .detect(Some("main.no-such-language"), &[])
+            .is_none());
+    }
+
+    #[test]
+    fn user_definition_overrides_bundled_one() {
+        let dir = env::temp_dir().join(format!("languages-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("rs.json"), r#"{"rs": {"primary_keys": ["mine"]}}"#).unwrap();
+        fs::write(dir.join("foo.json"), r#"{"foo": {}}"#).unwrap();
+        fs::write(dir.join("bad.json"), r#"{"bad": {"numbers": 1}}"#).unwrap();
+
+        let registry = Registry::load(Some(&dir));
+        let rust = registry.detect(Some("main.rs"), &[]).unwrap();
+        assert_eq!(rust.highlighting_opts().primary_keys(), &["mine"]);
+        assert!(registry.detect(Some("a.foo"), &[]).is_some());
+        assert!(registry.detect(Some("a.bad"), &[]).is_none());
+        assert_eq!(
+            registry.errors(),
+            ["bad.json: bad.numbers: expected an object"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detect_file_type() {
+        let registry = Registry::load(None);
+        // exact file names and globs
+        assert_eq!(detect(&registry, Some("dir/Makefile"), &[]), "Makefile");
+        assert_eq!(detect(&registry, Some("Dockerfile.dev"), &[]), "Dockerfile");
+        assert_eq!(detect(&registry, Some("/home/me/.bashrc"), &[]), "Shell");
+        // the shebang of scripts without a suffix
+        for shebang in [
+            "#!/usr/bin/env python3",
+            "#!/usr/bin/python3.11 -u",
+            "#!/usr/bin/env -S PYTHONPATH=. python",
+        ] {
+            assert_eq!(detect(&registry, Some("run"), &[shebang]), "Python");
+        }
+        assert_eq!(detect(&registry, Some("run"), &["#!/bin/bash -e"]), "Shell");
+        assert_eq!(
+            detect(&registry, None, &["#!/usr/bin/env node"]),
+            "JavaScript"
+        );
+        // the shebang only counts on the first row
+        assert_eq!(detect(&registry, Some("run"), &["", "#!/bin/sh"]), "");
+        // the name of the file wins over the shebang
+        assert_eq!(detect(&registry, Some("a.rs"), &["#!/bin/sh"]), "Rust");
+        // but a modeline wins over both
+        assert_eq!(
+            detect(
+                &registry,
+                Some("a.txt"),
+                &["#!/bin/sh", "# vim: set ft=python :"]
+            ),
+            "Python"
+        );
+        assert_eq!(
+            detect(&registry, Some("rules"), &["# vi: noet filetype=make"]),
+            "Makefile"
+        );
+        assert_eq!(
+            detect(
+                &registry,
+                Some("a.sh"),
+                &["// -*- mode: C++; tab-width: 4 -*-"]
+            ),
+            "C++"
+        );
+        assert_eq!(
+            detect(&registry, None, &["# -*- shell-script -*-"]),
+            "Shell"
+        );
+        // an unknown language in a modeline is ignored
+        assert_eq!(detect(&registry, Some("a.go"), &["// vim: ft=cobol"]), "Go");
+        // `vim:` must start a word
+        assert_eq!(
+            detect(&registry, Some("a.go"), &["// gvim:ft=python"]),
+            "Go"
+        );
+        // an Emacs modeline may follow a shebang
+        assert_eq!(
+            detect(
+                &registry,
+                Some("run"),
+                &["#!/bin/sh", "# -*- mode: python -*-"]
+            ),
+            "Python"
+        );
+        // but a line of prose in the middle of a file isn't a modeline
+        assert_eq!(
+            detect(
+                &registry,
+                Some("notes.md"),
+                &[
+                    "# Notes",
+                    "",
+                    "write -*- mode: python -*- on the first line"
+                ]
+            ),
+            "Markdown"
+        );
+        assert_eq!(
+            detect(
+                &registry,
+                Some("notes.md"),
+                &["# Notes", "a modeline like # vim: ft=python : goes last"]
+            ),
+            "Markdown"
+        );
+        assert_eq!(
+            detect(&registry, Some("a.lua"), &["x = 1", "-- vim: ft=python"]),
+            "Python"
+        );
+    }
+}
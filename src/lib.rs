@@ -0,0 +1,53 @@
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::missing_docs_in_private_items,
+    clippy::implicit_return,
+    clippy::shadow_reuse,
+    clippy::print_stdout,
+    clippy::wildcard_enum_match_arm,
+    clippy::else_if_without_else
+)]
+mod editor;
+
+mod config;
+
+mod keymap;
+
+mod command_line;
+
+mod vim;
+
+mod event;
+
+mod terminal;
+pub use terminal::Terminal;
+
+mod highlighting;
+
+mod theme;
+
+mod languages;
+
+mod filetype;
+pub use filetype::FileType;
+pub use filetype::HighlightingOptions;
+
+mod document;
+pub use document::Document;
+
+mod row;
+pub use row::Row;
+
+mod history;
+
+pub mod buffer;
+
+mod search;
+
+mod clipboard;
+
+mod window;
+
+pub use editor::Editor;
+pub use editor::Position;
+pub use editor::SearchDirection;
@@ -1,32 +1,4 @@
-#![warn(clippy::all, clippy::pedantic)]
-#![allow(
-    clippy::missing_docs_in_private_items,
-    clippy::implicit_return,
-    clippy::shadow_reuse,
-    clippy::print_stdout,
-    clippy::wildcard_enum_match_arm,
-    clippy::else_if_without_else,
-)]
-mod editor;
-
-mod terminal;
-pub use terminal::Terminal;
-
-mod highlighting;
-
-mod filetype;
-pub use filetype::FileType;
-pub use filetype::HighlightingOptions;
-
-mod document;
-pub use document::Document;
-
-mod row;
-pub use row::Row;
-
-use editor::Editor;
-pub use editor::Position;
-pub use editor::SearchDirection;
+use text_editor::Editor;
 
 fn main() {
     let mut editor = Editor::new();
@@ -1,219 +1,773 @@
-use termion::color;
+use std::{
+    borrow::Cow,
+    fmt::{self, Write},
+    iter,
+    ops::Range,
+};
+
+use termion::style;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    buffer::{Measure, Measured, Tree},
+    highlighting::{State, Type},
+    search::Query,
+    theme::Theme,
+    HighlightingOptions, SearchDirection,
+};
 
-use crate::{highlighting::Type, HighlightingOptions, SearchDirection};
+/// the most bytes a chunk of a row holds, unless a single grapheme is longer
+const MAX_CHUNK: usize = 1024;
 
+/// the text of a row is kept in chunks in a tree, which counts their graphemes and bytes.
+/// so an edit in a long row copies only the chunks around it,
+/// and its highlighting starts again from the chunks around it as well
 #[derive(Default)]
 pub struct Row {
-    content: String,
-    // highlighting is controlled by document, `row` just save them
-    highlighting: Vec<Type>,
+    chunks: Tree<Chunk>,
+    // the construct left open at the end of this row, the next row needs it to be highlighted
+    hl_state: State,
+    // the construct left open by the previous row when this row was highlighted last time,
+    // `None` if it hasn't been highlighted
+    hl_input: Option<State>,
+    // the graphemes edited since the row was highlighted
+    edited: Option<Range<usize>>,
+}
+
+/// a piece of the text of a row, it starts and ends between graphemes
+#[derive(Default)]
+struct Chunk {
+    text: String,
     // avoid repeating calculate the length
     len: usize,
+    // highlighting is controlled by document, `row` just save them, one type for each grapheme
+    highlighting: Vec<Type>,
+    // whether a token started right at the start of this chunk when it was highlighted,
+    // the highlighting can start again or stop at such a chunk
+    token_start: bool,
 }
 
-impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        // we should make sure that end is not greater than len of content
-        let end = end.min(self.content.len());
-        let start = start.min(end);
+impl Chunk {
+    fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            len: grapheme_count(text),
+            highlighting: Vec::new(),
+            token_start: false,
+        }
+    }
+}
+
+impl Measured for Chunk {
+    fn measure(&self) -> Measure {
+        Measure {
+            items: 1,
+            graphemes: self.len,
+            bytes: self.text.len(),
+        }
+    }
+}
 
+impl Row {
+    /// render the display columns in `start..end` in the styles of `theme`,
+    /// the style of the selection is put on top of the graphemes in `selection`.
+    /// the line break is the grapheme after the last one, it is drawn as a space when selected.
+    /// tabs are expanded to the next multiple of `tab_width`
+    #[must_use]
+    pub fn render(
+        &self,
+        start: usize,
+        end: usize,
+        selection: Option<&Range<usize>>,
+        tab_width: usize,
+        theme: &Theme,
+    ) -> String {
         // use a library to deal with the length of unicode string
         let mut result = String::new();
-        // keep track of current color type
-        // then we don't need to change color whenever ecounter a new character if it is the same
-        let mut cur_color_type = &Type::None;
-        result.push_str(&format!("{}", color::Fg(cur_color_type.to_color())));
+        // keep track of current style
+        // then we don't need to change it whenever ecounter a new character if it is the same
+        let mut current = None;
+        let mut column = 0;
 
-        for (index, g) in self.content[..]
-            .graphemes(true)
-            .enumerate()
-            .skip(start)
-            .take(end - start)
-        {
-            if let Some(c) = g.chars().next() {
-                // because `highlighting()` is invoked whenever one row is pushed into `rows`
-                // we can find coresponding highlighting type by index
-                let htype = self.highlighting.get(index).unwrap_or(&Type::None);
-
-                // if encounter a new color type, then we need to change the color
-                if cur_color_type != htype {
-                    cur_color_type = htype;
-                    let start_highlighting = format!("{}", color::Fg(htype.to_color()));
-                    result.push_str(&start_highlighting);
-                }
-                if c == '\t' {
-                    result.push(' ');
-                } else {
-                    result.push(c);
-                }
+        for (index, (g, htype)) in self.styled_graphemes().enumerate() {
+            if column >= end {
+                break;
+            }
+            let width = grapheme_width(g, column, tab_width);
+            let next = column.saturating_add(width);
+            // the columns of this grapheme on the screen
+            let (from, to) = (column.max(start), next.min(end));
+            column = next;
+            if from >= to {
+                continue;
+            }
+            let mut style = theme.syntax(htype);
+            if selection.is_some_and(|range| range.contains(&index)) {
+                style = style.patch(theme.selection);
+            }
+            // if encounter a new style, then we need to change it
+            if current != Some(style) {
+                current = Some(style);
+                let _ = write!(result, "{style}");
             }
+            // a tab, or a wide grapheme cut by the edge of the screen, is filled with spaces
+            if g == "\t" || to - from < width {
+                result.push_str(&" ".repeat(to - from));
+            } else {
+                result.push_str(g);
+            }
+        }
+        // the loop stops early only after `end`
+        if column >= start
+            && column < end
+            && selection.is_some_and(|range| range.contains(&self.len()))
+        {
+            let style = theme.syntax(Type::None).patch(theme.selection);
+            let _ = write!(result, "{style} ");
         }
-        let end_highlighting = format!("{}", color::Fg(color::Reset));
-        result.push_str(&end_highlighting);
+        let _ = write!(result, "{}", style::Reset);
         result
     }
 
-    pub fn append(&mut self, row: &Row) {
-        self.content = format!("{}{}", self.content, row.content);
-        self.len += row.len;
+    /// the graphemes of the row in order
+    fn graphemes(&self) -> impl Iterator<Item = &str> {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.text.graphemes(true))
     }
 
-    pub fn len(&self) -> usize {
-        self.len
+    /// the graphemes of the row with their highlighting types,
+    /// the ones which haven't been highlighted yet have none
+    fn styled_graphemes(&self) -> impl Iterator<Item = (&str, Type)> {
+        self.chunks.iter().flat_map(|chunk| {
+            let types = chunk
+                .highlighting
+                .iter()
+                .copied()
+                .chain(iter::repeat(Type::None));
+            chunk.text.graphemes(true).zip(types)
+        })
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.len == 0
+    /// the display column where the grapheme at `index` starts
+    #[must_use]
+    pub fn column(&self, index: usize, tab_width: usize) -> usize {
+        self.graphemes().take(index).fold(0, |column, g| {
+            column.saturating_add(grapheme_width(g, column, tab_width))
+        })
     }
 
-    pub fn insert(&mut self, at: usize, c: char) {
-        // if we need to append a char, just invoke `String::push`
-        if at >= self.len() {
-            self.content.push(c);
-            self.len += 1;
-            return;
+    /// the index of the grapheme shown at display `column`, the length of the row if it is shorter
+    #[must_use]
+    pub fn index_at_column(&self, column: usize, tab_width: usize) -> usize {
+        let mut start: usize = 0;
+        for (index, g) in self.graphemes().enumerate() {
+            start = start.saturating_add(grapheme_width(g, start, tab_width));
+            if column < start {
+                return index;
+            }
         }
-        // if we need to insert a char into the middle of content
-        // split the content at the position `at`
-        // and then push the char to the end of front part
-        // the combine the new string with another part
-        let mut result = String::new();
-        let mut length = 0;
-        for (index, grapheme) in self.content[..].graphemes(true).enumerate() {
-            length += 1;
-            if index == at {
-                length += 1;
-                result.push(c);
+        self.len()
+    }
+
+    /// split the row into visual lines no wider than `width` columns, as ranges of graphemes.
+    /// with `at_words` a line is broken after the last whitespace that fits, if there is one.
+    /// an empty row is still one line
+    #[must_use]
+    pub fn wrap(&self, width: usize, tab_width: usize, at_words: bool) -> Vec<Range<usize>> {
+        let width = width.max(1);
+        let mut lines = Vec::new();
+        // the index and the column where the current line starts
+        let (mut start, mut start_column) = (0, 0);
+        // the index and the column after the last whitespace of the current line
+        let mut word_break: Option<(usize, usize)> = None;
+        let mut column: usize = 0;
+        for (index, g) in self.graphemes().enumerate() {
+            let next = column.saturating_add(grapheme_width(g, column, tab_width));
+            // a grapheme wider than the screen gets a line of its own
+            while next.saturating_sub(start_column) > width && index > start {
+                let (end, end_column) = match word_break.take() {
+                    Some(after_word) if at_words => after_word,
+                    _ => (index, column),
+                };
+                lines.push(start..end);
+                (start, start_column) = (end, end_column);
             }
-            result.push_str(grapheme);
+            if g.chars().all(char::is_whitespace) {
+                word_break = Some((index.saturating_add(1), next));
+            }
+            column = next;
         }
-        self.content = result;
-        self.len = length;
+        lines.push(start..self.len());
+        lines
     }
 
-    pub fn delete(&mut self, at: usize) {
-        if at >= self.len() {
-            return;
+    /// append `row`, the row after this one. its chunks are moved over,
+    /// the highlighting of them is kept unless `row` has been highlighted with other settings
+    pub fn append(&mut self, row: Row) {
+        let len = self.len();
+        self.hl_state = row.hl_state;
+        if let Some(edited) = row.edited {
+            self.mark_edited(len.saturating_add(edited.start)..len.saturating_add(edited.end));
         }
-        let mut length = 0;
-        let mut result = String::new();
-        for (index, g) in self.content[..].graphemes(true).enumerate() {
-            if index != at {
-                length += 1;
-                result.push_str(g);
+        self.chunks.extend(row.chunks.into_items());
+        // the first grapheme of `row` may be combined with the last one of this row
+        self.splice(len, len, "");
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.chunks.measure().graphemes
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the grapheme at index `at`
+    #[must_use]
+    pub fn text(&self, at: usize) -> Option<String> {
+        let (index, before) = self.chunks.seek(at, |m| m.graphemes)?;
+        self.chunks[index]
+            .text
+            .graphemes(true)
+            .nth(at.saturating_sub(before.graphemes))
+            .map(String::from)
+    }
+
+    /// insert a string which doesn't contain '\n' at the grapheme index `at`
+    pub fn insert_str(&mut self, at: usize, string: &str) {
+        self.splice(at, at, string);
+    }
+
+    /// the graphemes in `start..end`, borrowed if they are in one chunk
+    #[must_use]
+    pub fn slice(&self, start: usize, end: usize) -> Cow<'_, str> {
+        let end = end.min(self.len());
+        let start = start.min(end);
+        let Some((first, before)) = self.chunks.seek(start, |m| m.graphemes) else {
+            return Cow::Borrowed("");
+        };
+        // the graphemes before chunk `index`
+        let mut at = before.graphemes;
+        let mut text = String::new();
+        for index in first..self.chunks.len() {
+            let chunk = &self.chunks[index];
+            let from = byte_offset(&chunk.text, start.saturating_sub(at));
+            let to = byte_offset(&chunk.text, end.saturating_sub(at));
+            if index == first && end <= at.saturating_add(chunk.len) {
+                return Cow::Borrowed(&chunk.text[from..to]);
+            }
+            text.push_str(&chunk.text[from..to]);
+            at = at.saturating_add(chunk.len);
+            if end <= at {
+                break;
             }
         }
-        self.content = result;
-        self.len = length;
+        Cow::Owned(text)
+    }
+
+    /// remove the graphemes in `start..end` and return them
+    pub fn drain(&mut self, start: usize, end: usize) -> String {
+        self.splice(start, end, "")
+    }
+
+    /// replace the graphemes in `start..end` by `text` and return them.
+    /// the chunks holding them and the chunks on both sides are made again,
+    /// because the graphemes at the edges of the edit may be combined now,
+    /// like "\r" before "\n", a letter before U+0301 or the hangul jamo on both sides of a removed letter
+    fn splice(&mut self, start: usize, end: usize, text: &str) -> String {
+        let len = self.len();
+        let end = end.min(len);
+        let start = start.min(end);
+        let count = self.chunks.len();
+        // from the chunk before the one holding `start`
+        let (mut first, mut before) = self
+            .chunks
+            .seek(start, |m| m.graphemes)
+            .unwrap_or((count, self.chunks.measure()));
+        if first > 0 {
+            first -= 1;
+            before = before - self.chunks[first].measure();
+        }
+        // to the chunk holding the grapheme after the edit
+        let last = self
+            .chunks
+            .seek(end, |m| m.graphemes)
+            .map_or(count, |(index, _)| index.saturating_add(1));
+        let mut joined = String::new();
+        for _ in first..last {
+            joined.push_str(&self.chunks.remove(first).text);
+        }
+        let from = byte_offset(&joined, start.saturating_sub(before.graphemes));
+        let to = byte_offset(&joined, end.saturating_sub(before.graphemes));
+        let removed = joined[from..to].to_string();
+        joined.replace_range(from..to, text);
+        for (offset, chunk) in chunks_of(&joined).into_iter().enumerate() {
+            self.chunks.insert(first.saturating_add(offset), chunk);
+        }
+        let inserted = self.len().saturating_add(end - start).saturating_sub(len);
+        self.shift_edited(start, end, inserted);
+        // a grapheme on either side may have been combined with the edit
+        self.mark_edited(start.saturating_sub(1)..start.saturating_add(inserted).saturating_add(1));
+        removed
+    }
+
+    /// move the edited range after the graphemes in `start..end` are replaced by `inserted` ones
+    fn shift_edited(&mut self, start: usize, end: usize, inserted: usize) {
+        let shift = |at: usize| {
+            if at <= start {
+                at
+            } else if at >= end {
+                at.saturating_add(inserted).saturating_sub(end - start)
+            } else {
+                start.saturating_add(inserted)
+            }
+        };
+        self.edited = self
+            .edited
+            .take()
+            .map(|edited| shift(edited.start)..shift(edited.end));
+    }
+
+    /// add `range` to the graphemes edited since the row was highlighted
+    fn mark_edited(&mut self, range: Range<usize>) {
+        self.edited = Some(match self.edited.take() {
+            Some(edited) => edited.start.min(range.start)..edited.end.max(range.end),
+            None => range,
+        });
+    }
+
+    /// the start of the last word before grapheme `at`, `None` if there is no word before it in this row.
+    /// a word is a run of letters, digits and `_`
+    #[must_use]
+    pub fn word_start_before(&self, at: usize) -> Option<usize> {
+        let words: Vec<bool> = self.graphemes().take(at).map(is_word).collect();
+        let last = words.iter().rposition(|word| *word)?;
+        Some(
+            words[..last]
+                .iter()
+                .rposition(|word| !*word)
+                .map_or(0, |i| i + 1),
+        )
+    }
+
+    /// the start of the first word after the one at grapheme `at`, `None` if there is none in this row
+    #[must_use]
+    pub fn word_start_after(&self, at: usize) -> Option<usize> {
+        let words: Vec<bool> = self.graphemes().map(is_word).collect();
+        let rest = words.get(at..)?;
+        let end = rest.iter().position(|word| !*word)?;
+        rest[end..]
+            .iter()
+            .position(|word| *word)
+            .map(|i| at + end + i)
+    }
+
+    /// the end of the first word after grapheme `at`, `None` if there is no word after it in this row
+    #[must_use]
+    pub fn word_end_after(&self, at: usize) -> Option<usize> {
+        let words: Vec<bool> = self.graphemes().map(is_word).collect();
+        let first = at + words.get(at..)?.iter().position(|word| *word)?;
+        Some(
+            words[first..]
+                .iter()
+                .position(|word| !*word)
+                .map_or(words.len(), |i| first + i),
+        )
+    }
+
+    /// the byte index of the grapheme at index `at`, or the length of the row in bytes if `at` is out of range
+    fn byte_index(&self, at: usize) -> usize {
+        let Some((index, before)) = self.chunks.seek(at, |m| m.graphemes) else {
+            return self.chunks.measure().bytes;
+        };
+        let text = &self.chunks[index].text;
+        before
+            .bytes
+            .saturating_add(byte_offset(text, at.saturating_sub(before.graphemes)))
     }
 
     /// Split the content into two parts
-    /// since it will create a new value, so its return value must be used
+    /// since it will create a new value, so its return value must be used.
+    /// the chunks after `at` are moved to the new row with their highlighting
     #[must_use]
     pub fn split(&mut self, at: usize) -> Self {
-        let mut row = String::new();
-        let mut length = 0;
-        let mut split_res = String::new();
-        let mut split_len = 0;
-
-        for (index, g) in self.content[..].graphemes(true).enumerate() {
-            if index < at {
-                length += 1;
-                row.push_str(g);
-            } else {
-                split_len += 1;
-                split_res.push_str(g);
+        let at = at.min(self.len());
+        let index = match self.chunks.seek(at, |m| m.graphemes) {
+            // cut the chunk holding `at` in two
+            Some((index, before)) if before.graphemes < at => {
+                let chunk = self.chunks.remove(index);
+                let cut = byte_offset(&chunk.text, at - before.graphemes);
+                self.chunks.insert(index, Chunk::new(&chunk.text[..cut]));
+                self.chunks
+                    .insert(index.saturating_add(1), Chunk::new(&chunk.text[cut..]));
+                index.saturating_add(1)
+            }
+            Some((index, _)) => index,
+            None => self.chunks.len(),
+        };
+        let mut row = Self {
+            chunks: self.chunks.split_off(index),
+            hl_state: self.hl_state,
+            // the new row starts after a line break, so the highlighting can't start in the middle of it
+            hl_input: None,
+            edited: None,
+        };
+        row.mark_edited(0..1);
+        if let Some(edited) = self.edited.take() {
+            if edited.end > at {
+                row.mark_edited(edited.start.saturating_sub(at)..edited.end.saturating_sub(at));
+            }
+            if edited.start < at {
+                self.mark_edited(edited.start..edited.end.min(at));
             }
         }
-        self.content = row;
-        self.len = length;
-        Self {
-            content: split_res,
-            len: split_len,
-            highlighting: Vec::new(),
-        }
+        self.mark_edited(at.saturating_sub(1)..at);
+        row
+    }
+
+    /// the whole text of the row, borrowed if it is in one chunk
+    fn contents(&self) -> Cow<'_, str> {
+        self.slice(0, self.len())
     }
 
     #[must_use]
-    pub fn as_bytes(&self) -> &[u8] {
-        self.content.as_bytes()
+    pub fn find(&self, query: &Query, at: usize, direction: SearchDirection) -> Option<usize> {
+        self.find_match(query, at, direction)
+            .map(|(start, _)| start)
     }
 
+    /// find the grapheme range `(start, end)` of a match after (or before) `at`
     #[must_use]
-    pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
-        if at > self.len || query.is_empty() {
+    pub fn find_match(
+        &self,
+        query: &Query,
+        at: usize,
+        direction: SearchDirection,
+    ) -> Option<(usize, usize)> {
+        if at > self.len() || query.is_empty() {
             return None;
         }
-
-        let start;
-        let end;
-        if direction == SearchDirection::Forward {
-            start = at;
-            end = self.len;
+        let contents = self.contents();
+        let at = self.byte_index(at);
+        let range = if direction == SearchDirection::Forward {
+            at..contents.len()
         } else {
-            start = 0;
-            end = at;
-        }
-
-        let content: String = self.content[..]
-            .graphemes(true)
-            .skip(start)
-            .take(end - start)
-            .collect();
+            0..at
+        };
+        let (start, end) = query.find_in(&contents, range, direction)?;
+        Some((self.grapheme_index(start), self.grapheme_index(end)))
+    }
 
-        // find the first occurence by string api, match_index is the index of target's first byte
-        let match_index = if direction == SearchDirection::Forward {
-            content.find(query)
+    /// the number of graphemes starting before the byte index
+    fn grapheme_index(&self, byte_index: usize) -> usize {
+        let Some((index, before)) = self.chunks.seek(byte_index, |m| m.bytes) else {
+            return self.len();
+        };
+        let text = &self.chunks[index].text;
+        let offset = byte_index.saturating_sub(before.bytes);
+        let count = if text.is_ascii() {
+            offset
         } else {
-            content.rfind(query)
+            text.grapheme_indices(true)
+                .take_while(|(index, _)| *index < offset)
+                .count()
         };
+        before.graphemes.saturating_add(count)
+    }
+
+    /// highlight the whole row, `state` is the construct left open by the previous row.
+    /// return the construct left open by this row
+    pub fn highlight(
+        &mut self,
+        query: Option<&Query>,
+        hl_opts: &HighlightingOptions,
+        state: State,
+    ) -> State {
+        self.scan(query, hl_opts, state, false)
+    }
+
+    /// highlight the row again after it is edited, or after the construct left open
+    /// by the previous row has changed. the row must have been highlighted with the same settings.
+    /// only the chunks around the edits are highlighted, unless the matches of a search are shown
+    pub fn highlight_edits(
+        &mut self,
+        query: Option<&Query>,
+        hl_opts: &HighlightingOptions,
+        state: State,
+    ) -> State {
+        self.scan(query, hl_opts, state, query.is_none())
+    }
 
-        // TODO: why not search the whole string with a single loop. Current solution iterates through the string for 3 times
-        if let Some(match_index) = match_index {
-            // we should find the unicode byte corresponding to that byteindex
-            for (g, (byte_index, _)) in content[..].grapheme_indices(true).enumerate() {
-                if match_index == byte_index {
-                    return Some(start + g);
+    /// forget how the row was highlighted, e.g. when the settings have changed since then
+    pub fn forget_highlighting(&mut self) {
+        self.hl_input = None;
+        for index in 0..self.chunks.len() {
+            self.chunks[index].token_start = false;
+        }
+    }
+
+    /// highlight the row from a chunk where a token started before the edits.
+    /// with `reuse`, the highlighting stops at a chunk after the edits where a token started last time,
+    /// everything after it would be highlighted the same way again
+    fn scan(
+        &mut self,
+        query: Option<&Query>,
+        hl_opts: &HighlightingOptions,
+        state: State,
+        reuse: bool,
+    ) -> State {
+        let edited = self.edited.take();
+        // the highlighting can start after the row start only if the row started the same way
+        let (restart, stop) = match (reuse, self.hl_input == Some(state), edited) {
+            (true, true, None) => return self.hl_state,
+            (true, true, Some(edited)) => (edited.start, edited.end),
+            (true, false, edited) => (0, edited.map_or(0, |edited| edited.end)),
+            (false, ..) => (0, usize::MAX),
+        };
+        // a rule may look ahead across the edits, like a number which reaches them,
+        // so the highlighting starts again a chunk before them
+        let restart = restart.saturating_sub(MAX_CHUNK);
+        let count = self.chunks.len();
+        // the chunk to start at, where a token started last time, and the graphemes before it
+        let (mut first, mut before) = self
+            .chunks
+            .seek(restart, |m| m.graphemes)
+            .map_or((count, self.len()), |(index, before)| {
+                (index, before.graphemes)
+            });
+        while first > 0 && (first == count || !self.chunks[first].token_start) {
+            first -= 1;
+            before = before.saturating_sub(self.chunks[first].len);
+        }
+        let lookbehind = first
+            .checked_sub(1)
+            .and_then(|index| self.chunks[index].text.chars().next_back());
+        let (highlighting, hl_state, token_starts, converged) = {
+            let chunks = &self.chunks;
+            let mut scanner = Scanner::new(
+                lookbehind,
+                (first..count).map(|index| chunks[index].text.as_str()),
+            );
+            let mut index = usize::from(lookbehind.is_some());
+            if first == 0 {
+                // finish the construct opened by the previous row first
+                match state {
+                    State::Comment(open) => {
+                        scanner.highlight_block_comment(hl_opts, &mut index, Some(open));
+                    }
+                    State::String(open) => {
+                        scanner.highlight_strings(hl_opts, &mut index, Some(open));
+                    }
+                    State::Normal => (),
+                }
+            }
+            // whether a token started at the start of each chunk highlighted
+            let mut token_starts = Vec::new();
+            // the character index and the grapheme index where the next chunk starts
+            let mut chunk_end = index;
+            let mut chunk_end_grapheme = before;
+            let mut converged = false;
+            loop {
+                // the chunks passed by the last token
+                while index >= chunk_end && first.saturating_add(token_starts.len()) < count {
+                    let chunk = &chunks[first.saturating_add(token_starts.len())];
+                    let token_start = index == chunk_end;
+                    if reuse && token_start && chunk.token_start && chunk_end_grapheme > stop {
+                        converged = true;
+                        break;
+                    }
+                    token_starts.push(token_start);
+                    chunk_end = chunk_end.saturating_add(chunk.text.chars().count());
+                    chunk_end_grapheme = chunk_end_grapheme.saturating_add(chunk.len);
+                }
+                if converged || scanner.chars.get(index).is_none() {
+                    break;
+                }
+                scanner.chars.drop_before(index.saturating_sub(1));
+                if !scanner.highlight_block_comment(hl_opts, &mut index, None)
+                    && !scanner.highlight_comment(hl_opts, &mut index)
+                    && !scanner.highlight_character(hl_opts, &mut index)
+                    && !scanner.highlight_strings(hl_opts, &mut index, None)
+                    && !scanner.highlight_number(hl_opts, &mut index)
+                    && !scanner.highlight_primary_keys(hl_opts, &mut index)
+                    && !scanner.highlight_secondary_keys(hl_opts, &mut index)
+                {
+                    scanner.highlighting.push(Type::None);
+                    index += 1;
                 }
             }
+            (
+                scanner.highlighting,
+                scanner.hl_state,
+                token_starts,
+                converged,
+            )
+        };
+        // the rules work on characters, but the row is drawn grapheme by grapheme
+        let mut types = highlighting.into_iter();
+        for (offset, token_start) in token_starts.into_iter().enumerate() {
+            let chunk = &mut self.chunks[first.saturating_add(offset)];
+            chunk.highlighting = grapheme_types(&chunk.text, chunk.len, &mut types);
+            chunk.token_start = token_start;
         }
-        None
+        if !converged {
+            self.hl_state = hl_state;
+        }
+        self.hl_input = Some(state);
+        if query.is_some() {
+            self.highlight_match(query);
+            // the matches are drawn over the highlighting, it can't be kept for the next edits
+            self.forget_highlighting();
+        }
+        self.hl_state
     }
 
-    pub fn highlight(&mut self, query: Option<&str>, hl_opts: &HighlightingOptions) {
-        self.highlighting.clear();
-        let chars: Vec<char> = self.content.chars().collect();
-        let mut index = 0;
-        while chars.get(index).is_some() {
-            if !self.highlight_comment(&chars, hl_opts, &mut index)
-                && !self.highlight_character(&chars, hl_opts, &mut index)
-                && !self.highlight_strings(&chars, hl_opts, &mut index)
-                && !self.highlight_number(&chars, hl_opts, &mut index)
-                && !self.highlight_primary_keys(&chars, hl_opts, &mut index)
-                && !self.highlight_secondary_keys(&chars, hl_opts, &mut index)
-            {
-                self.highlighting.push(Type::None);
-                index += 1;
+    /// the construct left open at the end of this row when it was highlighted last time
+    #[must_use]
+    pub fn hl_state(&self) -> State {
+        self.hl_state
+    }
+
+    pub fn highlight_match(&mut self, query: Option<&Query>) {
+        let Some(query) = query else {
+            return;
+        };
+        if query.is_empty() {
+            return;
+        }
+        // the text is put together once for all the matches
+        let contents = self.contents();
+        let mut matches = Vec::new();
+        let mut search_index = 0;
+        while let Some((start, end)) = query.find_in(
+            &contents,
+            search_index..contents.len(),
+            SearchDirection::Forward,
+        ) {
+            let range = self.grapheme_index(start)..self.grapheme_index(end);
+            // continue from the end of this match
+            search_index = self.byte_index(range.end);
+            matches.push(range);
+        }
+        drop(contents);
+        for range in matches {
+            self.set_highlighting(range, Type::Match);
+        }
+    }
+
+    /// highlight the graphemes in `range` as `htype`
+    fn set_highlighting(&mut self, range: Range<usize>, htype: Type) {
+        let Some((first, before)) = self.chunks.seek(range.start, |m| m.graphemes) else {
+            return;
+        };
+        let mut at = before.graphemes;
+        for index in first..self.chunks.len() {
+            if at >= range.end {
+                break;
             }
+            let chunk = &mut self.chunks[index];
+            chunk.highlighting.resize(chunk.len, Type::None);
+            let start = range.start.saturating_sub(at).min(chunk.len);
+            let end = range.end.saturating_sub(at).min(chunk.len);
+            chunk.highlighting[start..end].fill(htype);
+            at = at.saturating_add(chunk.len);
         }
+    }
+}
 
-        self.highlight_match(query);
+impl From<&str> for Row {
+    fn from(value: &str) -> Self {
+        Self {
+            chunks: chunks_of(value).into_iter().collect(),
+            ..Self::default()
+        }
     }
+}
 
-    fn highlight_target_str(
-        &mut self,
-        chars: &Vec<char>,
-        target: &str,
-        index: &mut usize,
-        hl_type: Type,
-    ) -> bool {
-        if *index > 0 && !self.is_separator(chars[*index - 1]) {
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunks
+            .iter()
+            .try_for_each(|chunk| f.write_str(&chunk.text))
+    }
+}
+
+/// the characters of a row, read chunk by chunk as the highlighting goes on
+struct Chars<'a> {
+    chunks: Box<dyn Iterator<Item = &'a str> + 'a>,
+    // the characters read and not dropped yet, the first one is at index `start`
+    buffer: Vec<char>,
+    start: usize,
+}
+
+impl Chars<'_> {
+    #[inline]
+    fn get(&mut self, index: usize) -> Option<char> {
+        let offset = index.checked_sub(self.start)?;
+        match self.buffer.get(offset) {
+            Some(c) => Some(*c),
+            None => self.read(offset),
+        }
+    }
+
+    /// read the chunks up to the character at `offset` in the buffer
+    #[cold]
+    fn read(&mut self, offset: usize) -> Option<char> {
+        while offset >= self.buffer.len() {
+            self.buffer.extend(self.chunks.next()?.chars());
+        }
+        self.buffer.get(offset).copied()
+    }
+
+    /// forget the characters before `index`, no rule looks further back.
+    /// they are dropped a chunk at a time, so that they are not moved again and again
+    fn drop_before(&mut self, index: usize) {
+        let count = index.saturating_sub(self.start).min(self.buffer.len());
+        if count >= MAX_CHUNK {
+            self.buffer.drain(..count);
+            self.start = self.start.saturating_add(count);
+        }
+    }
+}
+
+/// the rules of highlighting, they read the characters from `chars`
+/// and push one type for each character they highlight
+struct Scanner<'a> {
+    chars: Chars<'a>,
+    highlighting: Vec<Type>,
+    // the construct left open at the end of the row
+    hl_state: State,
+}
+
+impl<'a> Scanner<'a> {
+    /// read the characters of `chunks`, after `lookbehind`, the character before them if there is one
+    fn new(lookbehind: Option<char>, chunks: impl Iterator<Item = &'a str> + 'a) -> Self {
+        Self {
+            chars: Chars {
+                chunks: Box::new(chunks),
+                buffer: lookbehind.into_iter().collect(),
+                start: 0,
+            },
+            highlighting: Vec::new(),
+            hl_state: State::Normal,
+        }
+    }
+
+    fn highlight_target_str(&mut self, target: &str, index: &mut usize, hl_type: Type) -> bool {
+        // most keywords don't even start with the character, so it is checked first
+        if target.chars().next() != self.chars.get(*index) {
+            return false;
+        }
+        if *index > 0 && !self.chars.get(*index - 1).is_some_and(Self::is_separator) {
             return false;
         }
 
-        if let Some(c) = chars.get(target.len().saturating_add(*index)) {
-            if !self.is_separator(*c) {
+        if let Some(c) = self.chars.get(target.len().saturating_add(*index)) {
+            if !Self::is_separator(c) {
                 return false;
             }
         }
@@ -222,8 +776,8 @@ impl Row {
             return false;
         }
         for (i, c) in target.chars().enumerate() {
-            if let Some(next_char) = chars.get(i.saturating_add(*index)) {
-                if *next_char != c {
+            if let Some(next_char) = self.chars.get(i.saturating_add(*index)) {
+                if next_char != c {
                     return false;
                 }
             } else {
@@ -237,17 +791,12 @@ impl Row {
         true
     }
 
-    fn highlight_primary_keys(
-        &mut self,
-        chars: &Vec<char>,
-        hl_opts: &HighlightingOptions,
-        index: &mut usize,
-    ) -> bool {
+    fn highlight_primary_keys(&mut self, hl_opts: &HighlightingOptions, index: &mut usize) -> bool {
         if hl_opts.primary_keys().is_empty() {
             return false;
         }
         for keyword in hl_opts.primary_keys() {
-            if self.highlight_target_str(chars, keyword, index, Type::PrimaryKey) {
+            if self.highlight_target_str(keyword, index, Type::PrimaryKey) {
                 return true;
             }
         }
@@ -256,7 +805,6 @@ impl Row {
 
     fn highlight_secondary_keys(
         &mut self,
-        chars: &Vec<char>,
         hl_opts: &HighlightingOptions,
         index: &mut usize,
     ) -> bool {
@@ -264,177 +812,340 @@ impl Row {
             return false;
         }
         for keyword in hl_opts.secondary_keys() {
-            if self.highlight_target_str(chars, keyword, index, Type::SecondaryKey) {
+            if self.highlight_target_str(keyword, index, Type::SecondaryKey) {
                 return true;
             }
         }
         false
     }
 
-    fn highlight_number(
-        &mut self,
-        chars: &Vec<char>,
-        hl_opts: &HighlightingOptions,
-        index: &mut usize,
-    ) -> bool {
-        if !hl_opts.numbers() {
+    /// highlight a number literal like `42`, `1.5e3`, `0xff` or `10u32`,
+    /// depending on the number style of the language
+    fn highlight_number(&mut self, hl_opts: &HighlightingOptions, index: &mut usize) -> bool {
+        let Some(style) = hl_opts.numbers() else {
+            return false;
+        };
+        if *index > 0 && !self.chars.get(*index - 1).is_some_and(Self::is_separator) {
             return false;
         }
-        let mut prev_is_number = false;
-        let mut chganged = false;
-        while let Some(c) = chars.get(*index) {
-            if c.is_ascii_digit() || (*c == '.' && prev_is_number) {
-                prev_is_number = true;
-                chganged = true;
-                self.highlighting.push(Type::Number);
-                *index += 1;
-            } else {
-                break;
+        let Some(first) = self.chars.get(*index).filter(char::is_ascii_digit) else {
+            return false;
+        };
+        let prefix = self.chars.get(*index + 1).map(|c| c.to_ascii_lowercase());
+        let radix = match (first, prefix) {
+            ('0', Some('x')) if style.hex => 16,
+            ('0', Some('b')) if style.binary => 2,
+            ('0', Some('o')) if style.octal => 8,
+            _ => 10,
+        };
+        let is_digit = |c: char| c.is_digit(radix) || Some(c) == style.separator;
+        let mut end = *index;
+        if radix != 10 {
+            end += 2;
+            // `0x` alone is not a number
+            if !self.chars.get(end).is_some_and(|c| c.is_digit(radix)) {
+                return false;
             }
         }
-        chganged
+        while self.chars.get(end).is_some_and(is_digit) {
+            end += 1;
+        }
+        if radix == 10 && style.float {
+            if self.chars.get(end) == Some('.')
+                && self.chars.get(end + 1).is_some_and(|c| c.is_ascii_digit())
+            {
+                end += 1;
+                while self.chars.get(end).is_some_and(is_digit) {
+                    end += 1;
+                }
+            }
+            if matches!(self.chars.get(end), Some('e' | 'E')) {
+                let sign = usize::from(matches!(self.chars.get(end + 1), Some('+' | '-')));
+                if self
+                    .chars
+                    .get(end + 1 + sign)
+                    .is_some_and(|c| c.is_ascii_digit())
+                {
+                    end += 1 + sign;
+                    while self.chars.get(end).is_some_and(|c| c.is_ascii_digit()) {
+                        end += 1;
+                    }
+                }
+            }
+        }
+        if let Some(suffix) = style
+            .suffixes
+            .iter()
+            .filter(|suffix| self.starts_with_at(end, suffix))
+            .max_by_key(|suffix| suffix.len())
+        {
+            end += suffix.chars().count();
+        }
+        // e.g. `1abc` is not a number
+        if self.chars.get(end).is_some_and(|c| !Self::is_separator(c)) {
+            return false;
+        }
+        for _ in *index..end {
+            self.highlighting.push(Type::Number);
+        }
+        *index = end;
+        true
+    }
+
+    fn highlight_comment(&mut self, hl_opts: &HighlightingOptions, index: &mut usize) -> bool {
+        if !hl_opts
+            .line_comments()
+            .iter()
+            .any(|token| self.starts_with_at(*index, token))
+        {
+            return false;
+        }
+        while self.chars.get(*index).is_some() {
+            self.highlighting.push(Type::Comment);
+            *index += 1;
+        }
+        true
     }
 
-    fn highlight_comment(
+    /// highlight a block comment like `/* ... */`.
+    /// if `open` is given, we are in the comment opened by the previous rows
+    /// with the `open`th block comment of the language,
+    /// so there is no need to look for the start token
+    fn highlight_block_comment(
         &mut self,
-        chars: &Vec<char>,
         hl_opts: &HighlightingOptions,
         index: &mut usize,
+        open: Option<usize>,
     ) -> bool {
-        if !hl_opts.comments() || chars.get(*index).is_none() {
+        let comments = hl_opts.block_comments();
+        let open = if let Some(open) = open {
+            open
+        } else {
+            let Some(open) = comments
+                .iter()
+                .position(|(start, _)| self.starts_with_at(*index, start))
+            else {
+                return false;
+            };
+            for _ in comments[open].0.chars() {
+                self.highlighting.push(Type::Comment);
+                *index += 1;
+            }
+            open
+        };
+        // the language may have changed since the previous row was highlighted
+        let Some((_, end)) = comments.get(open) else {
             return false;
-        }
-
-        let c = chars.get(*index).unwrap();
-        if let Some(next_char) = chars.get((*index).saturating_add(1)) {
-            if *c == '/' && *next_char == '/' {
-                let start = *index;
-                for _ in start..chars.len() {
+        };
+        while self.chars.get(*index).is_some() {
+            if self.starts_with_at(*index, end) {
+                for _ in end.chars() {
                     self.highlighting.push(Type::Comment);
                     *index += 1;
                 }
                 return true;
             }
+            self.highlighting.push(Type::Comment);
+            *index += 1;
         }
-        false
+        // the comment continues on the next row
+        self.hl_state = State::Comment(open);
+        true
     }
 
-    fn highlight_match(&mut self, query: Option<&str>) {
-        let mut search_index = 0;
-
-        if let Some(query) = query {
-            if query.is_empty() {
-                return;
-            }
-            let query_len = query.graphemes(true).count();
-            while let Some(search_match) = self.find(query, search_index, SearchDirection::Forward)
-            {
-                // add query len to search_index as the next start index
-                // we use checked_add here in that it will return None if the result is out of range
-                if let Some(next_index) = search_match.checked_add(query_len) {
-                    for i in search_match..next_index {
-                        self.highlighting[i] = Type::Match;
-                    }
-                    search_index = next_index;
-                } else {
-                    break;
-                }
-            }
-        }
+    fn starts_with_at(&mut self, index: usize, token: &str) -> bool {
+        !token.is_empty()
+            && token
+                .chars()
+                .enumerate()
+                .all(|(i, c)| self.chars.get(index.saturating_add(i)) == Some(c))
     }
 
-    fn highlight_character(
-        &mut self,
-        chars: &Vec<char>,
-        hl_opts: &HighlightingOptions,
-        index: &mut usize,
-    ) -> bool {
-        if !hl_opts.characters() {
+    fn highlight_character(&mut self, hl_opts: &HighlightingOptions, index: &mut usize) -> bool {
+        if !hl_opts.characters() || self.chars.get(*index) != Some('\'') {
             return false;
         }
-        if let Some(c) = chars.get(*index) {
-            if *c != '\'' {
-                return false;
-            }
+        let escaped = hl_opts.escape().is_some() && self.chars.get(*index + 1) == hl_opts.escape();
+        let end = if escaped { *index + 4 } else { *index + 3 };
+        // e.g. the lifetime `'a` is not a character
+        if self.chars.get(end - 1) != Some('\'') {
+            return false;
         }
-        let start_index = *index;
-        if let Some(next_char) = chars.get(*index + 1) {
-            if *next_char == '\\' {
-                *index += 4;
-            } else {
-                *index += 3;
-            }
-            for _ in start_index..*index {
-                self.highlighting.push(Type::Character);
-            }
-            return true;
+        for _ in *index..end {
+            self.highlighting.push(Type::Character);
         }
-        false
+        *index = end;
+        true
     }
 
+    /// highlight a string between two delimiters of the language.
+    /// if `open` is given, we are in the string opened by the previous rows
+    /// with the `open`th delimiter, so there is no need to look for the opening one
     fn highlight_strings(
         &mut self,
-        chars: &Vec<char>,
         hl_opts: &HighlightingOptions,
         index: &mut usize,
+        open: Option<usize>,
     ) -> bool {
-        if !hl_opts.strings() {
-            return false;
-        }
-
-        // if this character is in string, push a Type::String
-        // if current character is '"', it means we have been in the end of string
-        if let Some(c) = chars.get(*index) {
-            if *c != '"' {
+        let delimiters = hl_opts.strings();
+        let open = if let Some(open) = open {
+            open
+        } else {
+            // the longest delimiter wins, e.g. `"""` over `"`
+            let Some((open, delimiter)) = delimiters
+                .iter()
+                .enumerate()
+                .filter(|(_, delimiter)| self.starts_with_at(*index, &delimiter.token))
+                .max_by_key(|(_, delimiter)| delimiter.token.len())
+            else {
                 return false;
-            }
-        }
-        *index += 1;
-        self.highlighting.push(Type::String);
-        while let Some(ch) = chars.get(*index) {
-            if *ch == '"' {
+            };
+            for _ in delimiter.token.chars() {
                 self.highlighting.push(Type::String);
                 *index += 1;
+            }
+            open
+        };
+        let Some(delimiter) = delimiters.get(open) else {
+            return false;
+        };
+        while let Some(ch) = self.chars.get(*index) {
+            if self.starts_with_at(*index, &delimiter.token) {
+                for _ in delimiter.token.chars() {
+                    self.highlighting.push(Type::String);
+                    *index += 1;
+                }
                 return true;
-            } else if *ch == '\\' {
-                self.highlighting.push(Type::Escape);
-                self.highlighting.push(Type::Escape);
-                *index += 2;
+            } else if Some(ch) == hl_opts.escape() {
+                // the escaped character may be the line break at the end of the row
+                let len = if self.chars.get(*index + 1).is_some() {
+                    2
+                } else {
+                    1
+                };
+                for _ in 0..len {
+                    self.highlighting.push(Type::Escape);
+                }
+                *index += len;
             } else {
                 self.highlighting.push(Type::String);
                 *index += 1;
             }
         }
-        false
+        // the string is not closed in this row
+        if delimiter.multiline {
+            self.hl_state = State::String(open);
+        }
+        true
     }
 
-    fn is_separator(&self, ch: char) -> bool {
+    fn is_separator(ch: char) -> bool {
         ch.is_ascii_whitespace() || ch.is_ascii_punctuation()
     }
 }
 
-impl From<&str> for Row {
-    fn from(value: &str) -> Self {
-        Self {
-            content: String::from(value),
-            highlighting: Vec::new(),
-            len: value.graphemes(true).count(),
+/// the number of graphemes in `text`
+fn grapheme_count(text: &str) -> usize {
+    // "\r\n" is the only ascii grapheme of two characters, and a row never contains '\n'
+    if text.is_ascii() {
+        text.len()
+    } else {
+        text.graphemes(true).count()
+    }
+}
+
+/// the byte index of the grapheme at index `at` in `text`, or the length of `text` if `at` is out of range
+fn byte_offset(text: &str, at: usize) -> usize {
+    // every grapheme of an ascii string is a single byte, so we don't need to walk through it
+    if text.is_ascii() {
+        return at.min(text.len());
+    }
+    text.grapheme_indices(true)
+        .nth(at)
+        .map_or(text.len(), |(index, _)| index)
+}
+
+/// cut `text` into chunks of at most `MAX_CHUNK` bytes between graphemes
+fn chunks_of(text: &str) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    if text.is_ascii() {
+        while start < text.len() {
+            let end = start.saturating_add(MAX_CHUNK).min(text.len());
+            chunks.push(Chunk::new(&text[start..end]));
+            start = end;
         }
+        return chunks;
     }
+    for (index, g) in text.grapheme_indices(true) {
+        if index > start && index.saturating_add(g.len()) - start > MAX_CHUNK {
+            chunks.push(Chunk::new(&text[start..index]));
+            start = index;
+        }
+    }
+    if start < text.len() {
+        chunks.push(Chunk::new(&text[start..]));
+    }
+    chunks
+}
+
+/// the type of each grapheme of a chunk taken from the types of its characters,
+/// a grapheme takes the type of its first character
+fn grapheme_types(text: &str, len: usize, types: &mut impl Iterator<Item = Type>) -> Vec<Type> {
+    if text.len() == len {
+        return types.take(len).collect();
+    }
+    text.graphemes(true)
+        .map(|g| {
+            let htype = types.next().unwrap_or(Type::None);
+            types
+                .by_ref()
+                .take(g.chars().count().saturating_sub(1))
+                .for_each(drop);
+            htype
+        })
+        .collect()
+}
+
+fn is_word(g: &str) -> bool {
+    g.chars()
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
 }
 
+/// the number of columns taken by `g` when it is shown at `column`, a tab reaches the next tab stop.
+/// East Asian wide characters and emoji take 2 columns,
+/// every other grapheme takes at least one, so that the cursor can be put on it
+fn grapheme_width(g: &str, column: usize, tab_width: usize) -> usize {
+    if g == "\t" {
+        let tab_width = tab_width.max(1);
+        tab_width - column % tab_width
+    } else {
+        g.width().max(1)
+    }
+}
+
+#[cfg(test)]
 mod row_tests {
-    use crate::FileType;
+    use std::iter;
+
+    use termion::style;
 
-    use super::*;
+    use crate::{
+        highlighting::{State, Type},
+        search::{Query, SearchOptions},
+        theme::{self, Theme},
+        FileType, HighlightingOptions, SearchDirection,
+    };
+
+    use super::{Row, Scanner, MAX_CHUNK};
 
     #[test]
     fn highlight_strings_test() {
-        let mut row = Row::from("\"h\\nello\"");
+        let mut row = scanner("\"h\\nello\"");
         let hl_opts = FileType::from("a.rs").highlighting_opts().clone();
-        let chars: Vec<char> = row.content.chars().collect();
         let mut index = 0;
-        row.highlight_strings(&chars, &hl_opts, &mut index);
+        row.highlight_strings(&hl_opts, &mut index, None);
         let mut expected = Vec::new();
         for i in 0..9 {
             if i == 2 || i == 3 {
@@ -452,10 +1163,9 @@ mod row_tests {
 
     #[test]
     fn highlight_character_test() {
-        let (mut row, hl_opts) = create_row("'1'");
+        let (mut row, hl_opts) = create_scanner("'1'");
         let mut index = 0;
-        let chars = row.content.chars().collect();
-        row.highlight_character(&chars, &hl_opts, &mut index);
+        row.highlight_character(&hl_opts, &mut index);
         let mut expected = Vec::new();
         for _i in 0..3 {
             expected.push(Type::Character);
@@ -467,10 +1177,9 @@ mod row_tests {
         );
         assert_eq!(index, 3);
 
-        let (mut row, hl_opts) = create_row("'\\n'");
+        let (mut row, hl_opts) = create_scanner("'\\n'");
         let mut index = 0;
-        let chs = row.content.chars().collect();
-        row.highlight_character(&chs, &hl_opts, &mut index);
+        row.highlight_character(&hl_opts, &mut index);
         expected.clear();
         for _ in 0..4 {
             expected.push(Type::Character);
@@ -485,9 +1194,9 @@ mod row_tests {
 
     #[test]
     fn highlight_comment_test() {
-        let (mut row, hl_opts) = create_row("// this is a comment");
+        let (mut row, hl_opts) = create_scanner("// this is a comment");
         let mut index = 0;
-        row.highlight_comment(&row.content.chars().collect(), &hl_opts, &mut index);
+        row.highlight_comment(&hl_opts, &mut index);
         let mut expected = Vec::new();
         for _i in 0..20 {
             expected.push(Type::Comment);
@@ -500,26 +1209,93 @@ mod row_tests {
         assert_eq!(index, 20);
     }
 
+    #[test]
+    fn highlight_block_comment_test() {
+        let (mut row, hl_opts) = create_row("a /* b");
+        assert_eq!(
+            row.highlight(None, &hl_opts, State::Normal),
+            State::Comment(0)
+        );
+        let mut expected = vec![Type::None, Type::None];
+        expected.extend([Type::Comment; 4]);
+        assert_eq!(highlighting(&row), expected);
+
+        // the row continues the comment opened by the previous row
+        let (mut row, hl_opts) = create_row("b */ c");
+        assert_eq!(
+            row.highlight(None, &hl_opts, State::Comment(0)),
+            State::Normal
+        );
+        let mut expected = vec![Type::Comment; 4];
+        expected.extend([Type::None, Type::None]);
+        assert_eq!(highlighting(&row), expected);
+
+        let (mut row, hl_opts) = create_row("/* a */ 1");
+        assert_eq!(row.highlight(None, &hl_opts, State::Normal), State::Normal);
+        assert_eq!(highlighting(&row)[8], Type::Number);
+    }
+
+    #[test]
+    fn highlight_multiline_string_test() {
+        let (mut row, hl_opts) = create_row("let s = \"a");
+        assert_eq!(
+            row.highlight(None, &hl_opts, State::Normal),
+            State::String(0)
+        );
+        let (mut row, hl_opts) = create_row("b\" // c");
+        assert_eq!(
+            row.highlight(None, &hl_opts, State::String(0)),
+            State::Normal
+        );
+        assert_eq!(highlighting(&row)[..2], [Type::String, Type::String]);
+        assert_eq!(highlighting(&row)[3], Type::Comment);
+    }
+
     #[test]
     fn highlight_match_test() {
         let (mut row, _hl_opts) = create_row("111");
-        for _ in 0..3 {
-            row.highlighting.push(Type::None);
-        }
-        row.highlight_match(Some("1"));
+        row.highlight_match(Some(&Query::from("1")));
         let expected = vec![Type::Match, Type::Match, Type::Match];
         assert_eq!(
-            row.highlighting, expected,
+            highlighting(&row),
+            expected,
             "res: {:#?}, expected: {:#?}",
-            row.highlighting, expected
+            highlighting(&row),
+            expected
+        );
+    }
+
+    #[test]
+    fn find_regex_test() {
+        let row = Row::from("let ä = fn_äb(1); fn ab(");
+        let options = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        let query = Query::new(r"fn \w+\(", options).unwrap();
+        assert_eq!(
+            row.find_match(&query, 0, SearchDirection::Forward),
+            Some((18, 24))
+        );
+        let query = Query::new(r"fn_\w+", options).unwrap();
+        assert_eq!(
+            row.find_match(&query, 0, SearchDirection::Forward),
+            Some((8, 13))
+        );
+        assert_eq!(row.find(&query, 9, SearchDirection::Forward), None);
+        assert_eq!(row.find(&query, 13, SearchDirection::Backward), Some(8));
+        // the part of the match before the cursor is found
+        assert_eq!(
+            row.find_match(&query, 12, SearchDirection::Backward),
+            Some((8, 12))
         );
     }
 
     #[test]
     fn highlight_number_test() {
-        let (mut row, hl_opts) = create_row("1");
+        let (mut row, hl_opts) = create_scanner("1");
         let mut index = 0;
-        row.highlight_number(&row.content.chars().collect(), &hl_opts, &mut index);
+        row.highlight_number(&hl_opts, &mut index);
         // let mut expected = Vec::new();
 
         assert_eq!(
@@ -532,9 +1308,8 @@ mod row_tests {
         assert_eq!(index, 1);
 
         index = 0;
-        row.content = "1.0".to_string();
-        row.highlighting.clear();
-        row.highlight_number(&row.content.chars().collect(), &hl_opts, &mut index);
+        row = scanner("1.0");
+        row.highlight_number(&hl_opts, &mut index);
         assert_eq!(
             row.highlighting,
             vec![Type::Number, Type::Number, Type::Number],
@@ -544,9 +1319,343 @@ mod row_tests {
         );
 
         index = 0;
-        row.content = "\"1.0\"".to_string();
-        row.highlighting.clear();
-        assert!(!row.highlight_number(&row.content.chars().collect(), &hl_opts, &mut index));
+        row = scanner("\"1.0\"");
+        assert!(!row.highlight_number(&hl_opts, &mut index));
+    }
+
+    #[test]
+    fn highlight_number_styles_test() {
+        let numbers = |text: &str| -> Vec<bool> {
+            let (mut row, hl_opts) = create_row(text);
+            row.highlight(None, &hl_opts, State::Normal);
+            highlighting(&row)
+                .iter()
+                .map(|t| *t == Type::Number)
+                .collect()
+        };
+        assert_eq!(numbers("0xff_u8"), vec![true; 7]);
+        assert_eq!(numbers("0b101"), vec![true; 5]);
+        assert_eq!(numbers("1.5e-3f64"), vec![true; 9]);
+        assert_eq!(numbers("1_000usize"), vec![true; 10]);
+        // the range operator is not a part of the number
+        assert_eq!(numbers("1..2"), vec![true, false, false, true]);
+        assert_eq!(numbers("x1 1x"), vec![false; 5]);
+        assert_eq!(numbers("0x"), vec![false; 2]);
+    }
+
+    #[test]
+    fn highlight_string_delimiters_test() {
+        let languages = FileType::parse(
+            r##"{"py": {
+                "comments": { "line": "#" },
+                "strings": { "delimiters": ["\"\"\"", "\"", "'"], "multiline": ["\"\"\""] }
+            }}"##,
+        )
+        .unwrap();
+        let hl_opts = languages[0].highlighting_opts();
+        let mut row = Row::from(r#"'a"' """b"#);
+        assert_eq!(
+            row.highlight(None, hl_opts, State::Normal),
+            State::String(0)
+        );
+        let mut expected = vec![Type::String; 4];
+        expected.push(Type::None);
+        expected.extend([Type::String; 4]);
+        assert_eq!(highlighting(&row), expected);
+        // `"` doesn't close a string opened by `"""`
+        let mut row = Row::from(r#"c" """ # d"#);
+        assert_eq!(
+            row.highlight(None, hl_opts, State::String(0)),
+            State::Normal
+        );
+        assert_eq!(highlighting(&row)[..6], [Type::String; 6]);
+        assert_eq!(highlighting(&row)[7..], [Type::Comment; 3]);
+        // a string opened by `'` ends with the row
+        let mut row = Row::from("'e");
+        assert_eq!(row.highlight(None, hl_opts, State::Normal), State::Normal);
+        // lifetimes are not characters
+        let (mut row, hl_opts) = create_row("&'a str");
+        row.highlight(None, &hl_opts, State::Normal);
+        assert!(!highlighting(&row).contains(&Type::Character));
+    }
+
+    /// highlight `text` as a row of a file called `filename`,
+    /// then check the type of each part, a part is found by its first occurrence in `text`
+    fn assert_highlighted(filename: &str, text: &str, parts: &[(&str, Type)]) {
+        let filetype = FileType::from(filename);
+        assert_ne!(filetype.name(), FileType::default().name(), "{filename}");
+        let mut row = Row::from(text);
+        row.highlight(None, filetype.highlighting_opts(), State::Normal);
+        for (part, expected) in parts {
+            let start = text.find(part).expect("the part is not in the text");
+            let start = text[..start].chars().count();
+            let end = start + part.chars().count();
+            assert_eq!(
+                highlighting(&row)[start..end],
+                vec![*expected; end - start],
+                "{part:?} in {text:?} of {filename}"
+            );
+        }
+    }
+
+    #[test]
+    fn highlight_c() {
+        let text = r#"if (n == 0x1Fu) return "a\n"; // 1.5f"#;
+        let parts = [
+            ("if", Type::PrimaryKey),
+            ("0x1Fu", Type::Number),
+            ("return", Type::PrimaryKey),
+            ("\"a", Type::String),
+            ("\\n", Type::Escape),
+            ("// 1.5f", Type::Comment),
+        ];
+        assert_highlighted("main.c", text, &parts);
+        assert_highlighted(
+            "main.h",
+            "#include <stdio.h>",
+            &[("#include", Type::PrimaryKey)],
+        );
+        assert_highlighted(
+            "main.c",
+            "unsigned char c = 'x'; /* 2 */ 1.5f",
+            &[
+                ("unsigned", Type::SecondaryKey),
+                ("'x'", Type::Character),
+                ("/* 2 */", Type::Comment),
+                ("1.5f", Type::Number),
+            ],
+        );
+    }
+
+    #[test]
+    fn highlight_cpp() {
+        assert_highlighted(
+            "main.cpp",
+            r#"constexpr auto n = 1'000ull; std::string s = "x"; // y"#,
+            &[
+                ("constexpr", Type::PrimaryKey),
+                ("auto", Type::PrimaryKey),
+                ("1'000ull", Type::Number),
+                ("std", Type::SecondaryKey),
+                ("\"x\"", Type::String),
+                ("// y", Type::Comment),
+            ],
+        );
+        assert_highlighted("a.hpp", "namespace a {", &[("namespace", Type::PrimaryKey)]);
+    }
+
+    #[test]
+    fn highlight_python() {
+        assert_highlighted(
+            "main.py",
+            r#"def f(x): return x + 0o17 + 2j if x is not None else 'a"b' # c"#,
+            &[
+                ("def", Type::PrimaryKey),
+                ("return", Type::PrimaryKey),
+                ("0o17", Type::Number),
+                ("2j", Type::Number),
+                ("None", Type::SecondaryKey),
+                ("'a\"b'", Type::String),
+                ("# c", Type::Comment),
+            ],
+        );
+        let filetype = FileType::from("main.py");
+        let mut row = Row::from(r#"s = """doc"#);
+        assert_eq!(
+            row.highlight(None, filetype.highlighting_opts(), State::Normal),
+            State::String(0)
+        );
+    }
+
+    #[test]
+    fn highlight_go() {
+        assert_highlighted(
+            "main.go",
+            r#"func main() { var r rune = 'x'; fmt.Println("hi", 1_000) } // end"#,
+            &[
+                ("func", Type::PrimaryKey),
+                ("var", Type::PrimaryKey),
+                ("rune", Type::SecondaryKey),
+                ("'x'", Type::Character),
+                ("\"hi\"", Type::String),
+                ("1_000", Type::Number),
+                ("// end", Type::Comment),
+            ],
+        );
+        let filetype = FileType::from("main.go");
+        let mut row = Row::from("s := `raw");
+        assert_eq!(
+            row.highlight(None, filetype.highlighting_opts(), State::Normal),
+            State::String(1)
+        );
+    }
+
+    #[test]
+    fn highlight_javascript_and_typescript() {
+        let text = "const n = 10n; let s = 'a' + `b`; // c";
+        let parts = [
+            ("const", Type::PrimaryKey),
+            ("10n", Type::Number),
+            ("let", Type::PrimaryKey),
+            ("'a'", Type::String),
+            ("`b`", Type::String),
+            ("// c", Type::Comment),
+        ];
+        assert_highlighted("main.js", text, &parts);
+        assert_highlighted("main.ts", text, &parts);
+        assert_highlighted(
+            "main.ts",
+            "interface A { n: number; /* 0xff */ }",
+            &[
+                ("interface", Type::PrimaryKey),
+                ("number", Type::SecondaryKey),
+                ("/* 0xff */", Type::Comment),
+            ],
+        );
+    }
+
+    #[test]
+    fn highlight_shell() {
+        let text = r#"if [ "$1" = 'x' ]; then echo 42; fi # done"#;
+        let parts = [
+            ("if", Type::PrimaryKey),
+            ("\"$1\"", Type::String),
+            ("'x'", Type::String),
+            ("then", Type::PrimaryKey),
+            ("echo", Type::SecondaryKey),
+            ("42", Type::Number),
+            ("fi", Type::PrimaryKey),
+            ("# done", Type::Comment),
+        ];
+        assert_highlighted("run.sh", text, &parts);
+        assert_highlighted(".bashrc", text, &parts);
+    }
+
+    #[test]
+    fn highlight_toml() {
+        assert_highlighted(
+            "Cargo.toml",
+            r#"version = "1.0" # x"#,
+            &[("\"1.0\"", Type::String), ("# x", Type::Comment)],
+        );
+        assert_highlighted(
+            "a.toml",
+            "opt = true; n = 0xdead_beef; f = 1e6",
+            &[
+                ("true", Type::PrimaryKey),
+                ("0xdead_beef", Type::Number),
+                ("1e6", Type::Number),
+            ],
+        );
+    }
+
+    #[test]
+    fn highlight_yaml() {
+        assert_highlighted(
+            "a.yml",
+            r#"key: "v" # c"#,
+            &[("\"v\"", Type::String), ("# c", Type::Comment)],
+        );
+        assert_highlighted(
+            "a.yaml",
+            "on: yes, n: 3.5",
+            &[
+                ("on", Type::PrimaryKey),
+                ("yes", Type::PrimaryKey),
+                ("3.5", Type::Number),
+            ],
+        );
+    }
+
+    #[test]
+    fn highlight_json() {
+        assert_highlighted(
+            "a.json",
+            r#"{"a": [1.5e3, true, null, "\u00e9"]}"#,
+            &[
+                ("\"a\"", Type::String),
+                ("1.5e3", Type::Number),
+                ("true", Type::PrimaryKey),
+                ("null", Type::PrimaryKey),
+                ("\\u", Type::Escape),
+            ],
+        );
+    }
+
+    #[test]
+    fn highlight_markdown() {
+        assert_highlighted(
+            "README.md",
+            r"## Use `cargo run` <!-- 1 -->",
+            &[
+                ("##", Type::PrimaryKey),
+                ("`cargo run`", Type::String),
+                ("<!-- 1 -->", Type::Comment),
+            ],
+        );
+        // numbers in prose are not highlighted, a backslash is not an escape
+        assert_highlighted(
+            "a.md",
+            r"step 2 `a\`",
+            &[("2", Type::None), ("`a\\`", Type::String)],
+        );
+        let filetype = FileType::from("a.md");
+        let mut row = Row::from("```rust");
+        assert_eq!(
+            row.highlight(None, filetype.highlighting_opts(), State::Normal),
+            State::String(0)
+        );
+    }
+
+    #[test]
+    fn highlight_makefile() {
+        let text = r#"ifeq ($(OS), "x") # 1"#;
+        let parts = [
+            ("ifeq", Type::PrimaryKey),
+            ("\"x\"", Type::String),
+            ("# 1", Type::Comment),
+        ];
+        assert_highlighted("Makefile", text, &parts);
+        assert_highlighted("rules.mk", text, &parts);
+        assert_highlighted(
+            "GNUmakefile",
+            "SRC := $(wildcard *.c) 8",
+            &[("wildcard", Type::SecondaryKey), ("8", Type::Number)],
+        );
+    }
+
+    #[test]
+    fn highlight_dockerfile() {
+        let text = r#"RUN echo "hi" 'x' 80 # c"#;
+        let parts = [
+            ("RUN", Type::PrimaryKey),
+            ("\"hi\"", Type::String),
+            ("'x'", Type::String),
+            ("80", Type::Number),
+            ("# c", Type::Comment),
+        ];
+        assert_highlighted("Dockerfile", text, &parts);
+        assert_highlighted("Dockerfile.dev", text, &parts);
+        assert_highlighted("app.dockerfile", text, &parts);
+        assert_highlighted(
+            "Containerfile",
+            "FROM rust AS build",
+            &[("AS", Type::PrimaryKey)],
+        );
+    }
+
+    fn scanner(text: &str) -> Scanner<'_> {
+        Scanner::new(None, iter::once(text))
+    }
+
+    fn create_scanner(text: &str) -> (Scanner<'_>, HighlightingOptions) {
+        let hl_opts = FileType::from("a.rs").highlighting_opts().clone();
+        (scanner(text), hl_opts)
+    }
+
+    /// the highlighting of every grapheme of the row
+    fn highlighting(row: &Row) -> Vec<Type> {
+        row.styled_graphemes().map(|(_, htype)| htype).collect()
     }
 
     fn create_row(string: &str) -> (Row, HighlightingOptions) {
@@ -555,10 +1664,225 @@ mod row_tests {
         (row, hl_opts)
     }
 
+    #[test]
     fn render_test() {
+        let theme = default_theme();
         let (mut row, hl_opts) = create_row("1");
-        let mut index = 0;
-        row.highlight(None, &hl_opts);
-        
+        row.highlight(None, &hl_opts, State::Normal);
+        let rendered = row.render(0, 1, None, 4, &theme);
+        assert!(rendered.contains('1'), "res: {rendered:?}");
+
+        // the selection is drawn on top of the colors of the text
+        let (mut row, _) = create_row("a\"b\"");
+        row.highlight(None, &hl_opts, State::Normal);
+        let rendered = row.render(0, 4, Some(&(1..3)), 4, &theme);
+        let selected = theme.string.patch(theme.selection);
+        let expected = format!("{}a{selected}\"b{}\"", theme.text, theme.string);
+        assert!(rendered.contains(&expected), "res: {rendered:?}");
+
+        // so is the line break, when the selection goes on to the next row
+        let rendered = row.render(0, 5, Some(&(3..5)), 4, &theme);
+        let selected = theme.text.patch(theme.selection);
+        assert!(rendered.ends_with(&format!("\"{selected} {}", style::Reset)));
+        assert_eq!(text(&row.render(0, 4, Some(&(3..5)), 4, &theme)), "a\"b\"");
+        assert_eq!(text(&row.render(0, 5, Some(&(3..4)), 4, &theme)), "a\"b\"");
+    }
+
+    fn default_theme() -> Theme {
+        theme::load(None).0.remove(0)
+    }
+
+    /// the text shown by a rendered row, without the escape sequences of colors and styles
+    fn text(rendered: &str) -> String {
+        rendered
+            .split('\x1b')
+            .map(|part| part.split_once('m').map_or(part, |(_, text)| text))
+            .collect()
+    }
+
+    #[test]
+    fn tab_stops_test() {
+        let theme = default_theme();
+        let row = Row::from("\tab\tc");
+        // a tab reaches the next multiple of the tab width
+        let columns: Vec<usize> = (0..=5).map(|index| row.column(index, 4)).collect();
+        assert_eq!(columns, [0, 4, 5, 6, 8, 9]);
+        assert_eq!(row.column(2, 8), 9);
+        // every column covered by a tab belongs to it
+        let indices: Vec<usize> = (0..10)
+            .map(|column| row.index_at_column(column, 4))
+            .collect();
+        assert_eq!(indices, [0, 0, 0, 0, 1, 2, 3, 3, 4, 5]);
+
+        assert_eq!(text(&row.render(0, 20, None, 4, &theme)), "    ab  c");
+        // a tab cut by the edge of the screen shows its visible columns
+        assert_eq!(text(&row.render(2, 7, None, 4, &theme)), "  ab ");
+        assert_eq!(text(&row.render(0, 20, None, 2, &theme)), "  ab  c");
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn wrap_test() {
+        let row = Row::from("the quick brown fox");
+        assert_eq!(row.wrap(8, 4, false), [0..8, 8..16, 16..19]);
+        // the whitespace stays at the end of the line
+        assert_eq!(row.wrap(8, 4, true), [0..4, 4..10, 10..16, 16..19]);
+        assert_eq!(row.wrap(30, 4, true), [0..19]);
+        // a word longer than the line is broken anywhere
+        assert_eq!(
+            Row::from("a abcdefgh").wrap(4, 4, true),
+            [0..2, 2..6, 6..10]
+        );
+        assert_eq!(Row::from("").wrap(4, 4, true), [0..0]);
+        // wide characters never straddle two lines
+        assert_eq!(Row::from("ab中文").wrap(3, 4, false), [0..2, 2..3, 3..4]);
+        // tabs are measured from the start of the row
+        assert_eq!(Row::from("a\tb\tc").wrap(6, 4, false), [0..3, 3..5]);
+    }
+
+    #[test]
+    fn wide_characters_test() {
+        let theme = default_theme();
+        // CJK and emoji take 2 columns, a combining accent stays with its letter
+        let row = Row::from("a中文🎉e\u{301}❤\u{fe0f}\tb");
+        let columns: Vec<usize> = (0..=8).map(|index| row.column(index, 4)).collect();
+        assert_eq!(columns, [0, 1, 3, 5, 7, 8, 10, 12, 13]);
+        let indices: Vec<usize> = (0..14)
+            .map(|column| row.index_at_column(column, 4))
+            .collect();
+        assert_eq!(indices, [0, 1, 1, 2, 2, 3, 3, 4, 5, 5, 6, 6, 7, 8]);
+
+        assert_eq!(
+            text(&row.render(0, 20, None, 4, &theme)),
+            "a中文🎉e\u{301}❤\u{fe0f}  b"
+        );
+        // a wide character cut by either edge of the screen is replaced by spaces
+        assert_eq!(text(&row.render(2, 6, None, 4, &theme)), " 文 ");
+        assert_eq!(text(&row.render(3, 5, None, 4, &theme)), "文");
+        // the selection is still counted in graphemes
+        let rendered = row.render(0, 5, Some(&(1..2)), 4, &theme);
+        let expected = format!("a{}中{}文", theme.selection, theme.text);
+        assert!(rendered.contains(&expected), "res: {rendered:?}");
+    }
+
+    #[test]
+    fn len_after_combining_edits() {
+        // a combining mark joins the letter before it
+        let mut row = Row::from("ab");
+        row.insert_str(1, "\u{301}x");
+        assert_eq!(row.len(), 3);
+        assert_eq!(row.text(0).as_deref(), Some("a\u{301}"));
+        // an ascii letter takes the mark at the start of the row
+        let mut row = Row::from("\u{301}b");
+        row.insert_str(0, "e");
+        assert_eq!(row.len(), 2);
+        // the hangul jamo on both sides of the removed letter become one syllable
+        let mut row = Row::from("\u{1100}a\u{1161}");
+        assert_eq!(row.len(), 3);
+        assert_eq!(row.drain(1, 2), "a");
+        assert_eq!(row.len(), 1);
+        // removing the letter leaves the mark on its own
+        let mut row = Row::from("ae\u{301}");
+        assert_eq!(row.drain(1, 5), "e\u{301}");
+        assert_eq!(row.len(), 1);
+        let mut row = Row::from("a");
+        row.append(Row::from("\u{301}b"));
+        assert_eq!(row.len(), 2);
+    }
+
+    #[test]
+    fn highlight_per_grapheme() {
+        // the emoji is made of three characters joined by U+200D
+        let (mut row, hl_opts) = create_row("👩\u{200d}💻 fn é\u{301} = 1");
+        row.highlight(Some(&Query::from("=")), &hl_opts, State::Normal);
+        assert_eq!(highlighting(&row).len(), row.len());
+        assert_eq!(
+            highlighting(&row)[..4],
+            [Type::None, Type::None, Type::PrimaryKey, Type::PrimaryKey]
+        );
+        assert_eq!(
+            highlighting(&row)[7..],
+            [Type::Match, Type::None, Type::Number]
+        );
+        let theme = default_theme();
+        let rendered = row.render(0, 20, None, 4, &theme);
+        assert!(
+            rendered.contains(&format!("{}fn", theme.primary_key)),
+            "res: {rendered:?}"
+        );
+    }
+
+    #[test]
+    fn highlight_edits_test() {
+        let (_, hl_opts) = create_row("");
+        let text = "let x = 0x1f; /* a */ \"s\\\"\" 'c' fn f(é\u{301}) {} ".repeat(100);
+        let mut row = Row::from(&text[..]);
+        assert!(row.len() > MAX_CHUNK * 3);
+        row.highlight(None, &hl_opts, State::Normal);
+        let edits = ["/*", "*/", "\"", "1", "\\", "//", "fn ", " ", "\u{301}"];
+        for (i, edit) in edits.iter().cycle().take(60).enumerate() {
+            let at = (i * 7919) % row.len();
+            if i % 3 == 0 {
+                row.drain(at, at + i % 5);
+            } else {
+                row.insert_str(at, edit);
+            }
+            let state = if i % 4 == 0 {
+                State::Comment(0)
+            } else {
+                State::Normal
+            };
+            // the same as highlighting the whole row again
+            let mut expected = Row::from(&row.to_string()[..]);
+            assert_eq!(
+                row.highlight_edits(None, &hl_opts, state),
+                expected.highlight(None, &hl_opts, state),
+                "edit {i}"
+            );
+            assert_eq!(highlighting(&row), highlighting(&expected), "edit {i}");
+        }
+        // rows split and joined keep the highlighting which still holds
+        let mut rest = row.split(row.len() / 2);
+        let state = row.highlight_edits(None, &hl_opts, State::Normal);
+        rest.highlight_edits(None, &hl_opts, state);
+        assert_eq!(
+            highlighting(&rest),
+            highlighting(&highlighted(&rest.to_string(), &hl_opts, state))
+        );
+        row.append(rest);
+        row.highlight_edits(None, &hl_opts, State::Normal);
+        assert_eq!(
+            highlighting(&row),
+            highlighting(&highlighted(&row.to_string(), &hl_opts, State::Normal))
+        );
+    }
+
+    fn highlighted(text: &str, hl_opts: &HighlightingOptions, state: State) -> Row {
+        let mut row = Row::from(text);
+        row.highlight(None, hl_opts, state);
+        row
+    }
+
+    #[test]
+    fn edits_across_chunks() {
+        let text = "ab\u{301}c".repeat(MAX_CHUNK);
+        let mut row = Row::from(&text[..]);
+        assert_eq!(row.len(), MAX_CHUNK * 3);
+        assert_eq!(row.to_string(), text);
+        // the removed graphemes span many chunks
+        let removed = row.drain(1, MAX_CHUNK * 3 - 1);
+        assert_eq!(removed, text[1..text.len() - 1]);
+        assert_eq!(row.to_string(), "ac");
+        row.insert_str(1, &text);
+        assert_eq!(row.len(), MAX_CHUNK * 3 + 2);
+        assert_eq!(row.slice(1, 4), "ab\u{301}c");
+        assert_eq!(row.text(MAX_CHUNK * 3 - 1).as_deref(), Some("b\u{301}"));
+        assert_eq!(
+            row.find(&Query::from("ca"), 0, SearchDirection::Forward),
+            Some(3)
+        );
+        let rest = row.split(MAX_CHUNK + 1);
+        assert_eq!(row.len() + rest.len(), MAX_CHUNK * 3 + 2);
+        assert_eq!(format!("{row}{rest}"), format!("a{text}c"));
     }
 }
@@ -0,0 +1,202 @@
+use std::{fmt, ops::Range};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::SearchDirection;
+
+/// the switches of the search prompt
+#[derive(Default, Clone, Copy)]
+pub struct SearchOptions {
+    pub regex: bool,
+    pub ignore_case: bool,
+    pub whole_word: bool,
+}
+
+impl fmt::Display for SearchOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags: Vec<&str> = [
+            (self.regex, "regex"),
+            (self.ignore_case, "ignore case"),
+            (self.whole_word, "whole word"),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, name)| *name)
+        .collect();
+        if flags.is_empty() {
+            return Ok(());
+        }
+        write!(f, "[{}] ", flags.join(", "))
+    }
+}
+
+/// what we are looking for.
+/// a plain query is matched as it is,
+/// the others are compiled into a regular expression
+#[derive(Clone)]
+pub enum Query {
+    Plain(String),
+    Pattern(Regex),
+}
+
+impl Query {
+    /// # Errors
+    ///
+    /// will return an error if the query is not a valid regular expression in regex mode
+    pub fn new(query: &str, options: SearchOptions) -> Result<Self, regex::Error> {
+        if !options.regex && !options.ignore_case && !options.whole_word {
+            return Ok(Query::Plain(query.to_string()));
+        }
+        let mut pattern = if options.regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        if options.whole_word {
+            pattern = format!(r"\b(?:{pattern})\b");
+        }
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(options.ignore_case)
+            .build()?;
+        Ok(Query::Pattern(regex))
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Query::Plain(query) => query.is_empty(),
+            Query::Pattern(regex) => regex.as_str().is_empty(),
+        }
+    }
+
+    /// find the byte range of the first (or last, if searching backward) match in `haystack`
+    /// which lies in `range`. the text before `range` is still used as the context of the match,
+    /// e.g. the word boundaries, while a match may end where `range` ends, like `a+` in the first
+    /// two bytes of "aaa". empty matches are ignored, otherwise we would find them everywhere
+    #[must_use]
+    pub fn find_in(
+        &self,
+        haystack: &str,
+        range: Range<usize>,
+        direction: SearchDirection,
+    ) -> Option<(usize, usize)> {
+        match self {
+            Query::Plain(query) => {
+                let text = haystack.get(range.clone())?;
+                let start = if direction == SearchDirection::Forward {
+                    text.find(query.as_str())
+                } else {
+                    text.rfind(query.as_str())
+                }?;
+                let start = start.saturating_add(range.start);
+                Some((start, start.saturating_add(query.len())))
+            }
+            Query::Pattern(regex) => {
+                let haystack = haystack.get(..range.end)?;
+                let mut found = None;
+                let mut from = range.start;
+                while let Some(m) = regex.find_at(haystack, from) {
+                    if !m.is_empty() {
+                        found = Some((m.start(), m.end()));
+                        if direction == SearchDirection::Forward {
+                            break;
+                        }
+                        from = m.end();
+                    } else if let Some(c) = haystack[m.end()..].chars().next() {
+                        // step over an empty match
+                        from = m.end().saturating_add(c.len_utf8());
+                    } else {
+                        break;
+                    }
+                }
+                found
+            }
+        }
+    }
+}
+
+impl From<&str> for Query {
+    fn from(query: &str) -> Self {
+        Query::Plain(query.to_string())
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use super::{Query, SearchOptions};
+    use crate::SearchDirection;
+
+    #[test]
+    fn plain_query() {
+        let query = Query::new("ab", SearchOptions::default()).unwrap();
+        assert_eq!(
+            query.find_in("xabab", 0..5, SearchDirection::Forward),
+            Some((1, 3))
+        );
+        assert_eq!(
+            query.find_in("xabab", 0..5, SearchDirection::Backward),
+            Some((3, 5))
+        );
+    }
+
+    #[test]
+    fn regex_query() {
+        let options = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        let query = Query::new(r"fn \w+\(", options).unwrap();
+        assert_eq!(
+            query.find_in("pub fn main() {", 0..15, SearchDirection::Forward),
+            Some((4, 12))
+        );
+        assert!(Query::new("(", options).is_err());
+        // empty matches are skipped
+        let query = Query::new("a*", options).unwrap();
+        assert_eq!(
+            query.find_in("bba", 0..3, SearchDirection::Forward),
+            Some((2, 3))
+        );
+        // the text before the range is still used to match word boundaries
+        let query = Query::new(r"\bb", options).unwrap();
+        assert_eq!(
+            query.find_in("ab b", 1..4, SearchDirection::Forward),
+            Some((3, 4))
+        );
+        assert_eq!(query.find_in("ab b", 0..3, SearchDirection::Backward), None);
+        // a match longer than the range is cut at its end
+        let query = Query::new("a+", options).unwrap();
+        assert_eq!(
+            query.find_in("aaa", 0..2, SearchDirection::Backward),
+            Some((0, 2))
+        );
+        assert_eq!(
+            query.find_in("baaa", 0..3, SearchDirection::Forward),
+            Some((1, 3))
+        );
+    }
+
+    #[test]
+    fn ignore_case_and_whole_word() {
+        let options = SearchOptions {
+            ignore_case: true,
+            whole_word: true,
+            ..SearchOptions::default()
+        };
+        let query = Query::new("Row", options).unwrap();
+        assert_eq!(
+            query.find_in("rows row ROW", 0..12, SearchDirection::Forward),
+            Some((5, 8))
+        );
+        assert_eq!(
+            query.find_in("rows row ROW", 0..12, SearchDirection::Backward),
+            Some((9, 12))
+        );
+        // the query is escaped when regex mode is off
+        let query = Query::new("a.b", options).unwrap();
+        assert_eq!(
+            query.find_in("axb a.b", 0..7, SearchDirection::Forward),
+            Some((4, 7))
+        );
+    }
+}
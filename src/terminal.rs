@@ -1,40 +1,100 @@
+use signal_hook::{consts::SIGWINCH, iterator::Signals};
 use std::io::{self, stdout};
 use std::io::{stdin, Write};
-use termion::color;
-use termion::event::Key;
-use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 
 use termion::raw::RawTerminal;
 
-use crate::Position;
+use crate::{
+    event::{self, Event, EventLoop},
+    theme::Style,
+    Position,
+};
 
 pub struct Size {
     height: u16,
     width: u16,
 }
 
+/// reports the current size of the terminal as (width, height)
+type SizeSource = Box<dyn Fn() -> Result<(u16, u16), io::Error>>;
+
 pub struct Terminal {
     size: Size,
-    _stdout: RawTerminal<io::Stdout>,
+    size_source: SizeSource,
+    _stdout: Option<RawTerminal<io::Stdout>>,
 }
 
 impl Terminal {
-    pub fn new() -> Result<Self, io::Error> {
+    /// the keys and the resizes of the terminal will be sent to `events`
+    ///
+    /// # Errors
+    ///
+    /// will return an error if the terminal size can't be read, raw mode can't be enabled
+    /// or the resize signal can't be caught
+    pub fn new(events: &EventLoop) -> Result<Self, io::Error> {
+        let mut signals = Signals::new([SIGWINCH])?;
+        events.spawn(move |sender| {
+            for _ in signals.forever() {
+                if sender.send(Ok(Event::Resize)).is_err() {
+                    break;
+                }
+            }
+        });
+        events.spawn(|sender| event::read_keys(stdin(), &sender));
         let size = termion::terminal_size()?;
-        Ok(Self {
+        let stdout = stdout().into_raw_mode()?;
+        Ok(Self::with(
+            size,
+            Box::new(termion::terminal_size),
+            Some(stdout),
+        ))
+    }
+
+    /// `size` is the current size reported by `size_source`
+    fn with(
+        (width, height): (u16, u16),
+        size_source: SizeSource,
+        stdout: Option<RawTerminal<io::Stdout>>,
+    ) -> Self {
+        Self {
             size: Size {
-                height: size.1.saturating_sub(2),
-                width: size.0,
+                height: height.saturating_sub(2),
+                width,
             },
-            _stdout: stdout().into_raw_mode()?,
-        })
+            size_source,
+            _stdout: stdout,
+        }
+    }
+
+    /// a terminal for tests, it reports whatever size is in `size`.
+    /// it doesn't switch the real terminal to raw mode
+    #[cfg(test)]
+    pub fn fake(size: std::rc::Rc<std::cell::Cell<(u16, u16)>>) -> Self {
+        Self::with(size.get(), Box::new(move || Ok(size.get())), None)
     }
 
+    /// read the size of the terminal again, return whether it has changed
+    ///
+    /// # Errors
+    ///
+    /// will return an error if the terminal size can't be read
+    pub fn update_size(&mut self) -> Result<bool, io::Error> {
+        let (width, height) = (self.size_source)()?;
+        let height = height.saturating_sub(2);
+        if (width, height) == (self.size.width, self.size.height) {
+            return Ok(false);
+        }
+        self.size = Size { height, width };
+        Ok(true)
+    }
+
+    #[must_use]
     pub fn width(&self) -> u16 {
         self.size.width
     }
 
+    #[must_use]
     pub fn height(&self) -> u16 {
         self.size.height
     }
@@ -43,6 +103,7 @@ impl Terminal {
         print!("{}", termion::clear::All);
     }
 
+    #[allow(clippy::cast_possible_truncation)]
     pub fn cursor_position(position: &Position) {
         let Position { mut x, mut y } = position;
         // avoid overflow of x and y
@@ -51,16 +112,16 @@ impl Terminal {
         print!("{}", termion::cursor::Goto(x as u16, y as u16));
     }
 
+    /// # Errors
+    ///
+    /// will return an error if stdout can't be flushed
     pub fn flush() -> Result<(), io::Error> {
         stdout().flush()
     }
 
-    pub fn read_key() -> Result<Key, io::Error> {
-        loop {
-            if let Some(key) = stdin().lock().keys().next() {
-                return key;
-            }
-        }
+    /// ask the terminal to put base64 encoded text into the system clipboard (OSC 52)
+    pub fn set_clipboard(encoded: &str) {
+        print!("\x1b]52;c;{encoded}\x07");
     }
 
     pub fn hide_cursor() {
@@ -75,19 +136,11 @@ impl Terminal {
         print!("{}", termion::clear::CurrentLine);
     }
 
-    pub fn set_bg_color(color: color::Rgb) {
-        print!("{}", color::Bg(color));
-    }
-        
-    pub fn reset_bg_color() {
-        print!("{}", color::Bg(color::Reset));
+    pub fn set_style(style: &Style) {
+        print!("{style}");
     }
 
-    pub fn set_fg_color(color: color::Rgb) {
-        print!("{}", color::Fg(color));
-    }
-        
-    pub fn reset_fg_color() {
-        print!("{}", color::Fg(color::Reset));
+    pub fn reset_style() {
+        print!("{}", termion::style::Reset);
     }
 }
@@ -0,0 +1,376 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use termion::{
+    color::{self, Rgb},
+    style,
+};
+use toml::{Table, Value};
+
+use crate::{config::config_dir, filetype::SchemaError, highlighting::Type};
+
+// `BUNDLED` is generated by build.rs from the files in src/themes
+include!(concat!(env!("OUT_DIR"), "/themes.rs"));
+
+// the theme every other theme starts from, the elements a theme leaves out look like in this one
+pub const DEFAULT_THEME: &str = "default";
+
+/// how a piece of the screen is drawn, a color left out is the color of the terminal
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Style {
+    pub fg: Option<Rgb>,
+    pub bg: Option<Rgb>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    // swap the colors, readable on any background
+    pub reverse: bool,
+}
+
+impl Style {
+    /// `other` on top of this style, the colors it leaves out show through
+    #[must_use]
+    pub fn patch(self, other: Self) -> Self {
+        Self {
+            fg: other.fg.or(self.fg),
+            bg: other.bg.or(self.bg),
+            bold: self.bold || other.bold,
+            italic: self.italic || other.italic,
+            underline: self.underline || other.underline,
+            reverse: self.reverse || other.reverse,
+        }
+    }
+}
+
+// the escape sequences switching from any style to this one
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", style::Reset)?;
+        if let Some(fg) = self.fg {
+            write!(f, "{}", color::Fg(fg))?;
+        }
+        if let Some(bg) = self.bg {
+            write!(f, "{}", color::Bg(bg))?;
+        }
+        if self.bold {
+            write!(f, "{}", style::Bold)?;
+        }
+        if self.italic {
+            write!(f, "{}", style::Italic)?;
+        }
+        if self.underline {
+            write!(f, "{}", style::Underline)?;
+        }
+        if self.reverse {
+            write!(f, "{}", style::Invert)?;
+        }
+        Ok(())
+    }
+}
+
+/// the style of every kind of highlighted text and of the parts of the screen around it
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Theme {
+    name: String,
+    pub text: Style,
+    pub number: Style,
+    pub string: Style,
+    pub escape: Style,
+    pub character: Style,
+    pub comment: Style,
+    pub primary_key: Style,
+    pub secondary_key: Style,
+    pub search_match: Style,
+    // on top of the style of the selected text
+    pub selection: Style,
+    pub status_bar: Style,
+    // the status bar of the windows without the focus
+    pub inactive_status_bar: Style,
+    pub gutter: Style,
+}
+
+const ELEMENTS: [&str; 13] = [
+    "text",
+    "number",
+    "string",
+    "escape",
+    "character",
+    "comment",
+    "primary_key",
+    "secondary_key",
+    "search_match",
+    "selection",
+    "status_bar",
+    "inactive_status_bar",
+    "gutter",
+];
+
+impl Theme {
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// the style of text highlighted as `htype`
+    #[must_use]
+    pub fn syntax(&self, htype: Type) -> Style {
+        match htype {
+            Type::None => self.text,
+            Type::Number => self.number,
+            Type::Match => self.search_match,
+            Type::String => self.string,
+            Type::Escape => self.escape,
+            Type::Character => self.character,
+            Type::Comment => self.comment,
+            Type::PrimaryKey => self.primary_key,
+            Type::SecondaryKey => self.secondary_key,
+        }
+    }
+
+    fn element(&mut self, name: &str) -> Option<&mut Style> {
+        Some(match name {
+            "text" => &mut self.text,
+            "number" => &mut self.number,
+            "string" => &mut self.string,
+            "escape" => &mut self.escape,
+            "character" => &mut self.character,
+            "comment" => &mut self.comment,
+            "primary_key" => &mut self.primary_key,
+            "secondary_key" => &mut self.secondary_key,
+            "search_match" => &mut self.search_match,
+            "selection" => &mut self.selection,
+            "status_bar" => &mut self.status_bar,
+            "inactive_status_bar" => &mut self.inactive_status_bar,
+            "gutter" => &mut self.gutter,
+            _ => return None,
+        })
+    }
+
+    /// the theme called `name` described by `source`, a TOML document.
+    /// the elements it leaves out are taken from `base`
+    ///
+    /// # Errors
+    ///
+    /// will return an error if `source` isn't valid TOML, or an element or a style is unknown
+    /// or has a value of the wrong type
+    pub fn parse(name: &str, source: &str, base: &Self) -> Result<Self, SchemaError> {
+        let table = source
+            .parse::<Table>()
+            .map_err(|e| SchemaError::new("", e.message().trim_end()))?;
+        let mut theme = Self {
+            name: name.to_string(),
+            ..base.clone()
+        };
+        for (key, value) in &table {
+            let Some(element) = theme.element(key) else {
+                return Err(SchemaError::new(
+                    key,
+                    &format!("unknown element, expected one of {}", ELEMENTS.join(", ")),
+                ));
+            };
+            *element = parse_style(value, key)?;
+        }
+        Ok(theme)
+    }
+}
+
+fn parse_style(value: &Value, path: &str) -> Result<Style, SchemaError> {
+    let table = value
+        .as_table()
+        .ok_or_else(|| SchemaError::new(path, "expected a table of fg, bg and attributes"))?;
+    let mut style = Style::default();
+    for (key, value) in table {
+        let path = format!("{path}.{key}");
+        match key.as_str() {
+            "fg" => style.fg = Some(as_color(value, &path)?),
+            "bg" => style.bg = Some(as_color(value, &path)?),
+            "bold" => style.bold = as_bool(value, &path)?,
+            "italic" => style.italic = as_bool(value, &path)?,
+            "underline" => style.underline = as_bool(value, &path)?,
+            "reverse" => style.reverse = as_bool(value, &path)?,
+            _ => {
+                return Err(SchemaError::new(
+                    &path,
+                    "unknown key, expected one of fg, bg, bold, italic, underline, reverse",
+                ))
+            }
+        }
+    }
+    Ok(style)
+}
+
+fn as_bool(value: &Value, path: &str) -> Result<bool, SchemaError> {
+    value
+        .as_bool()
+        .ok_or_else(|| SchemaError::new(path, "expected true or false"))
+}
+
+/// a color written like `#3f3f3f`
+fn as_color(value: &Value, path: &str) -> Result<Rgb, SchemaError> {
+    let hex = value
+        .as_str()
+        .and_then(|color| color.strip_prefix('#'))
+        .filter(|hex| hex.len() == 6);
+    let channel = |index: usize| {
+        let hex = hex?;
+        u8::from_str_radix(hex.get(index..index.saturating_add(2))?, 16).ok()
+    };
+    match (channel(0), channel(2), channel(4)) {
+        (Some(r), Some(g), Some(b)) => Ok(Rgb(r, g, b)),
+        _ => Err(SchemaError::new(path, "expected a color like \"#3f3f3f\"")),
+    }
+}
+
+/// the directory where users can add their own themes or override the bundled ones
+#[must_use]
+pub fn user_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("themes"))
+}
+
+/// every bundled theme and every theme in `user_dir`, the default one comes first.
+/// a theme in `user_dir` replaces the bundled one of the same name.
+/// the broken themes are left out and reported with the name of their file
+#[must_use]
+pub fn load(user_dir: Option<&Path>) -> (Vec<Theme>, Vec<String>) {
+    let mut sources: Vec<(String, String)> = BUNDLED
+        .iter()
+        .map(|(name, source)| ((*name).to_string(), (*source).to_string()))
+        .collect();
+    if let Some(entries) = user_dir.and_then(|dir| fs::read_dir(dir).ok()) {
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let Ok(source) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            sources.retain(|(bundled, _)| *bundled != name);
+            sources.push((name.into_owned(), source));
+        }
+    }
+    // the default theme is parsed first, the others are based on it
+    sources.sort_by_key(|(name, _)| name != DEFAULT_THEME);
+
+    let mut themes: Vec<Theme> = Vec::new();
+    let mut errors = Vec::new();
+    for (name, source) in sources {
+        let base = themes.first().cloned().unwrap_or_default();
+        match Theme::parse(&name, &source, &base) {
+            Ok(theme) => themes.push(theme),
+            Err(e) => errors.push(format!("{name}.toml: {e}")),
+        }
+    }
+    // a broken default theme is replaced by one without colors
+    if themes
+        .first()
+        .is_none_or(|theme| theme.name != DEFAULT_THEME)
+    {
+        themes.insert(
+            0,
+            Theme {
+                name: DEFAULT_THEME.to_string(),
+                ..Theme::default()
+            },
+        );
+    }
+    (themes, errors)
+}
+
+#[cfg(test)]
+mod theme_tests {
+    use std::{env, fs, process};
+
+    use termion::color::Rgb;
+
+    use super::{load, Style, Theme, DEFAULT_THEME};
+    use crate::highlighting::Type;
+
+    #[test]
+    fn bundled_themes() {
+        let (themes, errors) = load(None);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(themes[0].name(), DEFAULT_THEME);
+        assert!(themes.iter().any(|theme| theme.name() == "solarized-dark"));
+        // the text of the default theme has the colors of the terminal
+        assert_eq!(themes[0].syntax(Type::None), Style::default());
+        let light = themes.iter().find(|theme| theme.name() == "light").unwrap();
+        assert_eq!(light.syntax(Type::None).fg, Some(Rgb(56, 58, 66)));
+        assert!(light.syntax(Type::PrimaryKey).bold);
+    }
+
+    #[test]
+    fn parse_theme() {
+        let base = load(None).0.remove(0);
+        let theme = Theme::parse(
+            "mine",
+            r##"
+                comment = { fg = "#00ff00", italic = true }
+                selection = { bg = "#101010" }
+            "##,
+            &base,
+        )
+        .unwrap();
+        assert_eq!(theme.name(), "mine");
+        assert_eq!(
+            theme.syntax(Type::Comment),
+            Style {
+                fg: Some(Rgb(0, 255, 0)),
+                italic: true,
+                ..Style::default()
+            }
+        );
+        // the elements left out come from the base
+        assert_eq!(theme.status_bar, base.status_bar);
+
+        // the selection keeps the color of the text under it
+        let selected = theme.syntax(Type::Comment).patch(theme.selection);
+        assert_eq!(selected.fg, Some(Rgb(0, 255, 0)));
+        assert_eq!(selected.bg, Some(Rgb(16, 16, 16)));
+
+        let error = |source: &str| Theme::parse("bad", source, &base).unwrap_err().to_string();
+        assert_eq!(
+            error("keyword = {}"),
+            "keyword: unknown element, expected one of text, number, string, escape, character, comment, primary_key, secondary_key, search_match, selection, status_bar, inactive_status_bar, gutter"
+        );
+        assert_eq!(
+            error("text = { fg = \"red\" }"),
+            "text.fg: expected a color like \"#3f3f3f\""
+        );
+        assert_eq!(
+            error("text = { bold = 1 }"),
+            "text.bold: expected true or false"
+        );
+        assert_eq!(
+            error("gutter = \"#ffffff\""),
+            "gutter: expected a table of fg, bg and attributes"
+        );
+    }
+
+    #[test]
+    fn user_theme_overrides_bundled_one() {
+        let dir = env::temp_dir().join(format!("themes-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("light.toml"), "text = { fg = \"#000000\" }").unwrap();
+        fs::write(dir.join("mine.toml"), "gutter = { bold = true }").unwrap();
+        fs::write(dir.join("bad.toml"), "gutter = { blink = true }").unwrap();
+
+        let (themes, errors) = load(Some(&dir));
+        let find = |name: &str| themes.iter().find(|theme| theme.name() == name);
+        assert_eq!(find("light").unwrap().text.fg, Some(Rgb(0, 0, 0)));
+        // a user's theme starts from the default one, not the bundled one it replaces
+        assert_eq!(find("light").unwrap().number, themes[0].number);
+        assert!(find("mine").unwrap().gutter.bold);
+        assert!(find("bad").is_none());
+        assert_eq!(
+            errors,
+            ["bad.toml: gutter.blink: unknown key, expected one of fg, bg, bold, italic, underline, reverse"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,463 @@
+use termion::event::Key;
+
+use crate::keymap::Command;
+
+// the most times `p` and `.` are repeated, a larger count would only hang the editor
+const MAX_REPEAT: usize = 1000;
+
+/// the modes of the Vim profile
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Mode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    /// the name shown in the status bar
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::Insert => "INSERT",
+            Self::Visual => "VISUAL",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Motion {
+    Left,
+    Down,
+    Up,
+    Right,
+    // `w`, the start of the next word
+    WordStart,
+    // `b`, the start of the previous word
+    WordBack,
+    // `e`, the last grapheme of the word
+    WordEnd,
+    LineStart,
+    LineEnd,
+    // `gg`, the first line or the line of the count
+    FirstLine,
+    // `G`, the last line or the line of the count
+    LastLine,
+}
+
+impl Motion {
+    /// an operator works on the whole lines between the cursor and the target of the motion
+    #[must_use]
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Self::Up | Self::Down | Self::FirstLine | Self::LastLine
+        )
+    }
+
+    /// an operator takes the grapheme at the target of the motion too
+    #[must_use]
+    pub fn is_inclusive(self) -> bool {
+        matches!(self, Self::WordEnd | Self::LineEnd)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+/// where the text is typed in insert mode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Insert {
+    // `i`
+    BeforeCursor,
+    // `a`
+    AfterCursor,
+    // `I`
+    LineStart,
+    // `A`
+    LineEnd,
+    // `o`
+    LineBelow,
+    // `O`
+    LineAbove,
+}
+
+/// a command typed in normal or visual mode, the counts are `None` if the user typed none
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Move(Motion, Option<usize>),
+    // the motion is `None` for the whole lines of `dd`, `cc` and `yy`
+    Operate(Operator, Option<Motion>, Option<usize>),
+    // `d`, `c` or `y` in visual mode
+    OperateSelection(Operator),
+    Insert(Insert),
+    Paste { before: bool, count: usize },
+    Undo(usize),
+    // `.`
+    Repeat(usize),
+    // `v`, enter or leave visual mode
+    Visual,
+    // `:`
+    CommandLine,
+}
+
+impl Action {
+    /// a change is repeated by `.`
+    fn is_change(self) -> bool {
+        match self {
+            Self::Operate(operator, _, _) => operator != Operator::Yank,
+            Self::Insert(_) | Self::Paste { .. } => true,
+            Self::Move(..)
+            | Self::OperateSelection(_)
+            | Self::Undo(_)
+            | Self::Repeat(_)
+            | Self::Visual
+            | Self::CommandLine => false,
+        }
+    }
+}
+
+/// the keys read so far as a command
+enum Parsed<T> {
+    Done(T),
+    // a longer command starts with the keys
+    More,
+    Invalid,
+}
+
+/// the keys of one input of the editor and the command they are bound to
+pub type Input = (Vec<Key>, Option<Command>);
+
+/// the state of the Vim profile: the mode, the command being typed and the last change
+#[derive(Default)]
+pub struct Vim {
+    mode: Mode,
+    // the keys typed in normal or visual mode since the last command, like `2d` of `2dw`
+    pending: Vec<char>,
+    // the inputs of the change being made, it ends when insert mode is left
+    change: Option<Vec<Input>>,
+    last_change: Vec<Input>,
+}
+
+impl Vim {
+    #[must_use]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// the keys of the command being typed
+    #[must_use]
+    pub fn pending(&self) -> String {
+        self.pending.iter().collect()
+    }
+
+    /// the inputs which made the last change, `.` runs them again
+    #[must_use]
+    pub fn last_change(&self) -> Vec<Input> {
+        self.last_change.clone()
+    }
+
+    /// read a key typed in normal or visual mode, return the action when the command is complete.
+    /// the mode is switched by the action
+    pub fn key(&mut self, c: char) -> Option<Action> {
+        self.pending.push(c);
+        let action = match parse(&self.pending, self.mode == Mode::Visual) {
+            Parsed::Done(action) => action,
+            Parsed::More => return None,
+            Parsed::Invalid => {
+                self.pending.clear();
+                return None;
+            }
+        };
+        let keys = self
+            .pending
+            .drain(..)
+            .map(|c| (vec![Key::Char(c)], None))
+            .collect();
+        if action.is_change() {
+            self.change = Some(keys);
+        }
+        self.mode = match action {
+            Action::Insert(_)
+            | Action::Operate(Operator::Change, _, _)
+            | Action::OperateSelection(Operator::Change) => Mode::Insert,
+            Action::Visual if self.mode == Mode::Normal => Mode::Visual,
+            Action::Visual | Action::OperateSelection(_) => Mode::Normal,
+            _ => self.mode,
+        };
+        if self.mode != Mode::Insert {
+            self.finish_change();
+        }
+        Some(action)
+    }
+
+    /// remember an input typed in insert mode as a part of the change
+    pub fn record(&mut self, input: Input) {
+        if let Some(change) = &mut self.change {
+            change.push(input);
+        }
+    }
+
+    /// go back to normal mode, the command being typed is cancelled
+    pub fn escape(&mut self) {
+        if self.mode == Mode::Insert {
+            self.record((vec![Key::Esc], None));
+        }
+        self.mode = Mode::Normal;
+        self.pending.clear();
+        self.finish_change();
+    }
+
+    fn finish_change(&mut self) {
+        if let Some(change) = self.change.take() {
+            self.last_change = change;
+        }
+    }
+}
+
+/// the action typed with `keys`, operators work on the selection in visual mode
+fn parse(keys: &[char], visual: bool) -> Parsed<Action> {
+    let (count, keys) = parse_count(keys);
+    let Some((&first, rest)) = keys.split_first() else {
+        return Parsed::More;
+    };
+    let times = count.unwrap_or(1);
+    let operator = match first {
+        'd' | 'x' => Some(Operator::Delete),
+        'c' | 's' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        _ => None,
+    };
+    if let Some(operator) = operator {
+        if visual {
+            return Parsed::Done(Action::OperateSelection(operator));
+        }
+        if matches!(first, 'd' | 'c' | 'y') {
+            return parse_operation(operator, count, rest);
+        }
+    }
+    let action = match first {
+        'x' => Action::Operate(Operator::Delete, Some(Motion::Right), count),
+        'X' => Action::Operate(Operator::Delete, Some(Motion::Left), count),
+        's' => Action::Operate(Operator::Change, Some(Motion::Right), count),
+        'D' => Action::Operate(Operator::Delete, Some(Motion::LineEnd), count),
+        'C' => Action::Operate(Operator::Change, Some(Motion::LineEnd), count),
+        'Y' => Action::Operate(Operator::Yank, None, count),
+        'i' => Action::Insert(Insert::BeforeCursor),
+        'a' => Action::Insert(Insert::AfterCursor),
+        'I' => Action::Insert(Insert::LineStart),
+        'A' => Action::Insert(Insert::LineEnd),
+        'o' => Action::Insert(Insert::LineBelow),
+        'O' => Action::Insert(Insert::LineAbove),
+        'p' | 'P' => Action::Paste {
+            before: first == 'P',
+            count: times.min(MAX_REPEAT),
+        },
+        'u' => Action::Undo(times),
+        '.' => Action::Repeat(times.min(MAX_REPEAT)),
+        'v' => Action::Visual,
+        ':' => Action::CommandLine,
+        _ => {
+            return match parse_motion(keys) {
+                Parsed::Done(motion) => Parsed::Done(Action::Move(motion, count)),
+                Parsed::More => Parsed::More,
+                Parsed::Invalid => Parsed::Invalid,
+            }
+        }
+    };
+    Parsed::Done(action)
+}
+
+/// `operator` with the keys typed after it, the operator typed again works on whole lines
+fn parse_operation(operator: Operator, count: Option<usize>, keys: &[char]) -> Parsed<Action> {
+    let (motion_count, rest) = parse_count(keys);
+    // `2d3w` deletes 6 words
+    let count = match (count, motion_count) {
+        (None, None) => None,
+        _ => Some(count.unwrap_or(1).saturating_mul(motion_count.unwrap_or(1))),
+    };
+    let line = match operator {
+        Operator::Delete => 'd',
+        Operator::Change => 'c',
+        Operator::Yank => 'y',
+    };
+    if rest == [line] {
+        return Parsed::Done(Action::Operate(operator, None, count));
+    }
+    match parse_motion(rest) {
+        Parsed::Done(motion) => Parsed::Done(Action::Operate(operator, Some(motion), count)),
+        Parsed::More => Parsed::More,
+        Parsed::Invalid => Parsed::Invalid,
+    }
+}
+
+fn parse_motion(keys: &[char]) -> Parsed<Motion> {
+    let motion = match keys {
+        [] | ['g'] => return Parsed::More,
+        ['h'] => Motion::Left,
+        ['j'] => Motion::Down,
+        ['k'] => Motion::Up,
+        ['l'] => Motion::Right,
+        ['w'] => Motion::WordStart,
+        ['b'] => Motion::WordBack,
+        ['e'] => Motion::WordEnd,
+        ['0'] => Motion::LineStart,
+        ['$'] => Motion::LineEnd,
+        ['g', 'g'] => Motion::FirstLine,
+        ['G'] => Motion::LastLine,
+        _ => return Parsed::Invalid,
+    };
+    Parsed::Done(motion)
+}
+
+/// the count at the start of `keys` and the keys after it, `0` is a motion unless it follows a digit
+fn parse_count(keys: &[char]) -> (Option<usize>, &[char]) {
+    let digits = match keys.first() {
+        Some('1'..='9') => keys.iter().take_while(|c| c.is_ascii_digit()).count(),
+        _ => 0,
+    };
+    let count = keys[..digits]
+        .iter()
+        .collect::<String>()
+        .parse()
+        .ok()
+        // a count too large to be read moves as far as it can
+        .or((digits > 0).then_some(usize::MAX));
+    (count, &keys[digits..])
+}
+
+#[cfg(test)]
+mod vim_tests {
+    use termion::event::Key;
+
+    use super::{Action, Insert, Mode, Motion, Operator, Vim};
+
+    /// feed `keys` and return the action of the last one
+    fn type_keys(vim: &mut Vim, keys: &str) -> Option<Action> {
+        keys.chars().map(|c| vim.key(c)).last().flatten()
+    }
+
+    #[test]
+    fn motions_and_counts() {
+        let mut vim = Vim::default();
+        assert_eq!(
+            type_keys(&mut vim, "w"),
+            Some(Action::Move(Motion::WordStart, None))
+        );
+        assert_eq!(
+            type_keys(&mut vim, "12j"),
+            Some(Action::Move(Motion::Down, Some(12)))
+        );
+        assert_eq!(
+            type_keys(&mut vim, "0"),
+            Some(Action::Move(Motion::LineStart, None))
+        );
+        assert_eq!(type_keys(&mut vim, "10"), None);
+        assert_eq!(vim.pending(), "10");
+        assert_eq!(
+            type_keys(&mut vim, "G"),
+            Some(Action::Move(Motion::LastLine, Some(10)))
+        );
+        assert_eq!(type_keys(&mut vim, "g"), None);
+        assert_eq!(
+            type_keys(&mut vim, "g"),
+            Some(Action::Move(Motion::FirstLine, None))
+        );
+        // an unknown command is dropped
+        assert_eq!(type_keys(&mut vim, "2gq"), None);
+        assert_eq!(vim.pending(), "");
+        assert_eq!(
+            type_keys(&mut vim, "$"),
+            Some(Action::Move(Motion::LineEnd, None))
+        );
+    }
+
+    #[test]
+    fn operators() {
+        let mut vim = Vim::default();
+        assert_eq!(
+            type_keys(&mut vim, "2d3w"),
+            Some(Action::Operate(
+                Operator::Delete,
+                Some(Motion::WordStart),
+                Some(6)
+            ))
+        );
+        assert_eq!(
+            type_keys(&mut vim, "yy"),
+            Some(Action::Operate(Operator::Yank, None, None))
+        );
+        assert_eq!(
+            type_keys(&mut vim, "3x"),
+            Some(Action::Operate(
+                Operator::Delete,
+                Some(Motion::Right),
+                Some(3)
+            ))
+        );
+        assert_eq!(
+            type_keys(&mut vim, "dgg"),
+            Some(Action::Operate(
+                Operator::Delete,
+                Some(Motion::FirstLine),
+                None
+            ))
+        );
+        assert_eq!(type_keys(&mut vim, "dy"), None);
+        assert_eq!(vim.mode(), Mode::Normal);
+        assert_eq!(type_keys(&mut vim, ":"), Some(Action::CommandLine));
+
+        assert_eq!(
+            type_keys(&mut vim, "ce"),
+            Some(Action::Operate(
+                Operator::Change,
+                Some(Motion::WordEnd),
+                None
+            ))
+        );
+        assert_eq!(vim.mode(), Mode::Insert);
+        vim.escape();
+
+        // operators work on the selection in visual mode
+        assert_eq!(type_keys(&mut vim, "v"), Some(Action::Visual));
+        assert_eq!(vim.mode(), Mode::Visual);
+        assert_eq!(
+            type_keys(&mut vim, "l"),
+            Some(Action::Move(Motion::Right, None))
+        );
+        assert_eq!(
+            type_keys(&mut vim, "y"),
+            Some(Action::OperateSelection(Operator::Yank))
+        );
+        assert_eq!(vim.mode(), Mode::Normal);
+    }
+
+    #[test]
+    fn changes_are_recorded_for_repeat() {
+        let mut vim = Vim::default();
+        assert_eq!(
+            type_keys(&mut vim, "o"),
+            Some(Action::Insert(Insert::LineBelow))
+        );
+        vim.record((vec![Key::Char('x')], None));
+        vim.escape();
+        let change = vim.last_change();
+        assert_eq!(
+            change.iter().map(|(keys, _)| keys[0]).collect::<Vec<Key>>(),
+            [Key::Char('o'), Key::Char('x'), Key::Esc]
+        );
+
+        // moving and yanking aren't changes
+        type_keys(&mut vim, "3wyy");
+        assert_eq!(vim.last_change(), change);
+        type_keys(&mut vim, "2dd");
+        assert_eq!(vim.last_change().len(), 3);
+        assert_eq!(type_keys(&mut vim, "3."), Some(Action::Repeat(3)));
+        assert_eq!(vim.last_change().len(), 3);
+    }
+}
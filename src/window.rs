@@ -0,0 +1,272 @@
+use std::{mem, ops::Range};
+
+use crate::Position;
+
+/// a rectangle of the screen, the last line of a window's area is its status bar
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Area {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// the place in a buffer shown by a window
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct View {
+    // cusor position
+    pub position: Position,
+    // keep track of what row of the file the user is currently scrolled to,
+    // `offset.x` is a display column, not a grapheme index
+    pub offset: Position,
+    // the visual line of row `offset.y` shown at the top of the window, it is 0 without soft wrap
+    pub top_line: usize,
+}
+
+/// a part of the screen showing a buffer, windows on the same buffer show the same document
+#[derive(Default)]
+pub struct Window {
+    pub buffer: usize,
+    pub view: View,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Split {
+    // one window above the other
+    Horizontal,
+    // side by side, with a separator between them
+    Vertical,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// how the screen is shared by the windows, they are referred to by their indices
+#[derive(PartialEq, Debug)]
+pub enum Layout {
+    Window(usize),
+    Split(Split, Box<Layout>, Box<Layout>),
+}
+
+impl Layout {
+    /// divide `window` into itself and `new`, `new` is put below or on the right side of it
+    pub fn split(&mut self, window: usize, new: usize, split: Split) {
+        match self {
+            Self::Window(index) if *index == window => {
+                *self = Self::Split(
+                    split,
+                    Box::new(Self::Window(window)),
+                    Box::new(Self::Window(new)),
+                );
+            }
+            Self::Window(_) => (),
+            Self::Split(_, first, second) => {
+                first.split(window, new, split);
+                second.split(window, new, split);
+            }
+        }
+    }
+
+    /// remove `window`, the other side of its split takes its place.
+    /// the windows after it are renumbered like the elements after it removed from a `Vec`.
+    /// return the window which should get the focus, `None` if `window` is the only one
+    pub fn close(&mut self, window: usize) -> Option<usize> {
+        let next = self.remove(window)?;
+        self.renumber(window);
+        Some(if next > window {
+            next.saturating_sub(1)
+        } else {
+            next
+        })
+    }
+
+    fn remove(&mut self, window: usize) -> Option<usize> {
+        let Self::Split(_, first, second) = self else {
+            return None;
+        };
+        let kept = if **first == Self::Window(window) {
+            mem::replace(&mut **second, Self::Window(0))
+        } else if **second == Self::Window(window) {
+            mem::replace(&mut **first, Self::Window(0))
+        } else {
+            return first.remove(window).or_else(|| second.remove(window));
+        };
+        let next = kept.first();
+        *self = kept;
+        Some(next)
+    }
+
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Self::Window(index) => {
+                if *index > removed {
+                    *index = index.saturating_sub(1);
+                }
+            }
+            Self::Split(_, first, second) => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+
+    /// the top left window
+    fn first(&self) -> usize {
+        match self {
+            Self::Window(index) => *index,
+            Self::Split(_, first, _) => first.first(),
+        }
+    }
+
+    /// the area of every window when the layout fills `area`.
+    /// a vertical split leaves one column between the windows for the separator
+    #[must_use]
+    pub fn areas(&self, area: Area) -> Vec<(usize, Area)> {
+        match self {
+            Self::Window(index) => vec![(*index, area)],
+            Self::Split(split, first, second) => {
+                let (first_area, second_area) = match split {
+                    Split::Horizontal => {
+                        let height = area.height.saturating_sub(area.height / 2);
+                        (
+                            Area { height, ..area },
+                            Area {
+                                y: area.y.saturating_add(height),
+                                height: area.height / 2,
+                                ..area
+                            },
+                        )
+                    }
+                    Split::Vertical => {
+                        let room = area.width.saturating_sub(1);
+                        let width = room.saturating_sub(room / 2);
+                        (
+                            Area { width, ..area },
+                            Area {
+                                x: area.x.saturating_add(width).saturating_add(1),
+                                width: room / 2,
+                                ..area
+                            },
+                        )
+                    }
+                };
+                let mut areas = first.areas(first_area);
+                areas.extend(second.areas(second_area));
+                areas
+            }
+        }
+    }
+}
+
+/// the window next to `from` in `direction` among `areas`.
+/// the nearest one wins, then the one crossing the row or the column of `cursor`
+#[must_use]
+pub fn neighbour(
+    areas: &[(usize, Area)],
+    from: usize,
+    direction: Direction,
+    cursor: &Position,
+) -> Option<usize> {
+    let &(_, from) = areas.iter().find(|(index, _)| *index == from)?;
+    let rows = |area: &Area| area.y..area.y.saturating_add(area.height);
+    let columns = |area: &Area| area.x..area.x.saturating_add(area.width);
+    let overlap = |a: &Range<usize>, b: &Range<usize>| a.start < b.end && b.start < a.end;
+    areas
+        .iter()
+        .filter_map(|(index, area)| {
+            let (distance, crossing) = match direction {
+                Direction::Left if area.x < from.x => (
+                    from.x.checked_sub(columns(area).end)?,
+                    rows(area).contains(&cursor.y),
+                ),
+                Direction::Right if area.x > from.x => (
+                    area.x.checked_sub(columns(&from).end)?,
+                    rows(area).contains(&cursor.y),
+                ),
+                Direction::Up if area.y < from.y => (
+                    from.y.checked_sub(rows(area).end)?,
+                    columns(area).contains(&cursor.x),
+                ),
+                Direction::Down if area.y > from.y => (
+                    area.y.checked_sub(rows(&from).end)?,
+                    columns(area).contains(&cursor.x),
+                ),
+                _ => return None,
+            };
+            let side_by_side = match direction {
+                Direction::Left | Direction::Right => overlap(&rows(area), &rows(&from)),
+                Direction::Up | Direction::Down => overlap(&columns(area), &columns(&from)),
+            };
+            side_by_side.then_some((distance, !crossing, *index))
+        })
+        .min()
+        .map(|(_, _, index)| index)
+}
+
+#[cfg(test)]
+mod window_tests {
+    use super::{neighbour, Area, Direction, Layout, Split};
+    use crate::Position;
+
+    const SCREEN: Area = Area {
+        x: 0,
+        y: 0,
+        width: 81,
+        height: 24,
+    };
+
+    #[test]
+    fn split_and_close() {
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, Split::Vertical);
+        layout.split(1, 2, Split::Horizontal);
+        let area = |x, y, width, height| Area {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            layout.areas(SCREEN),
+            [
+                (0, area(0, 0, 40, 24)),
+                (1, area(41, 0, 40, 12)),
+                (2, area(41, 12, 40, 12)),
+            ]
+        );
+
+        // the window below takes the place of the closed one, and becomes window 1
+        assert_eq!(layout.close(1), Some(1));
+        assert_eq!(
+            layout.areas(SCREEN),
+            [(0, area(0, 0, 40, 24)), (1, area(41, 0, 40, 24))]
+        );
+        assert_eq!(layout.close(0), Some(0));
+        assert_eq!(layout, Layout::Window(0));
+        assert_eq!(layout.close(0), None);
+    }
+
+    #[test]
+    fn neighbours() {
+        // 0 | 1
+        //   |---
+        //   | 2
+        let mut layout = Layout::Window(0);
+        layout.split(0, 1, Split::Vertical);
+        layout.split(1, 2, Split::Horizontal);
+        let areas = layout.areas(SCREEN);
+        let at = |x, y| Position { x, y };
+        assert_eq!(neighbour(&areas, 0, Direction::Right, &at(3, 5)), Some(1));
+        assert_eq!(neighbour(&areas, 0, Direction::Right, &at(3, 20)), Some(2));
+        assert_eq!(neighbour(&areas, 2, Direction::Up, &at(50, 15)), Some(1));
+        assert_eq!(neighbour(&areas, 2, Direction::Left, &at(50, 15)), Some(0));
+        assert_eq!(neighbour(&areas, 1, Direction::Down, &at(50, 5)), Some(2));
+        assert_eq!(neighbour(&areas, 0, Direction::Left, &at(3, 5)), None);
+        assert_eq!(neighbour(&areas, 1, Direction::Up, &at(50, 5)), None);
+    }
+}